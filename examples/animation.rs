@@ -6,11 +6,11 @@ extern crate graphics;
 extern crate parent;
 #[macro_use(route, router)]
 extern crate entity;
-extern crate future_pulse;
 extern crate std_graphics;
 extern crate cgmath;
 extern crate image;
 extern crate bounding;
+extern crate spatial;
 extern crate animation;
 extern crate config;
 extern crate name;
@@ -24,7 +24,6 @@ use parent::{Parent, ParentSystem};
 use renderer::{DrawBinding, Camera, Primary, Renderer};
 use scene::Scene;
 use cgmath::{Decomposed, Transform, PerspectiveFov, Quaternion, Vector3, rad, Rotation3};
-use future_pulse::Future;
 use transform::{TransformSystem, Local, World};
 use entity::Entity;
 use animation::{animation, Lerp, Animation, AnimationSystem};
@@ -82,7 +81,16 @@ fn main() {
     let sscene = scene::scene(engine.sched(), parent.clone());
     let transform = transform::transform(engine.sched(), parent.clone());
     let graphics = graphics::Graphics::new(engine.sched());
-    let bound = bounding::Bounding::new(engine.sched(), graphics.clone());
+    engine.on_shutdown(parent.shutdown_signal());
+    engine.on_shutdown(graphics.shutdown_signal());
+    let render = renderer::renderer(engine.sched());
+    engine.on_shutdown(render.shutdown_signal());
+    let bound = bounding::Bounding::with_world(engine.sched(),
+                                               graphics.clone(),
+                                               parent.clone(),
+                                               transform.clone(),
+                                               render.clone());
+    let index = spatial::Spatial::new(engine.sched(), sscene.clone(), bound.clone());
     let name = name::name(engine.sched(), parent.clone());
     let config = config::config(engine.sched(), parent.clone());
 
@@ -90,16 +98,15 @@ fn main() {
     let s = sscene.clone();
     let n = name.clone();
     let c = config.clone();
-    let (read, set) = Future::new();
+    let r = render.clone();
     engine.start_render(|sched, ra|{
-        let (input, mut renderer) = renderer::RendererSystem::new(sched, graphics.clone(), t, s, bound, n, c, ra);
-        set.set(input);
+        let mut renderer = renderer::RendererSystem::new(sched, graphics.clone(), t, s, bound, index, r, n, c, ra);
         Box::new(move |sched, stream| {
             renderer.draw(sched, stream);
         })
     });
 
-    let renderer = read.get();
+    let renderer = render;
 
     let input = engine.input_channel();
     config_menu(
@@ -138,7 +145,7 @@ fn main() {
     }
 
     Entity::new()
-        .bind(Local(transform))
+        .bind(Local::Decomposed(transform))
         .bind(DrawBinding(shapes.cube, materials.flat.red))
         .bind(scene)
         .bind(lerp)
@@ -154,7 +161,7 @@ fn main() {
             msg.next_frame();
 
             camera.bind(Primary)
-                  .bind(Local(Decomposed::identity()))
+                  .bind(Local::Decomposed(Decomposed::identity()))
                   .bind(Camera(
                     PerspectiveFov {
                         fovy: cgmath::deg(90.),
@@ -7,13 +7,13 @@ extern crate graphics;
 extern crate parent;
 #[macro_use(route, router)]
 extern crate entity;
-extern crate future_pulse;
 extern crate no_clip;
 extern crate std_graphics;
 extern crate cgmath;
 extern crate time;
 extern crate image;
 extern crate bounding;
+extern crate spatial;
 extern crate config;
 extern crate name;
 extern crate config_menu;
@@ -25,8 +25,7 @@ use graphics::{
 use parent::{Parent, ParentSystem};
 use renderer::{DrawBinding, Camera, Primary, Renderer, DebugText};
 use scene::Scene;
-use cgmath::{Decomposed, Transform, PerspectiveFov};
-use future_pulse::Future;
+use cgmath::{Decomposed, Transform, PerspectiveFov, Quaternion, Vector3};
 use transform::TransformSystem;
 
 use entity::Entity;
@@ -81,7 +80,16 @@ fn main() {
     let sscene = scene::scene(engine.sched(), parent.clone());
     let transform = transform::transform(engine.sched(), parent.clone());
     let graphics = graphics::Graphics::new(engine.sched());
-    let bound = bounding::Bounding::new(engine.sched(), graphics.clone());
+    engine.on_shutdown(parent.shutdown_signal());
+    engine.on_shutdown(graphics.shutdown_signal());
+    let render = renderer::renderer(engine.sched());
+    engine.on_shutdown(render.shutdown_signal());
+    let bound = bounding::Bounding::with_world(engine.sched(),
+                                               graphics.clone(),
+                                               parent.clone(),
+                                               transform.clone(),
+                                               render.clone());
+    let index = spatial::Spatial::new(engine.sched(), sscene.clone(), bound.clone());
     let name = name::name(engine.sched(), parent.clone());
     let config = config::config(engine.sched(), parent.clone());
 
@@ -89,16 +97,15 @@ fn main() {
     let s = sscene.clone();
     let n = name.clone();
     let c = config.clone();
-    let (read, set) = Future::new();
+    let r = render.clone();
     engine.start_render(|sched, ra|{
-        let (input, mut renderer) = renderer::RendererSystem::new(sched, graphics.clone(), t, s, bound, n, c, ra);
-        set.set(input);
+        let mut renderer = renderer::RendererSystem::new(sched, graphics.clone(), t, s, bound, index, r, n, c, ra);
         Box::new(move |sched, stream| {
             renderer.draw(sched, stream);
         })
     });
 
-    let renderer = read.get();
+    let renderer = render;
 
     let input = engine.input_channel();
     config_menu(
@@ -134,16 +141,16 @@ fn main() {
         for (yi, y) in (-count..count).enumerate() {
             for (zi, z) in (-count..count).enumerate() {
 
-                let mut pos = Local(Decomposed::identity());
-                pos.0.disp.x = x as f32 * 5.;
-                pos.0.disp.y = y as f32 * 5.;
-                pos.0.disp.z = z as f32 * 5.;
+                let mut pos: Decomposed<f32, Vector3<f32>, Quaternion<f32>> = Decomposed::identity();
+                pos.disp.x = x as f32 * 5.;
+                pos.disp.y = y as f32 * 5.;
+                pos.disp.z = z as f32 * 5.;
 
                 let layer = ((x*x+y*y+z*z) as f32).sqrt() as usize;
 
                 Entity::new()
                        .bind(DrawBinding(shapes.cube, materials.flat.red))
-                       .bind(pos)
+                       .bind(Local::Decomposed(pos))
                        .bind(all)
                        .bind(xs[xi])
                        .bind(ys[yi])
@@ -7,13 +7,13 @@ extern crate graphics;
 extern crate parent;
 #[macro_use(route, router)]
 extern crate entity;
-extern crate future_pulse;
 extern crate no_clip;
 extern crate cgmath;
 extern crate time;
 extern crate image;
 extern crate obj_loader;
 extern crate bounding;
+extern crate spatial;
 extern crate config;
 extern crate name;
 extern crate config_menu;
@@ -29,7 +29,6 @@ use parent::{Parent, ParentSystem};
 use renderer::{DrawBinding, Camera, Primary, Renderer, DebugText};
 use scene::Scene;
 use cgmath::{Decomposed, Transform, PerspectiveFov};
-use future_pulse::Future;
 use transform::TransformSystem;
 
 use entity::Entity;
@@ -83,7 +82,16 @@ fn main() {
     let sscene = scene::scene(engine.sched(), parent.clone());
     let transform = transform::transform(engine.sched(), parent.clone());
     let graphics = graphics::Graphics::new(engine.sched());
-    let bound = bounding::Bounding::new(engine.sched(), graphics.clone());
+    engine.on_shutdown(parent.shutdown_signal());
+    engine.on_shutdown(graphics.shutdown_signal());
+    let render = renderer::renderer(engine.sched());
+    engine.on_shutdown(render.shutdown_signal());
+    let bound = bounding::Bounding::with_world(engine.sched(),
+                                               graphics.clone(),
+                                               parent.clone(),
+                                               transform.clone(),
+                                               render.clone());
+    let index = spatial::Spatial::new(engine.sched(), sscene.clone(), bound.clone());
     let name = name::name(engine.sched(), parent.clone());
     let config = config::config(engine.sched(), parent.clone());
 
@@ -91,16 +99,15 @@ fn main() {
     let s = sscene.clone();
     let n = name.clone();
     let c = config.clone();
-    let (read, set) = Future::new();
+    let r = render.clone();
     engine.start_render(|sched, ra|{
-        let (input, mut renderer) = renderer::RendererSystem::new(sched, graphics.clone(), t, s, bound, n, c, ra);
-        set.set(input);
+        let mut renderer = renderer::RendererSystem::new(sched, graphics.clone(), t, s, bound, index, r, n, c, ra);
         Box::new(move |sched, stream| {
             renderer.draw(sched, stream);
         })
     });
 
-    let renderer = read.get();
+    let renderer = render;
 
     let input = engine.input_channel();
     config_menu::config_menu(
@@ -133,7 +140,7 @@ fn main() {
             Entity::new()
                    .bind(DrawBinding(g, m))
                    .bind(scene)
-                   .bind(Local(comp))
+                   .bind(Local::Decomposed(comp))
                    .write(&mut sink);
         }
     }
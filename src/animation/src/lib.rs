@@ -12,7 +12,7 @@ use cgmath::{Decomposed, Vector3, Quaternion, EuclideanVector};
 use ordered_vec::OrderedVec;
 use entity::{Entity, Operation};
 use transform::{TransformSystem, Local};
-use engine::fibe::*;
+use engine::fibe::Schedule;
 use engine::event::WindowEvent;
 
 #[derive(Copy, Clone, Debug)]
@@ -86,8 +86,8 @@ impl AnimationData {
         let mut delete = Vec::new();
 
         for (eid, anim) in self.lerps.iter_mut() {
-            let mut current = if let Some(l) = t.local(*eid) {
-                l.0
+            let mut current = if let Some(l) = t.local(*eid).and_then(|l| l.decomposed()) {
+                l
             } else {
                 continue;
             };
@@ -118,7 +118,7 @@ impl AnimationData {
                 }
             }
 
-            eid.bind(Local(current)).write(t);
+            eid.bind(Local::Decomposed(current)).write(t);
         }
 
         if delete.len() != 0 {
@@ -127,65 +127,89 @@ impl AnimationData {
     }
 }
 
-// Reads from the parent channel
-fn sync_ingest(ingest: &mut system::channel::Receiver<Message>) -> Vec<Message> {
-    let mut msgs: Vec<Message> = Vec::new();
-    while let Ok(op) = ingest.recv() {
-        msgs.push(op.clone());
-    }
-    msgs
-}
-
 pub type Message = Operation<Entity, Animation>;
 
 pub fn animation(sched: &mut Schedule,
-                 mut input: engine::InputChannel,
+                 input: engine::InputChannel,
                  parent: parent::ParentSystem,
                  transform: TransformSystem) -> AnimationSystem {
+    let (system, handle, mut animate) = create(input, &parent, transform);
+    system.follow(sched, parent, move |anim, old, msgs, p, _| animate.update(anim, old, msgs, p));
+    handle
+}
+
+/// Create the animation system without starting it, the step
+/// depends on the steps of `parent` and of `transform`
+pub fn animation_step(input: engine::InputChannel,
+                      parent: parent::ParentSystem,
+                      transform: TransformSystem) -> (AnimationSystem, system::schedule::Step) {
+    let (system, handle, mut animate) = create(input, &parent, transform);
+    (handle, system.follow_step(parent, move |anim, old, msgs, p, _| animate.update(anim, old, msgs, p)))
+}
 
-    let mut transform = Some(shared_future::Future::from_value(transform));
+fn create(input: engine::InputChannel,
+          parent: &parent::ParentSystem,
+          transform: TransformSystem) -> (system::System<Message, AnimationData>, AnimationSystem, Animate) {
     let ad = AnimationData::new();
-    let (mut system, handle) = system::System::new(ad.clone(), ad);
-
-    let mut time = 0.;
-    let mut last_time = 0.;
-
-    task(move |_| {
-        let mut parent = Some(parent);
-        loop {
-            let s = system.update(|mut anim, old, mut msgs| {
-                last_time = time;
-                for x in input.iter() {
-                    if let &WindowEvent::TimeStamp(t) = x {
-                        time = t;
-                    }
-                }
-                input.next_frame();
-                let p = parent.take().unwrap().next_frame().get().unwrap();
-                anim.clone_from(old);
+    let (system, handle) = system::System::following("animation", parent, ad.clone(), ad);
+    let animate = Animate {
+        input: input,
+        transform: Some(transform.next_frame()),
+        shutdown: system.shutdown_signal(),
+        time: 0.,
+        last_time: 0.
+    };
+    (system, handle, animate)
+}
 
-                let mut msgs = sync_ingest(&mut msgs);
-                for &p in p.deleted.keys() {
-                    msgs.push(Operation::Delete(p));
-                }
-                msgs.sort_by(|a, b| a.key().cmp(b.key()));
+// Reads the clock from the input and writes the lerps to the transforms
+struct Animate {
+    input: engine::InputChannel,
+    transform: Option<shared_future::Future<TransformSystem>>,
+    shutdown: system::Shutdown,
+    time: f64,
+    last_time: f64
+}
 
-                anim.lerps.apply_updates(msgs.into_iter());
+impl Animate {
+    fn update(&mut self,
+              mut anim: AnimationData,
+              old: &AnimationData,
+              mut msgs: Vec<Message>,
+              p: &parent::ParentSystem) -> AnimationData {
+        self.last_time = self.time;
+        for x in self.input.iter() {
+            if let &WindowEvent::Tick(t) = x {
+                self.time = t.time;
+            }
+        }
+        if !self.input.next_frame() {
+            self.shutdown.signal();
+        }
+        anim.clone_from(old);
 
+        for &p in p.deleted.keys() {
+            msgs.push(Operation::Delete(p));
+        }
+        msgs.sort_by(|a, b| a.key().cmp(b.key()));
 
-                let mut t = transform.take().unwrap().get().unwrap();
-                anim.update(&mut t, last_time, time);
-                transform = Some(t.next_frame());
-                parent = Some(p);
+        anim.lerps.apply_updates(msgs.into_iter());
 
-                anim
-            });
-            system = if let Some(s) = s { s } else { return; };
+        match self.transform.take().unwrap().get() {
+            Ok(mut t) => {
+                anim.update(&mut t, self.last_time, self.time);
+                if t.is_shutdown() {
+                    self.shutdown.signal();
+                } else {
+                    self.transform = Some(t.next_frame());
+                }
+            }
+            // transform has already published its last frame
+            Err(_) => self.shutdown.signal()
         }
-    }).start(sched);
-
-    handle
 
+        anim
+    }
 }
 
 impl entity::WriteEntity<Entity, Lerp> for AnimationSystem {
@@ -197,11 +221,4 @@ impl entity::WriteEntity<Entity, Lerp> for AnimationSystem {
 }
 
 
-impl entity::WriteEntity<Entity, Animation> for AnimationSystem {
-    fn write(&mut self, eid: Entity, a: Animation) {
-        self.send(Operation::Upsert(eid, a));
-    }
-}
-
-
 pub type AnimationSystem = system::SystemHandle<Message, AnimationData>;
\ No newline at end of file
@@ -4,63 +4,198 @@ extern crate entity;
 extern crate lease;
 extern crate engine;
 extern crate shared_future;
+extern crate system;
+extern crate transform;
+extern crate parent;
+extern crate gfx;
 
 use std::collections::{HashMap, HashSet};
+use std::mem;
 use cgmath::{Aabb, Aabb3, Point3, Vector4, Matrix, Matrix4};
 use entity::Entity;
 use graphics::{Graphics, Geometry, GeometryData, VertexBufferData};
 use engine::fibe::*;
+use parent::ParentSystem;
+use transform::TransformSystem;
+use system::SystemHandle;
+
+mod position;
+mod world;
+
+pub use position::{BoundsError, positions, read_positions, half_to_f32};
+pub use world::WorldBounds;
 
 #[derive(Clone)]
 pub struct Bounding {
     inner: Option<lease::Lease<BoundingStore>>,
-    next: shared_future::Future<Bounding>
+    next: shared_future::Future<Bounding>,
+    shutdown: bool
 }
 
 #[derive(Clone)]
 pub struct BoundingStore {
     vb_to_geo: HashMap<Entity, HashSet<Geometry>>,
     pub aabb: HashMap<graphics::Geometry, Aabb3<f32>>,
-    pub aabb_updated: HashSet<graphics::Geometry>
+    pub sphere: HashMap<graphics::Geometry, Sphere>,
+    /// The geometries whose bounds could not be found
+    pub errors: HashMap<graphics::Geometry, BoundsError>,
+    pub aabb_updated: HashSet<graphics::Geometry>,
+    /// The world space bounds of the entities, this is only
+    /// updated by a bounding system created `with_world`
+    pub world: WorldBounds
 }
 
-fn to_point3(p: [f32; 3]) -> Point3<f32> {
-    Point3::new(p[0], p[1], p[2])
+/// The geometry bound to each entity, an entity's world bounds are
+/// the bounds of its geometry moved by its world transform
+pub trait GeometryBinding {
+    /// Get the geometry bound to the entity
+    fn geometry(&self, eid: Entity) -> Option<Geometry>;
+
+    /// The entities whose geometry was bound or unbound this frame
+    fn rebound(&self) -> &HashSet<Entity>;
 }
 
-fn create_aabb(geo: &GeometryData, vb: &VertexBufferData) -> Option<Aabb3<f32>> {
-    if vb.vertex.len() == 0 {
-        return None;
+// The systems the world bounds are read from
+trait Source: Send {
+    // Release the current frame of each system
+    fn release(&mut self);
+
+    // Wait for the next frame of each system
+    fn wait(&mut self);
+
+    // Update the world bounds, returns true if one
+    // of the systems shutdown
+    fn update(&mut self, store: &mut BoundingStore) -> bool;
+}
+
+struct World<M: Send+Sync, D: Send+Sync> {
+    parents: Option<ParentSystem>,
+    transforms: Option<TransformSystem>,
+    bindings: Option<SystemHandle<M, D>>,
+    next: Option<(shared_future::Future<ParentSystem>,
+                  shared_future::Future<TransformSystem>,
+                  shared_future::Future<SystemHandle<M, D>>)>
+}
+
+impl<M, D> Source for World<M, D>
+    where M: Send + Sync + 'static,
+          D: GeometryBinding + Send + Sync + 'static
+{
+    fn release(&mut self) {
+        self.next = Some((self.parents.take().unwrap().next_frame(),
+                          self.transforms.take().unwrap().next_frame(),
+                          self.bindings.take().unwrap().next_frame()));
     }
-    let position = vb.vertex[0].attribute_reader(0).unwrap();
 
+    fn wait(&mut self) {
+        let (parents, transforms, bindings) = self.next.take().unwrap();
+        self.parents = Some(parents.get().unwrap());
+        self.transforms = Some(transforms.get().unwrap());
+        self.bindings = Some(bindings.get().unwrap());
+    }
 
-    match vb.index {
-        Some(ref index) => {
-            let first = position[index[geo.buffer.start as usize] as usize];
-            let mut aabb = Aabb3::new(to_point3(first), to_point3(first));
+    fn update(&mut self, store: &mut BoundingStore) -> bool {
+        let parents = self.parents.as_ref().unwrap();
+        let transforms = self.transforms.as_ref().unwrap();
+        let bindings = self.bindings.as_ref().unwrap();
 
-            for i in (geo.buffer.start+1)..(geo.buffer.start + geo.buffer.length) {
-                let pos = position[index[i as usize] as usize];
-                aabb = aabb.grow(&to_point3(pos));
-            }
+        let mut world = mem::replace(&mut store.world, WorldBounds::new());
+        world.update(store, parents, transforms, bindings.rebound(), |eid| {
+            bindings.geometry(eid)
+        });
+        store.world = world;
 
-            Some(aabb)
+        parents.is_shutdown() ||
+        transforms.is_shutdown() ||
+        bindings.is_shutdown()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sphere {
+    pub center: Point3<f32>,
+    pub radius: f32
+}
+
+impl Sphere {
+    pub fn new(center: Point3<f32>, radius: f32) -> Sphere {
+        Sphere {
+            center: center,
+            radius: radius
         }
-        None => {
-            let first = position[geo.buffer.start as usize];
-            let mut aabb = Aabb3::new(to_point3(first), to_point3(first));
+    }
 
-            for i in (geo.buffer.start+1)..(geo.buffer.start + geo.buffer.length) {
-                let pos = position[i as usize];
-                aabb = aabb.grow(&to_point3(pos));
-            }
+    /// Check if the sphere and the box overlap
+    pub fn overlaps(&self, aabb: &Aabb3<f32>) -> bool {
+        let c = &self.center;
+        let dx = c.x - c.x.max(aabb.min.x).min(aabb.max.x);
+        let dy = c.y - c.y.max(aabb.min.y).min(aabb.max.y);
+        let dz = c.z - c.z.max(aabb.min.z).min(aabb.max.z);
+        dx * dx + dy * dy + dz * dz <= self.radius * self.radius
+    }
 
-            Some(aabb)
+    /// Transform the sphere, the radius is grown by the largest scale
+    /// of the matrix so the sphere still holds the geometry
+    pub fn transform(&self, mat: &Matrix4<f32>) -> Sphere {
+        let c = mat.mul_v(&Vector4::new(self.center.x, self.center.y, self.center.z, 1.));
+        let scale = [mat.x, mat.y, mat.z].iter()
+            .map(|v| (v.x * v.x + v.y * v.y + v.z * v.z).sqrt())
+            .fold(0f32, |a, b| a.max(b));
+        Sphere {
+            center: Point3::new(c.x / c.w, c.y / c.w, c.z / c.w),
+            radius: self.radius * scale
         }
     }
 }
 
+fn to_point3(p: [f32; 3]) -> Point3<f32> {
+    Point3::new(p[0], p[1], p[2])
+}
+
+// The box that holds every vertex of the geometry, and the sphere
+// around the center of the box that holds every vertex. `position`
+// is the decoded positions of `vb`.
+fn create_bounds(geo: &GeometryData, vb: &VertexBufferData, position: &[[f32; 3]])
+    -> Result<(Aabb3<f32>, Sphere), BoundsError> {
+
+    let start = geo.buffer.start as usize;
+    let end = start + geo.buffer.length as usize;
+
+    let mut points = Vec::with_capacity(end - start);
+    for i in start..end {
+        let i = match vb.index {
+            Some(ref index) => match index.get(i) {
+                Some(&i) => i as usize,
+                None => return Err(BoundsError::OutOfRange)
+            },
+            None => i
+        };
+        if let Some(&p) = position.get(i) {
+            points.push(to_point3(p));
+        } else {
+            return Err(BoundsError::OutOfRange);
+        }
+    }
+
+    let mut aabb = if let Some(first) = points.first() {
+        Aabb3::new(*first, *first)
+    } else {
+        return Err(BoundsError::Empty);
+    };
+    for p in points[1..].iter() {
+        aabb = aabb.grow(p);
+    }
+
+    let center = Point3::new((aabb.min.x + aabb.max.x) / 2.,
+                             (aabb.min.y + aabb.max.y) / 2.,
+                             (aabb.min.z + aabb.max.z) / 2.);
+    let mut radius = 0f32;
+    for p in points.iter() {
+        let (x, y, z) = (p.x - center.x, p.y - center.y, p.z - center.z);
+        radius = radius.max((x * x + y * y + z * z).sqrt());
+    }
+    Ok((aabb, Sphere::new(center, radius)))
+}
 
 impl BoundingStore {
     /// Search for any geometry that has been modified if it has
@@ -87,8 +222,12 @@ impl BoundingStore {
 
     fn update(&mut self, g: &graphics::Graphics) {
         let updated = self.create_update_list(g);
+
+        // a vertex buffer is decoded once, however many of
+        // its geometries need to be updated
+        let mut decoded = HashMap::new();
         for geo in updated.iter() {
-            let aabb = if let Some(gdat) = g.geometry.get(&geo) {
+            let bounds = if let Some(gdat) = g.geometry.get(&geo) {
                 if let Some(vb) = g.vertex_buffer.get(&gdat.buffer.parent) {
                     self.vb_to_geo
                         .entry(gdat.buffer.parent)
@@ -96,7 +235,12 @@ impl BoundingStore {
                         .insert(*geo);
 
                     // ok now we have the VB we an created the geometry
-                    create_aabb(gdat, vb)
+                    let position = decoded.entry(gdat.buffer.parent)
+                                          .or_insert_with(|| positions(vb));
+                    Some(match *position {
+                        Ok(ref position) => create_bounds(gdat, vb, &position[..]),
+                        Err(ref err) => Err(err.clone())
+                    })
                 } else {
                     None
                 }
@@ -104,23 +248,90 @@ impl BoundingStore {
                 None
             };
 
-            if let Some(aabb) = aabb {
-                self.aabb.insert(*geo, aabb);
-            } else {
-                self.aabb.remove(geo);
+            match bounds {
+                Some(Ok((aabb, sphere))) => {
+                    self.aabb.insert(*geo, aabb);
+                    self.sphere.insert(*geo, sphere);
+                    self.errors.remove(geo);
+                }
+                Some(Err(err)) => {
+                    self.aabb.remove(geo);
+                    self.sphere.remove(geo);
+                    self.errors.insert(*geo, err);
+                }
+                None => {
+                    self.aabb.remove(geo);
+                    self.sphere.remove(geo);
+                    self.errors.remove(geo);
+                }
             }
         }
         self.aabb_updated = updated;
     }
+
+    /// calculate a scaled aabb
+    pub fn scaled_aabb(&self, geo: &Geometry, mat: Matrix4<f32>) -> Option<Aabb3<f32>> {
+        fn to_point3(v: Vector4<f32>) -> Point3<f32> {
+            Point3::new(v.x / v.w, v.y / v.w, v.z / v.w)
+        }
+
+        self.aabb.get(geo)
+            .map(|aabb| {
+                let points = [
+                    to_point3(mat.mul_v(&Vector4::new(aabb.min.x, aabb.min.y, aabb.min.z, 1.))),
+                    to_point3(mat.mul_v(&Vector4::new(aabb.min.x, aabb.min.y, aabb.max.z, 1.))),
+                    to_point3(mat.mul_v(&Vector4::new(aabb.min.x, aabb.max.y, aabb.min.z, 1.))),
+                    to_point3(mat.mul_v(&Vector4::new(aabb.min.x, aabb.max.y, aabb.max.z, 1.))),
+                    to_point3(mat.mul_v(&Vector4::new(aabb.max.x, aabb.min.y, aabb.min.z, 1.))),
+                    to_point3(mat.mul_v(&Vector4::new(aabb.max.x, aabb.min.y, aabb.max.z, 1.))),
+                    to_point3(mat.mul_v(&Vector4::new(aabb.max.x, aabb.max.y, aabb.min.z, 1.))),
+                    to_point3(mat.mul_v(&Vector4::new(aabb.max.x, aabb.max.y, aabb.max.z, 1.))),
+                ];
+
+                let mut aabb = Aabb3::new(points[0], points[1]);
+                for p in points[2..].iter() {
+                    aabb = aabb.grow(p);
+                }
+                aabb
+            })
+    }
 }
 
 impl Bounding {
-    /// Create a new bounding system
+    /// Create a new bounding system, it only finds the bounds of
+    /// each geometry
     pub fn new(sched: &mut Schedule, graphics: graphics::Graphics) -> Bounding {
+        Bounding::start(sched, graphics, None)
+    }
+
+    /// Create a new bounding system that also finds the `world` bounds
+    /// of every entity that `bindings` binds a geometry to
+    pub fn with_world<M, D>(sched: &mut Schedule,
+                            graphics: graphics::Graphics,
+                            parents: ParentSystem,
+                            transforms: TransformSystem,
+                            bindings: SystemHandle<M, D>) -> Bounding
+        where M: Send + Sync + 'static,
+              D: GeometryBinding + Send + Sync + 'static
+    {
+        Bounding::start(sched, graphics, Some(Box::new(World{
+            parents: Some(parents),
+            transforms: Some(transforms),
+            bindings: Some(bindings),
+            next: None
+        }) as Box<Source>))
+    }
+
+    fn start(sched: &mut Schedule,
+             graphics: graphics::Graphics,
+             mut source: Option<Box<Source>>) -> Bounding {
         let mut inner = BoundingStore {
             vb_to_geo: HashMap::new(),
             aabb: HashMap::new(),
-            aabb_updated: HashSet::new()
+            sphere: HashMap::new(),
+            errors: HashMap::new(),
+            aabb_updated: HashSet::new(),
+            world: WorldBounds::new()
         };
 
         inner.update(&graphics);
@@ -132,63 +343,71 @@ impl Bounding {
         let mut graphics = Some(graphics);
         task(move |_| {
             loop {
-                let g = graphics.take().unwrap().next_frame().get().unwrap();
+                let g = graphics.take().unwrap().next_frame();
+                if let Some(ref mut source) = source {
+                    source.release();
+                }
+                let start = system::profile::now();
+                let g = g.get().unwrap();
+                if let Some(ref mut source) = source {
+                    source.wait();
+                }
                 let mut inner = back.get();
+                let ready = system::profile::now();
                 inner.clone_from(&*front);
 
                 inner.update(&g);
+                let done = match source {
+                    Some(ref mut source) => source.update(&mut inner),
+                    None => false
+                } || g.is_shutdown();
+                system::profile::timeline().record(system::profile::Span {
+                    name: "bounding",
+                    start: start,
+                    ready: ready,
+                    end: system::profile::now(),
+                    messages: inner.aabb_updated.len(),
+                    thread: system::profile::thread_id()
+                });
                 let (nown, nlease) = lease::lease(inner);
                 back = front;
                 front = nown;
                 let (next, nset) = shared_future::Future::new();
                 set.set(Bounding{
                     inner: Some(nlease),
-                    next: next
+                    next: next,
+                    shutdown: done
                 });
+
+                // an input published its last frame, so this is ours
+                if done {
+                    return;
+                }
+
                 set = nset;
                 graphics = Some(g);
-
             }
         }).start(sched);
 
         Bounding {
             inner: Some(l),
-            next: future
+            next: future,
+            shutdown: false
         }
     }
 
     pub fn next_frame(&mut self) {
         drop(self.inner.take());
-        let Bounding{inner, next} = self.next.clone().get().unwrap();
+        let Bounding{inner, next, shutdown} = self.next.clone().get().unwrap();
         self.inner = inner;
         self.next = next;
+        self.shutdown = shutdown;
     }
 
-    /// calculate a scaled aabb
-    pub fn scaled_aabb(&self, geo: &Geometry, mat: Matrix4<f32>) -> Option<Aabb3<f32>> {
-        fn to_point3(v: Vector4<f32>) -> Point3<f32> {
-            Point3::new(v.x / v.w, v.y / v.w, v.z / v.w)
-        }
-
-        self.aabb.get(geo)
-            .map(|aabb| {
-                let points = [
-                    to_point3(mat.mul_v(&Vector4::new(aabb.min.x, aabb.min.y, aabb.min.z, 1.))),
-                    to_point3(mat.mul_v(&Vector4::new(aabb.min.x, aabb.min.y, aabb.max.z, 1.))),
-                    to_point3(mat.mul_v(&Vector4::new(aabb.min.x, aabb.max.y, aabb.min.z, 1.))),
-                    to_point3(mat.mul_v(&Vector4::new(aabb.min.x, aabb.max.y, aabb.max.z, 1.))),
-                    to_point3(mat.mul_v(&Vector4::new(aabb.max.x, aabb.min.y, aabb.min.z, 1.))),
-                    to_point3(mat.mul_v(&Vector4::new(aabb.max.x, aabb.min.y, aabb.max.z, 1.))),
-                    to_point3(mat.mul_v(&Vector4::new(aabb.max.x, aabb.max.y, aabb.min.z, 1.))),
-                    to_point3(mat.mul_v(&Vector4::new(aabb.max.x, aabb.max.y, aabb.max.z, 1.))),
-                ];
-
-                let mut aabb = Aabb3::new(points[0], points[1]);
-                for p in points[2..].iter() {
-                    aabb = aabb.grow(p);
-                }
-                aabb
-            })
+    /// Returns true if this is the last frame, the bounding
+    /// system follows the systems it was created with
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown
     }
 }
 
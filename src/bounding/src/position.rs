@@ -0,0 +1,139 @@
+use std::{cmp, fmt, mem};
+use gfx::attrib::{Format, Type, FloatSize, IntSize, IntSubType, SignFlag};
+use graphics::{VertexBufferData, POSITION};
+
+/// Errors that stop the bounds of a geometry from being found
+#[derive(Clone, Debug, PartialEq)]
+pub enum BoundsError {
+    /// The vertex buffer has no vertices
+    NoVertices,
+    /// None of the vertex's attributes are named `graphics::POSITION`
+    NoPosition,
+    /// The position attribute uses a type that can't be read
+    UnsupportedFormat(String),
+    /// The geometry or its indices point past the end of the buffer
+    OutOfRange,
+    /// The geometry has a length of zero, it has no bounds
+    Empty
+}
+
+impl fmt::Display for BoundsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &BoundsError::NoVertices => write!(f, "the vertex buffer is empty"),
+            &BoundsError::NoPosition => write!(f, "the vertex buffer has no {} attribute", POSITION),
+            &BoundsError::UnsupportedFormat(ref ty) => {
+                write!(f, "the {} attribute has an unsupported type {}", POSITION, ty)
+            }
+            &BoundsError::OutOfRange => write!(f, "the geometry is outside of the vertex buffer"),
+            &BoundsError::Empty => write!(f, "the geometry has no vertices")
+        }
+    }
+}
+
+fn size_of(ty: Type) -> Option<usize> {
+    match ty {
+        Type::Float(_, FloatSize::F16) => Some(2),
+        Type::Float(_, FloatSize::F32) => Some(4),
+        Type::Float(_, FloatSize::F64) => Some(8),
+        Type::Int(_, IntSize::U8, _) => Some(1),
+        Type::Int(_, IntSize::U16, _) => Some(2),
+        Type::Int(_, IntSize::U32, _) => Some(4),
+        _ => None
+    }
+}
+
+// Read an unsigned little endian number
+fn read_bits(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |acc, &b| (acc << 8) | b as u64)
+}
+
+/// Convert the bits of a half precision float to an `f32`
+pub fn half_to_f32(h: u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1. } else { 1. };
+    let exp = ((h >> 10) & 0x1f) as i32;
+    let mant = (h & 0x3ff) as f32;
+    match exp {
+        0 => sign * mant * (2f32).powi(-24),
+        31 if mant == 0. => sign * ::std::f32::INFINITY,
+        31 => ::std::f32::NAN,
+        _ => sign * (1. + mant / 1024.) * (2f32).powi(exp - 15)
+    }
+}
+
+fn read_component(bytes: &[u8], ty: Type) -> f32 {
+    let bits = read_bits(bytes);
+    match ty {
+        Type::Float(_, FloatSize::F16) => half_to_f32(bits as u16),
+        Type::Float(_, FloatSize::F32) => unsafe { mem::transmute::<u32, f32>(bits as u32) },
+        Type::Float(_, FloatSize::F64) => unsafe { mem::transmute::<u64, f64>(bits) as f32 },
+        Type::Int(sub, _, sign) => {
+            let width = bytes.len() * 8;
+            let (value, max) = match sign {
+                SignFlag::Unsigned => (bits as f64, ((1u64 << width) - 1) as f64),
+                SignFlag::Signed => {
+                    // sign extend the value to 64 bits
+                    let shift = 64 - width;
+                    (((bits << shift) as i64 >> shift) as f64, ((1u64 << (width - 1)) - 1) as f64)
+                }
+            };
+            match sub {
+                IntSubType::Normalized => (value / max).max(-1.) as f32,
+                _ => value as f32
+            }
+        }
+        _ => 0.
+    }
+}
+
+/// Read the first three elements of an attribute from each of the
+/// `len` vertices in `data`, missing elements are zero. Integers
+/// are read as floats, normalized integers are scaled to [-1, 1]
+/// if they are signed or [0, 1] if they are not.
+pub fn read_positions(data: &[u8], len: usize, format: &Format) -> Result<Vec<[f32; 3]>, BoundsError> {
+    let ty = format.elem_type;
+    let size = if let Some(s) = size_of(ty) {
+        s
+    } else {
+        return Err(BoundsError::UnsupportedFormat(format!("{:?}", ty)));
+    };
+
+    let count = cmp::min(format.elem_count as usize, 3);
+    let offset = format.offset as usize;
+    let stride = if format.stride == 0 {
+        size * format.elem_count as usize
+    } else {
+        format.stride as usize
+    };
+
+    let mut out = Vec::with_capacity(len);
+    for i in 0..len {
+        let base = offset + i * stride;
+        if base + size * count > data.len() {
+            return Err(BoundsError::OutOfRange);
+        }
+        let mut p = [0f32; 3];
+        for (e, v) in p.iter_mut().enumerate().take(count) {
+            let start = base + e * size;
+            *v = read_component(&data[start..start + size], ty);
+        }
+        out.push(p);
+    }
+    Ok(out)
+}
+
+/// Read the position of every vertex in the buffer. The position
+/// is the attribute named `graphics::POSITION` in any of the buffer's
+/// vertex streams, it can be any float or integer type.
+pub fn positions(vb: &VertexBufferData) -> Result<Vec<[f32; 3]>, BoundsError> {
+    if vb.vertex.len() == 0 {
+        return Err(BoundsError::NoVertices);
+    }
+
+    for vertex in vb.vertex.iter() {
+        if let Some(attr) = vertex.attributes().iter().find(|a| a.name == POSITION) {
+            return read_positions(vertex.data(), vertex.len(), &attr.format);
+        }
+    }
+    Err(BoundsError::NoPosition)
+}
@@ -0,0 +1,199 @@
+use std::collections::{HashMap, HashSet};
+use cgmath::{Aabb3, Point3};
+use entity::Entity;
+use graphics::Geometry;
+use parent::{ParentData, Parent};
+use transform::TransformData;
+use {BoundingStore, Sphere};
+
+fn union(a: &Aabb3<f32>, b: &Aabb3<f32>) -> Aabb3<f32> {
+    Aabb3 {
+        min: Point3::new(a.min.x.min(b.min.x), a.min.y.min(b.min.y), a.min.z.min(b.min.z)),
+        max: Point3::new(a.max.x.max(b.max.x), a.max.y.max(b.max.y), a.max.z.max(b.max.z))
+    }
+}
+
+// Add the entity to the entities waiting to have their subtree
+// recomputed, they are grouped by their depth
+fn mark(dirty: &mut Vec<HashSet<Entity>>, parents: &ParentData, eid: Entity) {
+    let depth = parents.depth(eid).unwrap_or(0);
+    while dirty.len() <= depth {
+        dirty.push(HashSet::new());
+    }
+    dirty[depth].insert(eid);
+}
+
+/// The world space bounds of every entity that has a geometry. This
+/// is updated from what changed in a frame so it must be updated with
+/// every frame of the systems it reads.
+#[derive(Clone)]
+pub struct WorldBounds {
+    aabb: HashMap<Entity, Aabb3<f32>>,
+    sphere: HashMap<Entity, Sphere>,
+
+    // the box of each entity and all of its descendants
+    subtree: HashMap<Entity, Aabb3<f32>>,
+
+    // the geometry of each entity, and the entities using each geometry
+    geometry: HashMap<Entity, Geometry>,
+    users: HashMap<Geometry, HashSet<Entity>>,
+
+    /// The entities whose bounds changed, were added or were
+    /// removed by the last update
+    pub changed: HashSet<Entity>,
+}
+
+impl WorldBounds {
+    pub fn new() -> WorldBounds {
+        WorldBounds {
+            aabb: HashMap::new(),
+            sphere: HashMap::new(),
+            subtree: HashMap::new(),
+            geometry: HashMap::new(),
+            users: HashMap::new(),
+            changed: HashSet::new(),
+        }
+    }
+
+    /// Get the world space box of the entity
+    pub fn aabb(&self, eid: Entity) -> Option<&Aabb3<f32>> {
+        self.aabb.get(&eid)
+    }
+
+    /// Get the world space sphere of the entity
+    pub fn sphere(&self, eid: Entity) -> Option<&Sphere> {
+        self.sphere.get(&eid)
+    }
+
+    /// Get the box that holds the entity and all of its descendants
+    pub fn subtree(&self, eid: Entity) -> Option<&Aabb3<f32>> {
+        self.subtree.get(&eid)
+    }
+
+    /// Apply a frame's changes. `rebound` is the entities whose geometry
+    /// changed this frame, `geometry` looks up an entity's geometry.
+    pub fn update<F>(&mut self,
+                     bounding: &BoundingStore,
+                     parents: &ParentData,
+                     transform: &TransformData,
+                     rebound: &HashSet<Entity>,
+                     geometry: F)
+        where F: Fn(Entity) -> Option<Geometry>
+    {
+        let mut refresh: HashSet<Entity> = rebound.clone();
+        for eid in transform.changed.iter().chain(transform.removed.iter()) {
+            if self.geometry.contains_key(eid) || geometry(*eid).is_some() {
+                refresh.insert(*eid);
+            }
+        }
+        for geo in bounding.aabb_updated.iter() {
+            if let Some(users) = self.users.get(geo) {
+                refresh.extend(users.iter().cloned());
+            }
+        }
+
+        self.changed.clear();
+        for eid in refresh {
+            self.refresh(eid, bounding, transform, geometry(eid));
+        }
+        self.propagate(parents);
+    }
+
+    // Recompute the subtree boxes of the entities whose box changed or
+    // that gained or lost a child. The deepest are visited first so each
+    // parent sees the new boxes of its children, a parent is only visited
+    // again if the box of one of its children changed.
+    fn propagate(&mut self, parents: &ParentData) {
+        let mut dirty = Vec::new();
+        for eid in self.changed.iter() {
+            mark(&mut dirty, parents, *eid);
+        }
+        for (eid, old) in parents.modified.iter() {
+            if let &Some(Parent::Child(p)) = old {
+                mark(&mut dirty, parents, p);
+            }
+            if let Some(&Parent::Child(p)) = parents.child_to_parent.get(eid) {
+                mark(&mut dirty, parents, p);
+            }
+        }
+        for (eid, old) in parents.deleted.iter() {
+            self.subtree.remove(eid);
+            if let &Some(Parent::Child(p)) = old {
+                mark(&mut dirty, parents, p);
+            }
+        }
+
+        while let Some(level) = dirty.pop() {
+            for eid in level {
+                if parents.deleted.contains_key(&eid) {
+                    continue;
+                }
+
+                let aabb = parents.children(eid)
+                    .filter_map(|c| self.subtree.get(&c))
+                    .chain(self.aabb.get(&eid))
+                    .fold(None, |acc, b| {
+                        Some(match acc {
+                            Some(a) => union(&a, b),
+                            None => *b
+                        })
+                    });
+                let old = match aabb {
+                    Some(aabb) => self.subtree.insert(eid, aabb),
+                    None => self.subtree.remove(&eid)
+                };
+
+                if old != aabb {
+                    if let Some(&Parent::Child(p)) = parents.child_to_parent.get(&eid) {
+                        mark(&mut dirty, parents, p);
+                    }
+                }
+            }
+        }
+    }
+
+    fn refresh(&mut self,
+               eid: Entity,
+               bounding: &BoundingStore,
+               transform: &TransformData,
+               geo: Option<Geometry>) {
+        if let Some(old) = self.geometry.remove(&eid) {
+            let empty = self.users.get_mut(&old).map(|u| {
+                u.remove(&eid);
+                u.is_empty()
+            });
+            if empty == Some(true) {
+                self.users.remove(&old);
+            }
+        }
+        if let Some(geo) = geo {
+            self.geometry.insert(eid, geo);
+            self.users
+                .entry(geo)
+                .or_insert_with(HashSet::new)
+                .insert(eid);
+        }
+
+        let world = transform.world(eid).map(|w| w.0);
+        let (aabb, sphere) = match (geo, world) {
+            (Some(geo), Some(world)) => {
+                (bounding.scaled_aabb(&geo, world),
+                 bounding.sphere.get(&geo).map(|s| s.transform(&world)))
+            }
+            _ => (None, None)
+        };
+
+        let had = self.aabb.contains_key(&eid);
+        match aabb {
+            Some(aabb) => { self.aabb.insert(eid, aabb); }
+            None => { self.aabb.remove(&eid); }
+        }
+        match sphere {
+            Some(sphere) => { self.sphere.insert(eid, sphere); }
+            None => { self.sphere.remove(&eid); }
+        }
+        if had || aabb.is_some() {
+            self.changed.insert(eid);
+        }
+    }
+}
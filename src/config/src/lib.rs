@@ -1,13 +1,12 @@
 extern crate system;
 extern crate engine;
 extern crate entity;
-extern crate ordered_vec;
 extern crate parent;
 extern crate name;
 
 use engine::fibe::*;
+use engine::EngineBuilder;
 use entity::*;
-use ordered_vec::OrderedVec;
 use parent::ParentSystem;
 use name::PathLookup;
 
@@ -18,77 +17,21 @@ pub enum Config {
     String(String)
 }
 
-#[derive(Clone)]
-pub struct ConfigData {
-    pub current: OrderedVec<Entity, Config>,
-    pub changed: OrderedVec<Entity, Config>
-}
-
-impl ConfigData {
-    fn new() -> ConfigData {
-        ConfigData {
-            current: OrderedVec::new(),
-            changed: OrderedVec::new()
-        }
-    }
-
-    fn apply_ingest(&mut self, data: &[Message]) {
-        self.changed.clear();
-        self.current.apply_updates(data.iter().map(|x| x.clone()));
-        self.changed.apply_updates(data.iter().map(|x| x.clone()));
-    }
-}
-
-type Message = Operation<Entity, Config>;
-
-// Reads from the parent channel
-fn sync_ingest(ingest: &mut system::channel::Receiver<Message>) -> Vec<Operation<Entity, Config>> {
-    ingest.iter().map(|x| x.clone()).collect()
-}
+pub type ConfigData = system::ComponentData<Config>;
+pub type ConfigSystem = system::ComponentSystem<Config>;
 
+/// Create a config system, the config entries of
+/// any entity deleted from `parents` are removed
 pub fn config(sched: &mut Schedule, parents: ParentSystem) -> ConfigSystem {
-    let td = ConfigData::new();
-    let (mut system, handle) = system::System::new(td.clone(), td);
-
-    task(move |_| {
-        let mut parents = Some(parents);
-        loop {
-            let s = system.update(|mut config, old, mut msgs| {
-                let p = parents.take().unwrap().next_frame().get().unwrap();
-
-                config.clone_from(old);
-
-                let mut imsgs = sync_ingest(&mut msgs);
-                for &d in p.deleted.keys() {
-                    imsgs.push(Operation::Delete(d));
-                }
-                imsgs.sort_by(|a, b| a.key().cmp(b.key()));
-                config.apply_ingest(&imsgs[..]);
-
-                parents = Some(p);
-                config
-            });
-            system = if let Some(s) = s { s } else { return; };
-        }
-    }).start(sched);
-
-    handle
-}
-
-impl entity::WriteEntity<Entity, Config> for ConfigSystem {
-    fn write(&mut self, eid: Entity, delta: Config) {
-        self.send(Operation::Upsert(eid, delta));
-    }
+    system::component(sched, "config", parents)
 }
 
-impl entity::ReadEntity<Entity, Config> for ConfigSystem {
-    fn read(&self, eid: &Entity) -> Option<&Config> {
-        self.current.get(eid)
-    }
+/// Create the config system without starting it, the step
+/// depends on the step of `parents`
+pub fn config_step(parents: ParentSystem) -> (ConfigSystem, system::schedule::Step) {
+    system::component_step("config", parents)
 }
 
-pub type ConfigSystem = system::SystemHandle<Message, ConfigData>;
-
 pub trait GetConfig<'a> {
     fn config_bool(&self, item: &'a str) -> Option<bool>;
     fn config_f64(&self, item: &'a str) -> Option<f64>;
@@ -135,3 +78,55 @@ impl<'a, T> GetConfig<'a> for T
             })
     }
 }
+
+/// Reads the `engine.*` config entries into an `EngineBuilder`,
+/// any entry that is missing keeps the builder's current value.
+///
+///  - `engine.window.title` String
+///  - `engine.window.width` & `engine.window.height` Float
+///  - `engine.window.depth_bits` Float
+///  - `engine.window.vsync` Bool
+///  - `engine.window.fullscreen` Bool
+///  - `engine.window.multisampling` Float
+///  - `engine.gl.major` & `engine.gl.minor` Float
+///  - `engine.tick_rate` Float
+pub trait ConfigureEngine {
+    fn configure<'a, T>(self, config: &T) -> Self
+        where T: GetConfig<'a>;
+}
+
+impl ConfigureEngine for EngineBuilder {
+    fn configure<'a, T>(self, config: &T) -> EngineBuilder
+        where T: GetConfig<'a>
+    {
+        let mut builder = self;
+
+        if let Some(title) = config.config_string("engine.window.title") {
+            builder = builder.with_title(title.to_string());
+        }
+        if let (Some(w), Some(h)) = (config.config_f64("engine.window.width"),
+                                     config.config_f64("engine.window.height")) {
+            builder = builder.with_dimensions(w as u32, h as u32);
+        }
+        if let Some(bits) = config.config_f64("engine.window.depth_bits") {
+            builder = builder.with_depth_buffer(bits as u8);
+        }
+        if let Some(vsync) = config.config_bool("engine.window.vsync") {
+            builder = builder.with_vsync(vsync);
+        }
+        if let Some(fullscreen) = config.config_bool("engine.window.fullscreen") {
+            builder = builder.with_fullscreen(fullscreen);
+        }
+        if let Some(samples) = config.config_f64("engine.window.multisampling") {
+            builder = builder.with_multisampling(samples as u16);
+        }
+        if let (Some(major), Some(minor)) = (config.config_f64("engine.gl.major"),
+                                             config.config_f64("engine.gl.minor")) {
+            builder = builder.with_gl_version(major as u8, minor as u8);
+        }
+        if let Some(rate) = config.config_f64("engine.tick_rate") {
+            builder = builder.with_tick_rate(rate);
+        }
+        builder
+    }
+}
@@ -0,0 +1,248 @@
+extern crate engine;
+extern crate entity;
+extern crate cgmath;
+extern crate ordered_vec;
+extern crate system;
+extern crate parent;
+
+use cgmath::*;
+use ordered_vec::OrderedVec;
+use entity::{Entity, Operation};
+use parent::ParentSystem;
+use engine::fibe::Schedule;
+
+/// Changes the world transform of an entity after its parents
+/// were applied. The `Local` of the entity is left alone.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Constraint {
+    /// Turn the entity so that its -Z axis points at the target
+    LookAt(Entity),
+    /// Move the entity to the position of the target
+    CopyPosition(Entity),
+    /// Place the entity `distance` away from the target and look at it.
+    /// The angles are in radians, a yaw and pitch of zero is along +Z.
+    Orbit{target: Entity, distance: f32, yaw: f32, pitch: f32},
+    /// Use the rotation of the target, this is normally the camera
+    Billboard(Entity),
+    /// Keep the entity between `min` and `max` away from the target
+    Distance{target: Entity, min: f32, max: f32},
+    /// Limit how far the -Z axis of the entity can point up or down,
+    /// in radians
+    Pitch{min: f32, max: f32}
+}
+
+/// The constraints of an entity, they are applied in order
+#[derive(Clone, Debug, PartialEq)]
+pub struct Constraints(pub Vec<Constraint>);
+
+impl Constraints {
+    pub fn new(c: Constraint) -> Constraints {
+        Constraints(vec![c])
+    }
+
+    /// Add a constraint that is applied after the existing ones
+    pub fn then(mut self, c: Constraint) -> Constraints {
+        self.0.push(c);
+        self
+    }
+
+    /// Apply the constraints in order to the world transform of an
+    /// entity. `target` looks up the world transform of the entities
+    /// that the constraints refer to.
+    pub fn apply<F>(&self, world: &Matrix4<f32>, target: F) -> Matrix4<f32>
+        where F: Fn(Entity) -> Option<Matrix4<f32>>
+    {
+        let mut pose = Pose::new(world);
+        for c in self.0.iter() {
+            apply(&mut pose, c, &target);
+        }
+        pose.to_mat()
+    }
+}
+
+fn axis(v: Vector4<f32>) -> Vector3<f32> {
+    Vector3::new(v.x, v.y, v.z)
+}
+
+fn position(m: &Matrix4<f32>) -> Vector3<f32> {
+    axis(m.w)
+}
+
+/// A world transform split into a position, the axes
+/// of the rotation and the scale of each axis
+#[derive(Copy, Clone, Debug)]
+struct Pose {
+    pos: Vector3<f32>,
+    rot: [Vector3<f32>; 3],
+    scale: Vector3<f32>
+}
+
+impl Pose {
+    fn new(m: &Matrix4<f32>) -> Pose {
+        let cols = [axis(m.x), axis(m.y), axis(m.z)];
+        let mut rot = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()];
+        let mut scale = [1f32; 3];
+        for i in 0..3 {
+            let len = cols[i].length();
+            if len != 0. {
+                rot[i] = cols[i].mul_s(1. / len);
+                scale[i] = len;
+            }
+        }
+        Pose {
+            pos: position(m),
+            rot: rot,
+            scale: Vector3::new(scale[0], scale[1], scale[2])
+        }
+    }
+
+    fn to_mat(&self) -> Matrix4<f32> {
+        let x = self.rot[0].mul_s(self.scale.x);
+        let y = self.rot[1].mul_s(self.scale.y);
+        let z = self.rot[2].mul_s(self.scale.z);
+        Matrix4::new(
+            x.x, x.y, x.z, 0.,
+            y.x, y.y, y.z, 0.,
+            z.x, z.y, z.z, 0.,
+            self.pos.x, self.pos.y, self.pos.z, 1.
+        )
+    }
+
+    /// Turn so the -Z axis points along `forward`
+    fn look_along(&mut self, forward: Vector3<f32>) {
+        if forward.length() < 1e-6 {
+            return;
+        }
+        let f = forward.normalize();
+        // fall back to +Z as up when looking straight up or down
+        let up = if f.y.abs() > 0.9999 { Vector3::unit_z() } else { Vector3::unit_y() };
+        let right = f.cross(&up).normalize();
+        let up = right.cross(&f);
+        self.rot = [right, up, f.mul_s(-1.)];
+    }
+
+    fn forward(&self) -> Vector3<f32> {
+        self.rot[2].mul_s(-1.)
+    }
+}
+
+#[derive(Clone)]
+pub struct ConstraintData {
+    pub constraints: OrderedVec<Entity, Constraints>,
+}
+
+impl ConstraintData {
+    fn new() -> ConstraintData {
+        ConstraintData {
+            constraints: OrderedVec::new(),
+        }
+    }
+}
+
+fn apply<F>(pose: &mut Pose, c: &Constraint, target: &F)
+    where F: Fn(Entity) -> Option<Matrix4<f32>>
+{
+    match c {
+        &Constraint::LookAt(eid) => {
+            if let Some(m) = target(eid) {
+                let dir = position(&m).sub_v(&pose.pos);
+                pose.look_along(dir);
+            }
+        }
+        &Constraint::CopyPosition(eid) => {
+            if let Some(m) = target(eid) {
+                pose.pos = position(&m);
+            }
+        }
+        &Constraint::Orbit{target: eid, distance, yaw, pitch} => {
+            if let Some(m) = target(eid) {
+                let center = position(&m);
+                let offset = Vector3::new(
+                    yaw.sin() * pitch.cos(),
+                    pitch.sin(),
+                    yaw.cos() * pitch.cos()
+                );
+                pose.pos = center.add_v(&offset.mul_s(distance));
+                pose.look_along(offset.mul_s(-1.));
+            }
+        }
+        &Constraint::Billboard(eid) => {
+            if let Some(m) = target(eid) {
+                pose.rot = Pose::new(&m).rot;
+            }
+        }
+        &Constraint::Distance{target: eid, min, max} => {
+            if let Some(m) = target(eid) {
+                let center = position(&m);
+                let offset = pose.pos.sub_v(&center);
+                let len = offset.length();
+                if len < 1e-6 {
+                    return;
+                }
+                let clamped = len.max(min).min(max);
+                if clamped != len {
+                    pose.pos = center.add_v(&offset.mul_s(clamped / len));
+                }
+            }
+        }
+        &Constraint::Pitch{min, max} => {
+            let f = pose.forward();
+            let pitch = f.y.max(-1.).min(1.).asin();
+            let clamped = pitch.max(min).min(max);
+            if clamped == pitch {
+                return;
+            }
+            let flat = Vector3::new(f.x, 0., f.z);
+            if flat.length() < 1e-6 {
+                return;
+            }
+            let flat = flat.normalize().mul_s(clamped.cos());
+            pose.look_along(Vector3::new(flat.x, clamped.sin(), flat.z));
+        }
+    }
+}
+
+pub type Message = Operation<Entity, Constraints>;
+
+/// Holds the constraints of each entity, they are evaluated by a transform
+/// system that was created with `transform::constrained`. The constraints
+/// of an entity are removed when the entity is deleted from `parent`.
+pub fn constraint(sched: &mut Schedule, parent: ParentSystem) -> ConstraintSystem {
+    let cd = ConstraintData::new();
+    let (system, handle) = system::System::following("constraint", &parent, cd.clone(), cd);
+
+    system.follow(sched, parent, |data, old, msgs, p, _| update(data, old, msgs, p));
+
+    handle
+}
+
+/// Create the constraint system without starting it, the step
+/// depends on the step of `parent`
+pub fn constraint_step(parent: ParentSystem) -> (ConstraintSystem, system::schedule::Step) {
+    let cd = ConstraintData::new();
+    let (system, handle) = system::System::following("constraint", &parent, cd.clone(), cd);
+    (handle, system.follow_step(parent, |data, old, msgs, p, _| update(data, old, msgs, p)))
+}
+
+fn update(mut data: ConstraintData,
+          old: &ConstraintData,
+          mut msgs: Vec<Message>,
+          p: &ParentSystem) -> ConstraintData {
+    data.clone_from(old);
+
+    for &p in p.deleted.keys() {
+        msgs.push(Operation::Delete(p));
+    }
+    msgs.sort_by(|a, b| a.key().cmp(b.key()));
+
+    data.constraints.apply_updates(msgs.into_iter());
+    data
+}
+
+impl entity::WriteEntity<Entity, Constraint> for ConstraintSystem {
+    fn write(&mut self, eid: Entity, c: Constraint) {
+        self.send(Operation::Upsert(eid, Constraints::new(c)));
+    }
+}
+
+pub type ConstraintSystem = system::SystemHandle<Message, ConstraintData>;
@@ -0,0 +1,155 @@
+use gfx_device_gl;
+use gfx_window_glutin;
+use glutin;
+
+#[cfg(feature="virtual_reality")]
+use vr;
+#[cfg(feature="virtual_reality")]
+use gfx_vr;
+
+use {Engine, Output, RenderArgs};
+
+/// Describes the window and GL context the `Engine` is created with
+#[derive(Clone, Debug)]
+pub struct EngineBuilder {
+    title: String,
+    dimensions: (u32, u32),
+    depth_bits: u8,
+    gl_version: (u8, u8),
+    vsync: bool,
+    fullscreen: bool,
+    multisampling: Option<u16>,
+    tick_rate: f64
+}
+
+impl EngineBuilder {
+    /// Create a builder with the default settings, an 800x600
+    /// window titled "whiske-rs" with a 24bit depth buffer and
+    /// a OpenGL 3.2 core context
+    pub fn new() -> EngineBuilder {
+        EngineBuilder {
+            title: "whiske-rs".to_string(),
+            dimensions: (800, 600),
+            depth_bits: 24,
+            gl_version: (3, 2),
+            vsync: false,
+            fullscreen: false,
+            multisampling: None,
+            tick_rate: 60.
+        }
+    }
+
+    /// Set the title of the window
+    pub fn with_title(mut self, title: String) -> EngineBuilder {
+        self.title = title;
+        self
+    }
+
+    /// Set the width and height of the window
+    pub fn with_dimensions(mut self, width: u32, height: u32) -> EngineBuilder {
+        self.dimensions = (width, height);
+        self
+    }
+
+    /// Set the number of bits in the depth buffer
+    pub fn with_depth_buffer(mut self, bits: u8) -> EngineBuilder {
+        self.depth_bits = bits;
+        self
+    }
+
+    /// Request a specific version of the OpenGL core profile
+    pub fn with_gl_version(mut self, major: u8, minor: u8) -> EngineBuilder {
+        self.gl_version = (major, minor);
+        self
+    }
+
+    /// Enable or disable vsync
+    pub fn with_vsync(mut self, vsync: bool) -> EngineBuilder {
+        self.vsync = vsync;
+        self
+    }
+
+    /// Open the window fullscreen on the primary monitor
+    pub fn with_fullscreen(mut self, fullscreen: bool) -> EngineBuilder {
+        self.fullscreen = fullscreen;
+        self
+    }
+
+    /// Set the number of samples used for multisampling, 0 disables it
+    pub fn with_multisampling(mut self, samples: u16) -> EngineBuilder {
+        self.multisampling = if samples == 0 { None } else { Some(samples) };
+        self
+    }
+
+    /// Set the number of fixed simulation ticks per second
+    pub fn with_tick_rate(mut self, hz: f64) -> EngineBuilder {
+        self.tick_rate = hz;
+        self
+    }
+
+    fn window_builder(&self) -> glutin::WindowBuilder<'static> {
+        let (major, minor) = self.gl_version;
+        let mut builder = glutin::WindowBuilder::new()
+            .with_title(self.title.clone())
+            .with_dimensions(self.dimensions.0, self.dimensions.1)
+            .with_gl(glutin::GlRequest::Specific(glutin::Api::OpenGl, (major, minor)))
+            .with_depth_buffer(self.depth_bits);
+
+        if self.vsync {
+            builder = builder.with_vsync();
+        }
+        if self.fullscreen {
+            builder = builder.with_fullscreen(glutin::get_primary_monitor());
+        }
+        if let Some(samples) = self.multisampling {
+            builder = builder.with_multisampling(samples);
+        }
+        builder
+    }
+
+    /// Open the window and create the Engine
+    #[cfg(feature="virtual_reality")]
+    pub fn build(self) -> Engine<gfx_device_gl::Device,
+                                 gfx_device_gl::Factory,
+                                 gfx_device_gl::Resources> {
+
+        let vr = vr::IVRSystem::init();
+
+        let window = if let Ok(ref vr) = vr {
+            gfx_vr::window::glutin::build(vr)
+        } else {
+            self.window_builder().build()
+        }.unwrap();
+
+        let (stream, device, factory) = gfx_window_glutin::init(window);
+
+        let ra = RenderArgs {
+            vr: vr.ok(),
+            device: device,
+            factory: factory
+        };
+
+        let mut engine = Engine::from_output(Output::Window(stream), Some(ra));
+        engine.set_tick_rate(self.tick_rate);
+        engine
+    }
+
+    /// Open the window and create the Engine
+    #[cfg(not(feature="virtual_reality"))]
+    pub fn build(self) -> Engine<gfx_device_gl::Device,
+                                 gfx_device_gl::Factory,
+                                 gfx_device_gl::Resources> {
+
+        let window = self.window_builder().build().unwrap();
+        let (stream, device, factory) = gfx_window_glutin::init(window);
+
+        let ra = RenderArgs {
+            device: device,
+            factory: factory
+        };
+
+        let mut engine = Engine::from_output(Output::Window(stream), Some(ra));
+        engine.set_tick_rate(self.tick_rate);
+        engine
+    }
+}
@@ -0,0 +1,160 @@
+use std::sync::{Arc, Mutex};
+
+/// A single fixed step of the simulation clock
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Tick {
+    /// The number of ticks that happened before this one
+    pub index: u64,
+    /// The amount of simulation time covered by this tick in seconds
+    pub dt: f64,
+    /// The simulation time at the end of this tick in seconds, this is
+    /// the sum of the `dt` of every tick so far
+    pub time: f64
+}
+
+const NANOS: f64 = 1_000_000_000.;
+
+fn to_nanos(seconds: f64) -> u64 {
+    (seconds * NANOS).round() as u64
+}
+
+#[derive(Copy, Clone, Debug)]
+struct ClockState {
+    paused: bool,
+    scale: f64,
+    steps: u32
+}
+
+/// A handle to control the simulation clock from outside of the engine
+#[derive(Clone)]
+pub struct ClockControl(Arc<Mutex<ClockState>>);
+
+impl ClockControl {
+    /// Stop the simulation clock, no ticks are produced until it is resumed
+    pub fn pause(&self) {
+        self.0.lock().unwrap().paused = true;
+    }
+
+    /// Start the simulation clock after it was paused
+    pub fn resume(&self) {
+        self.0.lock().unwrap().paused = false;
+    }
+
+    /// Returns true if the clock is paused
+    pub fn is_paused(&self) -> bool {
+        self.0.lock().unwrap().paused
+    }
+
+    /// Set how fast simulation time passes relative to real time,
+    /// 1.0 is real time, 0.5 is half speed.
+    pub fn set_time_scale(&self, scale: f64) {
+        assert!(scale >= 0.);
+        self.0.lock().unwrap().scale = scale;
+    }
+
+    /// Get the current time scale
+    pub fn time_scale(&self) -> f64 {
+        self.0.lock().unwrap().scale
+    }
+
+    /// Run a single tick on the next frame, this is only useful when
+    /// the clock is paused.
+    pub fn step(&self) {
+        self.0.lock().unwrap().steps += 1;
+    }
+}
+
+/// A fixed timestep clock. Real time is accumulated and split into
+/// fixed sized `Tick`s, what is left over is reported as the
+/// interpolation alpha between the last two ticks.
+///
+/// Time is counted in whole nanoseconds so that rounding never
+/// changes how many ticks a given amount of time is worth.
+pub struct Clock {
+    dt: u64,
+    max_ticks: u32,
+    index: u64,
+    time: u64,
+    accumulator: u64,
+    state: Arc<Mutex<ClockState>>
+}
+
+impl Clock {
+    /// Create a new clock that ticks every `dt` seconds
+    pub fn new(dt: f64) -> Clock {
+        assert!(dt > 0.);
+        Clock {
+            dt: to_nanos(dt),
+            max_ticks: 8,
+            index: 0,
+            time: 0,
+            accumulator: 0,
+            state: Arc::new(Mutex::new(ClockState {
+                paused: false,
+                scale: 1.,
+                steps: 0
+            }))
+        }
+    }
+
+    /// The duration of a tick
+    pub fn dt(&self) -> f64 {
+        self.dt as f64 / NANOS
+    }
+
+    /// Change the duration of a tick, partial ticks that were
+    /// accumulated are dropped
+    pub fn set_dt(&mut self, dt: f64) {
+        assert!(dt > 0.);
+        self.dt = to_nanos(dt);
+        self.accumulator = 0;
+    }
+
+    /// Get a handle to control the clock
+    pub fn control(&self) -> ClockControl {
+        ClockControl(self.state.clone())
+    }
+
+    /// Advance the clock by `elapsed` seconds of real time. This returns
+    /// the ticks that are ready to run and the interpolation alpha.
+    ///
+    /// To avoid falling further and further behind, at most `max_ticks`
+    /// will be produced for a single call, the rest of the time is dropped.
+    pub fn advance(&mut self, elapsed: f64) -> (Vec<Tick>, f64) {
+        let mut ticks = Vec::new();
+        let state = {
+            let mut state = self.state.lock().unwrap();
+            let s = *state;
+            state.steps = 0;
+            s
+        };
+
+        if state.paused {
+            for _ in 0..state.steps {
+                ticks.push(self.tick());
+            }
+        } else {
+            self.accumulator += to_nanos(elapsed * state.scale);
+            while self.accumulator >= self.dt && ticks.len() < self.max_ticks as usize {
+                self.accumulator -= self.dt;
+                ticks.push(self.tick());
+            }
+            if self.accumulator >= self.dt {
+                self.accumulator = self.accumulator % self.dt;
+            }
+        }
+
+        (ticks, self.accumulator as f64 / self.dt as f64)
+    }
+
+    fn tick(&mut self) -> Tick {
+        self.time += self.dt;
+        let tick = Tick {
+            index: self.index,
+            dt: self.dt as f64 / NANOS,
+            time: self.time as f64 / NANOS
+        };
+        self.index += 1;
+        tick
+    }
+}
@@ -1,5 +1,6 @@
 
 use glutin::{self, Event};
+use clock::Tick;
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Button {
@@ -342,6 +343,11 @@ impl Button {
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum WindowEvent {
     TimeStamp(f64),
+    /// A fixed step of the simulation clock, zero or more of these
+    /// are sent each frame after the `TimeStamp`
+    Tick(Tick),
+    /// How far between the last tick and the next the rendered frame is
+    Interpolation(f64),
     Pos(i32, i32),
     Size(u32, u32),
     Close,
@@ -5,6 +5,7 @@ extern crate gfx_device_gl;
 extern crate gfx_window_glutin;
 extern crate glutin;
 extern crate time;
+extern crate system;
 
 #[cfg(feature="virtual_reality")]
 extern crate vr;
@@ -12,7 +13,11 @@ extern crate vr;
 extern crate gfx_vr;
 
 pub mod event;
+pub mod builder;
+pub mod clock;
 use event::WindowEvent;
+pub use builder::EngineBuilder;
+pub use clock::{Clock, ClockControl, Tick};
 
 pub mod fibe {
     pub use fibers::*;
@@ -24,12 +29,31 @@ pub use snowstorm::channel::*;
 
 pub type Window<D, R> = gfx::extra::stream::OwnedStream<D, gfx_window_glutin::Output<R>>;
 
+/// The amount of time that passes between two frames of a headless engine
+pub const HEADLESS_FRAME_TIME: f64 = 1. / 60.;
+
+/// Where the engine gets its frames from
+enum Output<D: gfx::Device, R: gfx::Resources> {
+    /// Frames are driven by a glutin window
+    Window(Window<D, R>),
+    /// There is no window, frames are counted and the timestamps
+    /// are derived from the frame number
+    Headless(u64)
+}
+
 pub struct Engine<D: gfx::Device, F, R: gfx::Resources> {
-    input: (Sender<WindowEvent>, Receiver<WindowEvent>),
+    input: Sender<WindowEvent>,
+    input_rx: Receiver<WindowEvent>,
     pool: fibe::Frontend,
-    window: Window<D, R>,
+    output: Output<D, R>,
+    start: Option<f64>,
+    last_time: f64,
+    clock: Clock,
     render_args: Option<RenderArgs<D, F>>,
     render: Option<Box<FnMut(&mut fibe::Schedule, &mut Window<D, R>)>>,
+    headless_render: Option<Box<FnMut(&mut fibe::Schedule)>>,
+    shutdown: Vec<system::Shutdown>,
+    closed: bool
 }
 
 pub struct RenderArgs<D: gfx::Device, F> {
@@ -44,69 +68,24 @@ impl Engine<gfx_device_gl::Device,
             gfx_device_gl::Resources> {
 
 
-    /// Create a new Engine context
-    #[cfg(feature="virtual_reality")]
+    /// Create a new Engine context using the default `EngineBuilder`
     pub fn new() -> Engine<gfx_device_gl::Device,
                            gfx_device_gl::Factory,
                            gfx_device_gl::Resources> {
-
-        let vr = vr::IVRSystem::init();
-
-        let window = if let Ok(ref vr) = vr {
-            gfx_vr::window::glutin::build(vr)
-        } else {
-            glutin::WindowBuilder::new()
-                .with_title("whiske-rs".to_string())
-                .with_dimensions(800, 600)
-                .with_gl(glutin::GL_CORE)
-                .with_depth_buffer(24)
-                .build()
-        }.unwrap();
-
-        let (stream, device, factory) = gfx_window_glutin::init(window);
-
-        let ra = RenderArgs {
-            vr: vr.ok(),
-            device: device,
-            factory: factory
-        };
-
-        Engine {
-            input: channel(),
-            pool: fibe::Frontend::new(),
-            window: stream,
-            render_args: Some(ra),
-            render: None
-        }
+        EngineBuilder::new().build()
     }
 
-    /// Create a new Engine context
-    #[cfg(not(feature="virtual_reality"))]
-    pub fn new() -> Engine<gfx_device_gl::Device,
-                           gfx_device_gl::Factory,
-                           gfx_device_gl::Resources> {
-
-        let window = glutin::WindowBuilder::new()
-            .with_title("whiske-rs".to_string())
-            .with_dimensions(800, 600)
-            .with_gl(glutin::GL_CORE)
-            .with_depth_buffer(24)
-            .build().unwrap();
-
-        let (stream, device, factory) = gfx_window_glutin::init(window);
-
-        let ra = RenderArgs {
-            device: device,
-            factory: factory
-        };
-
-        Engine {
-            input: channel(),
-            pool: fibe::Frontend::new(),
-            window: stream,
-            render_args: Some(ra),
-            render: None
-        }
+    /// Create a new Engine context that does not open a window. The
+    /// engine runs the scheduler and the input channel as normal, but
+    /// each frame advances the `TimeStamp` by `HEADLESS_FRAME_TIME`
+    /// so that the frames are deterministic.
+    ///
+    /// There are no `RenderArgs` for a headless engine, use
+    /// `start_headless_render` to install a render callback.
+    pub fn headless() -> Engine<gfx_device_gl::Device,
+                                gfx_device_gl::Factory,
+                                gfx_device_gl::Resources> {
+        Engine::from_output(Output::Headless(0), None)
     }
 }
 
@@ -116,12 +95,38 @@ impl<D, F, R> Engine<D, F, R>
           R: gfx::Resources
 
  {
+    fn from_output(output: Output<D, R>, ra: Option<RenderArgs<D, F>>) -> Engine<D, F, R> {
+        let (tx, rx) = channel();
+        Engine {
+            input: tx,
+            input_rx: rx,
+            pool: fibe::Frontend::new(),
+            output: output,
+            start: None,
+            last_time: 0.,
+            clock: Clock::new(HEADLESS_FRAME_TIME),
+            render_args: ra,
+            render: None,
+            headless_render: None,
+            shutdown: Vec::new(),
+            closed: false
+        }
+    }
+
+    /// Returns true if the engine was created without a window
+    pub fn is_headless(&self) -> bool {
+        match self.output {
+            Output::Headless(_) => true,
+            Output::Window(_) => false
+        }
+    }
+
     /// Fetch a copy of the input stream and run actor
     /// with the input stream as a input
     pub fn start_input_processor<C>(&mut self, actor: C)
         where C: FnOnce(&mut fibe::Schedule, Receiver<WindowEvent>)+Send+'static {
         
-        let rx = self.input.1.clone();
+        let rx = self.input_rx.clone();
         task(|sched| {
             actor(sched, rx);
         }).start(&mut self.pool);
@@ -132,45 +137,154 @@ impl<D, F, R> Engine<D, F, R>
     pub fn start_render<C>(&mut self, render: C)
         where C: FnOnce(&mut fibe::Schedule, RenderArgs<D, F>) -> Box<FnMut(&mut fibe::Schedule, &mut Window<D, R>)> {
 
-        let ra = self.render_args.take().expect("Only one render can be created");
+        let ra = self.render_args.take().expect("Only one render can be created, and none for a headless engine");
         let render = render(&mut self.pool, ra);
         self.render = Some(render);
     }
 
+    /// Install a render callback that does not need a window. It is called
+    /// once per frame after the input for that frame has been sent. This
+    /// is the only render a headless engine can run.
+    pub fn start_headless_render<C>(&mut self, render: C)
+        where C: FnMut(&mut fibe::Schedule) + 'static {
+
+        assert!(self.headless_render.is_none(), "Only one render can be created");
+        self.headless_render = Some(Box::new(render));
+    }
+
+    /// Get a handle to pause, scale or single step the simulation clock
+    pub fn clock_control(&self) -> ClockControl {
+        self.clock.control()
+    }
+
+    /// Set the number of simulation ticks per second
+    pub fn set_tick_rate(&mut self, hz: f64) {
+        self.clock.set_dt(1. / hz);
+    }
+
+    /// Queue an event to be sent to the input channel with the next frame
+    pub fn send_event(&mut self, event: WindowEvent) {
+        self.input.send(event);
+    }
+
+    /// Signal `shutdown` when the engine shuts down. Register the
+    /// systems that do not follow another system, the systems
+    /// downstream of them shut down once they see the last frame.
+    pub fn on_shutdown(&mut self, shutdown: system::Shutdown) {
+        self.shutdown.push(shutdown);
+    }
+
+    /// Shutdown every registered system and stop running frames.
+    /// This is done for you when the window is closed.
+    pub fn shutdown(&mut self) {
+        for s in self.shutdown.drain(..) {
+            s.signal();
+        }
+        self.closed = true;
+    }
+
+    /// Returns true once the engine was shutdown
+    pub fn is_shutdown(&self) -> bool {
+        self.closed
+    }
+
     /// Get the scheduler to scheduler tasks on it
     pub fn sched(&mut self) -> &mut fibe::Schedule {
         &mut self.pool
     }
 
-    /// Get a copy of the input channel
+    /// Get a copy of the input channel, the copy sees every frame
+    /// that is sent after it was made
     pub fn input_channel(&self) -> InputChannel {
-        self.input.1.clone()
+        self.input_rx.clone()
     }
 
-    /// run the engine
-    pub fn run(mut self) {
+    /// Run a single frame, returns false if the engine was shutdown
+    fn frame(&mut self) -> bool {
+        if self.closed {
+            return false;
+        }
         let mut run = true;
-        let (mut send, recv) = self.input;
-        drop(recv);
-
-
-        let start = time::precise_time_s();
-        let mut render = self.render.take().expect("no render installed!");
 
-        while run {
-            for event in self.window.out.window.poll_events() {
-                match event {
-                    glutin::Event::Closed => {
-                        run = false;
+        let (time, elapsed) = match self.output {
+            Output::Window(ref mut window) => {
+                for event in window.out.window.poll_events() {
+                    match event {
+                        glutin::Event::Closed => {
+                            run = false;
+                        }
+                        _ => ()
+                    }
+                    if let Some(e) = WindowEvent::from_glutin(event) {
+                        self.input.send(e);
                     }
-                    _ => ()
                 }
-                WindowEvent::from_glutin(event).map(|e| send.send(e));
+
+                if self.start.is_none() {
+                    self.start = Some(time::precise_time_s());
+                }
+                let time = time::precise_time_s() - self.start.unwrap();
+                let elapsed = time - self.last_time;
+                self.last_time = time;
+                (time, elapsed)
+            }
+            Output::Headless(ref mut frame) => {
+                // Every frame is exactly one frame time apart, it is not
+                // derived from the timestamps so there is no rounding
+                let elapsed = if *frame == 0 { 0. } else { HEADLESS_FRAME_TIME };
+                let time = *frame as f64 * HEADLESS_FRAME_TIME;
+                *frame += 1;
+                (time, elapsed)
+            }
+        };
+
+        self.input.send(WindowEvent::TimeStamp(time));
+
+        let (ticks, alpha) = self.clock.advance(elapsed);
+        for tick in ticks {
+            self.input.send(WindowEvent::Tick(tick));
+        }
+        self.input.send(WindowEvent::Interpolation(alpha));
+        self.input.next_frame();
+
+        // Our own copy of the input is only kept so that it can be
+        // cloned, skip past the frame so it does not buffer forever
+        while self.input_rx.recv().is_ok() {}
+        self.input_rx.next_frame();
+
+        if let Output::Window(ref mut window) = self.output {
+            if let Some(ref mut render) = self.render {
+                render(&mut self.pool, window);
+            }
+        }
+        if let Some(ref mut render) = self.headless_render {
+            render(&mut self.pool);
+        }
+
+        if !run {
+            self.shutdown();
+        }
+        run
+    }
+
+    /// Run `n` frames of the engine. Returns false if the
+    /// engine was shutdown before all the frames were run.
+    pub fn run_frames(&mut self, n: usize) -> bool {
+        for _ in 0..n {
+            if !self.frame() {
+                return false;
             }
-            send.send(WindowEvent::TimeStamp(time::precise_time_s() - start));
-            send.next_frame();
-            render(&mut self.pool, &mut self.window);
         }
+        true
+    }
+
+    /// run the engine
+    pub fn run(mut self) {
+        if self.render.is_none() && self.headless_render.is_none() {
+            panic!("no render installed!");
+        }
+
+        while self.frame() {}
     }
 }
 
@@ -10,6 +10,7 @@ extern crate gfx_mesh;
 extern crate shared_future;
 extern crate lease;
 extern crate engine;
+extern crate system;
 
 use std::collections::HashMap;
 
@@ -17,6 +18,7 @@ use engine::fibe::*;
 
 use entity::*;
 use snowstorm::mpsc::*;
+use system::Shutdown;
 
 pub use material::*;
 pub use texture::Texture;
@@ -272,12 +274,44 @@ pub struct Graphics {
 
     /// Link to the data associated with this frame
     data: lease::Lease<GraphicsStore>,
+
+    /// Shared with the worker, used to stop it
+    shutdown: Shutdown,
+
+    /// Set if this is the last frame the worker published
+    last: bool
 }
 
 impl Graphics {
     fn send(&mut self, msg: Message) {
         self.channel.send(msg)
     }
+
+    /// Ask the graphics worker to shutdown after its current frame
+    pub fn shutdown(&self) {
+        self.shutdown.signal();
+    }
+
+    /// Get a copy of the shutdown flag, signaling it has the same
+    /// effect as `shutdown` without holding on to the frame
+    pub fn shutdown_signal(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// Returns true if this is the last frame the graphics worker
+    /// will publish
+    pub fn is_shutdown(&self) -> bool {
+        self.last
+    }
+
+    /// Shutdown the graphics worker and wait for it to publish its last frame
+    pub fn join(self) {
+        self.shutdown();
+        let mut graphics = self;
+        while let Ok(next) = graphics.next_frame().get() {
+            graphics = next;
+        }
+    }
 }
 
 impl std::ops::Deref for Graphics {
@@ -390,13 +424,21 @@ impl GraphicsStore {
 
 fn worker(mut owner: lease::Owner<GraphicsStore>,
           mut set: shared_future::Set<Graphics>,
-          mut input: Receiver<Message>) {
+          mut input: Receiver<Message>,
+          shutdown: Shutdown) {
 
     loop {
         let mut data = owner.get();
+        let start = system::profile::now();
+        let mut messages = 0;
+        // the messages are applied as they arrive, the time spent
+        // between them is time spent waiting for the senders
+        let mut busy = 0.;
         data.clear_frame();
 
         for msg in input.iter() {
+            messages += 1;
+            let applied = system::profile::now();
             match msg {
                 Message::Vertex(Operation::Upsert(eid, vd)) => {
                     data.upsert_vertex(eid, vd);
@@ -428,16 +470,35 @@ fn worker(mut owner: lease::Owner<GraphicsStore>,
                 }
 
             }
+            busy += system::profile::now() - applied;
         }
 
+        let end = system::profile::now();
+        system::profile::timeline().record(system::profile::Span {
+            name: "graphics",
+            start: start,
+            ready: end - busy,
+            end: end,
+            messages: messages,
+            thread: system::profile::thread_id()
+        });
+
         let (nowner, lease) = lease::lease(data);
         let (tx, ninput) = channel();
         let (next, nset) = shared_future::Future::new();
+        let last = shutdown.is_signaled();
         set.set(Graphics{
             channel: tx,
             next: next,
-            data: lease
+            data: lease,
+            shutdown: shutdown.clone(),
+            last: last
         });
+
+        if last {
+            return;
+        }
+
         owner = nowner;
         set = nset;
         input = ninput;
@@ -460,19 +521,23 @@ impl Graphics {
             colors: HashMap::new()
         });
 
-        task(|_| worker(owner, set, rx)).start(sched);
+        let shutdown = Shutdown::new();
+        let s = shutdown.clone();
+        task(|_| worker(owner, set, rx, s)).start(sched);
 
         Graphics {
             channel: tx,
             next: future,
-            data: lease
+            data: lease,
+            shutdown: shutdown,
+            last: false
         }
     }
 
     /// Fetch the next frame
     pub fn next_frame(self) -> shared_future::Future<Graphics> {
         let Graphics {
-            channel, next, data
+            channel, next, data, shutdown: _, last: _
         } = self;
         drop((channel, data));
         next
@@ -5,8 +5,8 @@ extern crate engine;
 
 use std::collections::HashMap;
 use std::sync::Arc;
-use engine::fibe::*;
-use entity::{Entity, ReadEntity, WriteEntity, Operation};
+use engine::fibe::Schedule;
+use entity::{Entity, ReadEntity, Operation};
 use parent::{Parent, ParentSystem};
 
 pub type Message = Operation<Entity, Name>;
@@ -152,47 +152,28 @@ impl NameData {
     }
 }
 
-// Reads from the parent channel
-fn sync_ingest(ingest: &mut system::channel::Receiver<Message>) -> Vec<Message> {
-    let mut msgs: Vec<Message> = Vec::new();
-    while let Ok(op) = ingest.recv() {
-        msgs.push(op.clone());
-    }
-    msgs
-}
-
 /// The `parent` system takes and input of parent child bindings
 pub fn name(sched: &mut Schedule, parent: ParentSystem) -> NameSystem {
     let pd = NameData::new();
-    let (mut system, handle) = system::System::new(pd.clone(), pd);
+    let (system, handle) = system::System::following("name", &parent, pd.clone(), pd);
 
-    task(move |_| {
-        let mut parent = Some(parent);
-        loop {
-            let s = system.update(|mut name, src, mut msgs| {
-                let p = parent.take().unwrap().next_frame().get().unwrap();
-                name.clone_from(src);
-                name.update(sync_ingest(&mut msgs), &p);
-                parent = Some(p);
-                name
-            });
-            system = if let Some(s) = s { s } else { return; };
-        }
-    }).start(sched);
+    system.follow(sched, parent, |name, src, msgs, p, _| update(name, src, msgs, p));
 
     handle
 }
 
-impl entity::WriteEntity<Entity, Name> for NameSystem {
-    fn write(&mut self, eid: Entity, value: Name) {
-        self.send(Operation::Upsert(eid, value));
-    }
+/// Create the `name` system without starting it, the step
+/// depends on the step of `parent`
+pub fn name_step(parent: ParentSystem) -> (NameSystem, system::schedule::Step) {
+    let pd = NameData::new();
+    let (system, handle) = system::System::following("name", &parent, pd.clone(), pd);
+    (handle, system.follow_step(parent, |name, src, msgs, p, _| update(name, src, msgs, p)))
 }
 
-impl entity::ReadEntity<Entity, Name> for NameSystem {
-    fn read(&self, eid: &Entity) -> Option<&Name> {
-        self.name.get(eid)
-    }
+fn update(mut name: NameData, src: &NameData, msgs: Vec<Message>, p: &ParentSystem) -> NameData {
+    name.clone_from(src);
+    name.update(msgs, p);
+    name
 }
 
 impl entity::ReadEntity<Entity, Name> for NameData {
@@ -213,16 +194,6 @@ impl<'a> entity::ReadEntity<ChildByName<'a>, Entity> for NameData {
     }
 }
 
-impl<'a> entity::ReadEntity<ChildByName<'a>, Entity> for NameSystem {
-    fn read(&self, eid: &ChildByName<'a>) -> Option<&Entity> {
-        if let Some(path) = self.path.get(&eid.0) {
-            path.get(eid.1)
-        } else {
-            None
-        }
-    }
-}
-
 pub struct RootName<'a>(pub &'a str);
 
 impl<'a> entity::ReadEntity<RootName<'a>, Entity> for NameData {
@@ -231,12 +202,6 @@ impl<'a> entity::ReadEntity<RootName<'a>, Entity> for NameData {
     }
 }
 
-impl<'a> entity::ReadEntity<RootName<'a>, Entity> for NameSystem {
-    fn read(&self, eid: &RootName<'a>) -> Option<&Entity> {
-        self.root.get(eid.0)
-    }
-}
-
 pub type NameSystem = system::SystemHandle<Message, NameData>;
 
 pub trait PathLookup<'a> {
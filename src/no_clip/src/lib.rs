@@ -24,7 +24,8 @@ pub fn no_clip(sched: &mut Schedule,
     let mut speed_right = 0.;
     let speed_up = 0.;
     let mut last_mouse = None;
-    let rate = 2. / 60.;
+    // units per second
+    let rate = 2.;
 
     task(move |_| {
         loop {
@@ -74,22 +75,24 @@ pub fn no_clip(sched: &mut Schedule,
                     WindowEvent::ButtonUp(Button::D) => {
                         speed_right = 0.;
                     }
+                    WindowEvent::Tick(tick) => {
+                        let dt = tick.dt as f32;
+                        let camera = camera::Camera::new(last);
+                        let pos = camera.move_with_vector(
+                            &Vector3::new(speed_right * dt,
+                                          speed_up * dt,
+                                          -speed_foward * dt)
+                        );
+
+                        last.disp.x = pos.x;
+                        last.disp.y = pos.y;
+                        last.disp.z = pos.z;
+                    }
                     _ => ()
                 }
             }
 
-            let camera = camera::Camera::new(last);
-            let pos = camera.move_with_vector(
-                &Vector3::new(speed_right,
-                              speed_up,
-                              -speed_foward)
-            );
-
-            last.disp.x = pos.x;
-            last.disp.y = pos.y;
-            last.disp.z = pos.z;
-
-            entity.bind(Local(last)).write(&mut output);
+            entity.bind(Local::Decomposed(last)).write(&mut output);
 
             if !input.next_frame() {
                 return;
@@ -2,25 +2,81 @@ extern crate engine;
 extern crate entity;
 extern crate system;
 
-use std::collections::{HashMap, HashSet};
-use engine::fibe::*;
+use std::collections::HashMap;
+use engine::fibe::Schedule;
 use entity::{Entity, WriteEntity, Operation};
 
-pub type Message = Operation<Entity, Parent>;
+mod query;
+pub use query::{Ancestors, Children, Descendants, Order};
+
+pub type Message = Operation<Entity, Bind>;
 
 #[derive(Clone)]
 pub struct ParentData {
     /// Lookup table to find the parent from the child eid
     pub child_to_parent: HashMap<Entity, Parent>,
 
-    /// lookup table to find the children from the parent's eid
-    pub parent_to_children: HashMap<Entity, HashSet<Entity>>,
+    /// lookup table to find the children from the parent's eid,
+    /// use `children` to visit them in sibling order
+    pub parent_to_children: HashMap<Entity, ChildList>,
+
+    // the neighbours of every child amongst its siblings
+    siblings: HashMap<Entity, Link>,
 
     // Set of deleted entities from the last updated
     pub deleted: HashMap<Entity, Option<Parent>>,
 
     // Entities that's parent was changed during the last update
-    pub modified: HashMap<Entity, Option<Parent>>
+    pub modified: HashMap<Entity, Option<Parent>>,
+
+    /// Upserts that were not applied during the last update
+    pub rejected: Vec<Rejected>
+}
+
+/// The first and last child of a parent, the children in
+/// between are linked to each other in sibling order
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChildList {
+    pub first: Option<Entity>,
+    pub last: Option<Entity>,
+    /// The number of children
+    pub len: usize
+}
+
+impl ChildList {
+    /// Returns true if there are no children
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Link {
+    prev: Option<Entity>,
+    next: Option<Entity>
+}
+
+/// Why an `Upsert` of a `Parent` was not applied
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Rejected {
+    /// The child is the parent, or is one of the parent's ancestors
+    Cycle{child: Entity, parent: Entity},
+    /// The sibling is the child, the sibling does not have a parent
+    /// or the sibling is a root. Roots have no sibling order.
+    Sibling{child: Entity, sibling: Entity}
+}
+
+/// Where a child is placed amongst its siblings
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Bind {
+    /// Set the parent, a new child is placed after its siblings
+    Parent(Parent),
+    /// Move the child next to the sibling, before it. The sibling
+    /// must have a parent, a root sibling is `Rejected`.
+    Before(Entity),
+    /// Move the child next to the sibling, after it. The sibling
+    /// must have a parent, a root sibling is `Rejected`.
+    After(Entity)
 }
 
 impl ParentData {
@@ -28,54 +84,170 @@ impl ParentData {
         ParentData {
             child_to_parent: HashMap::new(),
             parent_to_children: HashMap::new(),
+            siblings: HashMap::new(),
             deleted: HashMap::new(),
-            modified: HashMap::new()
+            modified: HashMap::new(),
+            rejected: Vec::new()
         }
     }
 
-    /// This creates a binding between the parent and the child
-    fn bind(&mut self, parent: Entity, child: Entity) {
-        let old = self.child_to_parent.insert(child, Parent::Child(parent));
-        self.parent_to_children
+    /// Check if `ancestor` is `eid` or one of its ancestors
+    fn is_ancestor(&self, ancestor: Entity, eid: Entity) -> bool {
+        let mut eid = eid;
+        loop {
+            if eid == ancestor {
+                return true;
+            }
+            match self.child_to_parent.get(&eid) {
+                Some(&Parent::Child(p)) => eid = p,
+                Some(&Parent::Root) | None => return false
+            }
+        }
+    }
+
+    /// Remove the child from the children of its old parent
+    fn unlink(&mut self, child: Entity, old: Option<Parent>) {
+        let p = match old {
+            Some(Parent::Child(p)) => p,
+            _ => return
+        };
+        let link = match self.siblings.remove(&child) {
+            Some(link) => link,
+            None => return
+        };
+
+        match link.prev {
+            Some(prev) => self.siblings.get_mut(&prev).unwrap().next = link.next,
+            None => {
+                if let Some(list) = self.parent_to_children.get_mut(&p) {
+                    list.first = link.next;
+                }
+            }
+        }
+        match link.next {
+            Some(next) => self.siblings.get_mut(&next).unwrap().prev = link.prev,
+            None => {
+                if let Some(list) = self.parent_to_children.get_mut(&p) {
+                    list.last = link.prev;
+                }
+            }
+        }
+        if let Some(list) = self.parent_to_children.get_mut(&p) {
+            list.len -= 1;
+        }
+    }
+
+    /// Add the child to the children of the parent, before or after
+    /// `sibling` if it is one of the parent's children, otherwise
+    /// after all of them
+    fn link(&mut self, parent: Entity, child: Entity, sibling: Option<(Entity, bool)>) {
+        let sibling = sibling.and_then(|(sibling, after)| {
+            match self.child_to_parent.get(&sibling) {
+                Some(&Parent::Child(p)) if p == parent && sibling != child => {
+                    self.siblings.get(&sibling).map(|l| {
+                        if after { (Some(sibling), l.next) } else { (l.prev, Some(sibling)) }
+                    })
+                }
+                _ => None
+            }
+        });
+
+        let list = self.parent_to_children
             .entry(parent)
-            .or_insert_with(HashSet::new)
-            .insert(child);
-        self.modified.insert(child, old);
+            .or_insert_with(ChildList::default);
+        let (prev, next) = sibling.unwrap_or((list.last, None));
+
+        match prev {
+            Some(prev) => self.siblings.get_mut(&prev).unwrap().next = Some(child),
+            None => list.first = Some(child)
+        }
+        match next {
+            Some(next) => self.siblings.get_mut(&next).unwrap().prev = Some(child),
+            None => list.last = Some(child)
+        }
+        list.len += 1;
+        self.siblings.insert(child, Link{prev: prev, next: next});
+    }
+
+    /// This creates a binding between the parent and the child. The child
+    /// is placed next to `sibling`, or after all of its siblings. A child
+    /// that already belongs to the parent stays in place if no sibling
+    /// was given.
+    fn bind(&mut self, parent: Entity, child: Entity, sibling: Option<(Entity, bool)>) {
+        let old = self.child_to_parent.insert(child, Parent::Child(parent));
+        if old == Some(Parent::Child(parent)) && sibling.is_none() {
+            return;
+        }
+
+        let link = self.siblings.get(&child).map(|l| *l);
+        self.unlink(child, old);
+        self.link(parent, child, sibling);
+
+        // moving a child to where it already is changes nothing
+        if old != Some(Parent::Child(parent)) || self.siblings.get(&child).map(|l| *l) != link {
+            self.modified.insert(child, old);
+        }
     }
 
     /// Recessively delete the children of a parent
     fn delete(&mut self, eid: Entity) {
-        if let Some(children) = self.parent_to_children.remove(&eid) {
-            for child in children {
-                self.delete(child);
-            }
+        let children: Vec<Entity> = self.children(eid).collect();
+        self.parent_to_children.remove(&eid);
+        for child in children {
+            self.delete(child);
         }
         let old = self.child_to_parent.remove(&eid);
-        if let Some(_) = old {
-            if let Some(p2c) = self.parent_to_children.get_mut(&eid) {
-                p2c.remove(&eid);
+        self.unlink(eid, old);
+        self.deleted.insert(eid, old);
+    }
+
+    fn root(&mut self, eid: Entity) {
+        let old = self.child_to_parent.insert(eid, Parent::Root);
+        self.unlink(eid, old);
+        self.parent_to_children
+            .entry(eid)
+            .or_insert_with(ChildList::default);
+        if old != Some(Parent::Root) {
+            self.modified.insert(eid, old);
+        }
+    }
+
+    fn child(&mut self, eid: Entity, parent: Entity, sibling: Option<(Entity, bool)>) {
+        if self.is_ancestor(eid, parent) {
+            self.rejected.push(Rejected::Cycle{child: eid, parent: parent});
+        } else {
+            self.bind(parent, eid, sibling);
+        }
+    }
+
+    fn sibling(&mut self, eid: Entity, sibling: Entity, after: bool) {
+        match self.child_to_parent.get(&sibling).map(|x| *x) {
+            Some(_) if sibling == eid => {
+                self.rejected.push(Rejected::Sibling{child: eid, sibling: sibling});
+            }
+            Some(Parent::Child(p)) => self.child(eid, p, Some((sibling, after))),
+            Some(Parent::Root) | None => {
+                self.rejected.push(Rejected::Sibling{child: eid, sibling: sibling});
             }
         }
-        self.deleted.insert(eid, old);
     }
 
-    fn write(&mut self, op: Operation<Entity, Parent>) {
+    fn write(&mut self, op: Operation<Entity, Bind>) {
         match op {
             Operation::Delete(eid) => self.delete(eid),
-            Operation::Upsert(eid, Parent::Root) => {
-                self.parent_to_children.insert(eid, HashSet::new());
-                let old = self.child_to_parent.insert(eid, Parent::Root);
-                self.modified.insert(eid, old);
-            }
-            Operation::Upsert(eid, Parent::Child(parent)) => {
-                self.bind(parent, eid);
+            Operation::Upsert(eid, Bind::Parent(Parent::Root)) => self.root(eid),
+            Operation::Upsert(eid, Bind::Parent(Parent::Child(parent))) => {
+                self.child(eid, parent, None)
             }
+            Operation::Upsert(eid, Bind::Before(sibling)) => self.sibling(eid, sibling, false),
+            Operation::Upsert(eid, Bind::After(sibling)) => self.sibling(eid, sibling, true)
         }
     }
 
     fn apply_parent(&mut self, msgs: &[Message]) {
         self.deleted.clear();
         self.modified.clear();
+        self.rejected.clear();
 
         for &m in msgs {
             self.write(m);
@@ -83,13 +255,10 @@ impl ParentData {
     }
 }
 
-// Reads from the parent channel
-fn sync_ingest(ingest: &mut system::channel::Receiver<Message>) -> Vec<Message> {
-    let mut msgs: Vec<Message> = Vec::new();
-    while let Ok(op) = ingest.recv() {
-        msgs.push(*op);
+impl system::DeletedEntities for ParentData {
+    fn deleted_entities(&self) -> Vec<Entity> {
+        self.deleted.keys().map(|&k| k).collect()
     }
-    msgs
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -100,38 +269,50 @@ pub enum Parent {
     Child(Entity)
 }
 
+// Applies each frame's messages, the back buffer is a frame behind
+// so the messages of the last frame are replayed first
+struct Ingest {
+    last: Vec<Message>
+}
+
+impl Ingest {
+    fn apply(&mut self, mut parent: ParentData, msgs: Vec<Message>) -> ParentData {
+        parent.apply_parent(&self.last[..]);
+        parent.apply_parent(&msgs[..]);
+        self.last = msgs;
+        parent
+    }
+}
+
 /// The `parent` system takes and input of parent child bindings
 pub fn parent(sched: &mut Schedule) -> ParentSystem {
     let pd = ParentData::new();
-    let (mut system, handle) = system::System::new(pd.clone(), pd);
-
-    let mut lpmsgs = Vec::new();
+    let (system, handle) = system::System::named("parent", pd.clone(), pd);
 
-    task(move |_| {
-        loop {
-            let s = system.update(|mut parent, _, mut msgs| {
-                let pmsgs = sync_ingest(&mut msgs);
+    let mut ingest = Ingest{last: Vec::new()};
+    system.run(sched, move |parent, _, msgs| ingest.apply(parent, msgs));
 
-                parent.apply_parent(&lpmsgs[..]);
-                parent.apply_parent(&pmsgs[..]);
+    handle
+}
 
-                lpmsgs = pmsgs;
-                parent
-            });
-            system = if let Some(s) = s { s } else { return; };
-        }
-    }).start(sched);
+/// Create the `parent` system without starting it. The returned step
+/// runs a single frame of the system, it is meant to be added to a
+/// `system::Graph` so the system is run by a `system::FrameScheduler`.
+pub fn parent_step() -> (ParentSystem, system::schedule::Step) {
+    let pd = ParentData::new();
+    let (system, handle) = system::System::named("parent", pd.clone(), pd);
 
-    handle
+    let mut ingest = Ingest{last: Vec::new()};
+    (handle, system.step(move |parent, _, msgs| ingest.apply(parent, msgs)))
 }
 
 impl entity::WriteEntity<Entity, Parent> for ParentSystem {
     fn write(&mut self, eid: Entity, value: Parent) {
-        self.send(Operation::Upsert(eid, value));
+        self.send(Operation::Upsert(eid, Bind::Parent(value)));
     }
 }
 
-impl entity::ReadEntity<Entity, Parent> for ParentSystem {
+impl entity::ReadEntity<Entity, Parent> for ParentData {
     fn read(&self, eid: &Entity) -> Option<&Parent> {
         self.child_to_parent.get(eid)
     }
@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+use entity::Entity;
+use {ParentData, Parent, ChildList};
+
+/// The order `ParentData::descendants` visits entities in
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Order {
+    /// Visit a child's descendants before its next sibling
+    DepthFirst,
+    /// Visit every entity of a level before the next level
+    BreadthFirst
+}
+
+/// Iterates over the parent, grandparent and so on of an entity
+pub struct Ancestors<'a> {
+    parent: &'a ParentData,
+    next: Option<Entity>
+}
+
+impl<'a> Iterator for Ancestors<'a> {
+    type Item = Entity;
+
+    fn next(&mut self) -> Option<Entity> {
+        let eid = if let Some(eid) = self.next {
+            eid
+        } else {
+            return None;
+        };
+
+        self.next = match self.parent.child_to_parent.get(&eid) {
+            Some(&Parent::Child(p)) => Some(p),
+            Some(&Parent::Root) | None => None
+        };
+        Some(eid)
+    }
+}
+
+/// Iterates over the children of an entity in sibling order
+pub struct Children<'a> {
+    parent: &'a ParentData,
+    front: Option<Entity>,
+    back: Option<Entity>,
+    len: usize
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = Entity;
+
+    fn next(&mut self) -> Option<Entity> {
+        if self.len == 0 {
+            return None;
+        }
+        let eid = self.front.unwrap();
+        self.front = self.parent.siblings.get(&eid).and_then(|l| l.next);
+        self.len -= 1;
+        Some(eid)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a> DoubleEndedIterator for Children<'a> {
+    fn next_back(&mut self) -> Option<Entity> {
+        if self.len == 0 {
+            return None;
+        }
+        let eid = self.back.unwrap();
+        self.back = self.parent.siblings.get(&eid).and_then(|l| l.prev);
+        self.len -= 1;
+        Some(eid)
+    }
+}
+
+impl<'a> ExactSizeIterator for Children<'a> {}
+
+/// Iterates over the children, grandchildren and so on of an entity
+pub struct Descendants<'a> {
+    parent: &'a ParentData,
+    order: Order,
+    pending: VecDeque<Entity>
+}
+
+impl<'a> Descendants<'a> {
+    fn push_children(&mut self, eid: Entity) {
+        let parent = self.parent;
+        let children = parent.children(eid);
+        match self.order {
+            Order::DepthFirst => {
+                for c in children.rev() {
+                    self.pending.push_front(c);
+                }
+            }
+            Order::BreadthFirst => {
+                for c in children {
+                    self.pending.push_back(c);
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = Entity;
+
+    fn next(&mut self) -> Option<Entity> {
+        let eid = if let Some(eid) = self.pending.pop_front() {
+            eid
+        } else {
+            return None;
+        };
+        self.push_children(eid);
+        Some(eid)
+    }
+}
+
+impl ParentData {
+    /// The children of `eid` in sibling order
+    pub fn children(&self, eid: Entity) -> Children {
+        let list = self.parent_to_children.get(&eid).map(|l| *l).unwrap_or(ChildList::default());
+        Children {
+            parent: self,
+            front: list.first,
+            back: list.last,
+            len: list.len
+        }
+    }
+
+    /// The ancestors of `eid` starting with its parent
+    pub fn ancestors(&self, eid: Entity) -> Ancestors {
+        let mut iter = Ancestors {
+            parent: self,
+            next: Some(eid)
+        };
+        iter.next();
+        iter
+    }
+
+    /// The descendants of `eid`, not including `eid`. Siblings are
+    /// always visited in sibling order.
+    pub fn descendants(&self, eid: Entity, order: Order) -> Descendants {
+        let mut iter = Descendants {
+            parent: self,
+            order: order,
+            pending: VecDeque::new()
+        };
+        iter.push_children(eid);
+        iter
+    }
+
+    /// The number of ancestors `eid` has, None if `eid` has no parent
+    pub fn depth(&self, eid: Entity) -> Option<usize> {
+        if self.child_to_parent.contains_key(&eid) {
+            Some(self.ancestors(eid).count())
+        } else {
+            None
+        }
+    }
+
+    /// The oldest ancestor of `eid`, or `eid` if it is a root. None if
+    /// `eid` has no parent
+    pub fn root_of(&self, eid: Entity) -> Option<Entity> {
+        if self.child_to_parent.contains_key(&eid) {
+            Some(self.ancestors(eid).last().unwrap_or(eid))
+        } else {
+            None
+        }
+    }
+}
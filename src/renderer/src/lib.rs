@@ -11,6 +11,7 @@ extern crate shared_future;
 extern crate system;
 extern crate config;
 extern crate name;
+extern crate spatial;
 
 #[macro_use]
 extern crate gfx;
@@ -34,8 +35,10 @@ extern crate image;
 extern crate vr;
 
 mod render_data;
+mod picking;
+mod origin;
 
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use transform::TransformSystem;
 use graphics::{
     Graphics, Texture, Geometry,
@@ -56,12 +59,14 @@ use gfx_pipeline::{Material, Transparency, forward, Pipeline};
 use gfx::device::Resources;
 use image::GenericImage;
 use gfx_mesh::IntoMesh;
-use cgmath::{Transform, AffineMatrix3, Matrix4, Aabb3};
+use cgmath::{AffineMatrix3, Matrix4, Vector3, Aabb3};
 
 #[cfg(feature="virtual_reality")]
 use cgmath::Matrix;
 
-pub use render_data::{DrawBinding, Camera, Primary, DebugText, Renderer};
+pub use render_data::{DrawBinding, Camera, Primary, DebugText, Renderer, RenderData, renderer, renderer_step};
+pub use picking::{Hit, cursor_ray, pick};
+pub use origin::{move_origin, rebase, rebase_point, camera_frustum};
 
 struct GeometrySlice<R: Resources> {
     mesh: Mesh<R>,
@@ -71,11 +76,13 @@ struct GeometrySlice<R: Resources> {
 struct Globals {
     config_show_aabb: Entity,
     config_show_profile: Entity,
+    config_origin_distance: Entity,
 
     graphics: graphics::Graphics,
     transform: TransformSystem,
     scenes: SceneSystem,    
     bounding: bounding::Bounding,
+    spatial: spatial::Spatial,
     render: Renderer,
     config: config::ConfigSystem,
     name: name::NameSystem,
@@ -96,8 +103,14 @@ pub struct RendererSystem<R: Resources, C: gfx::CommandBuffer<R>, D: gfx::Device
 
     // Tempoary holding place for the input data
     globals: Option<Globals>,
+
+    // Every transform is made relative to this point before rendering
+    origin: Vector3<f64>,
     gfx_data: Option<GfxData<R>>,
 
+    // The world positions of the pipeline's lights
+    lights: Vec<cgmath::Vector4<f32>>,
+
     pipeline: Option<forward::Pipeline<R>>,
 
     // debug
@@ -159,6 +172,7 @@ impl<R: gfx::Resources, M> gfx_scene::Entity<R, M> for MaterializedEntity<R, M>
 
 struct RenderContext<R: Resources>{
     scene: Scene,
+    origin: Vector3<f64>,
     local: GfxData<R>,
     globals: Globals
 }
@@ -182,9 +196,15 @@ impl<R> AbstractScene<R> for RenderContext<R>
     
     {   
         let mut culler = Frustum::new();
-        let empty = HashSet::new();
-        let drawlist = self.globals.scenes.scene_entities(self.scene)
-                                          .unwrap_or_else(|| &empty);
+
+        // In VR this is called once for each eye with the eye's camera,
+        // so each eye is culled with its own frustum
+        let frustum = camera_frustum(&camera.transform.mat, &camera.projection, self.origin);
+        let drawlist = self.globals.spatial.visible(self.scene,
+                                                    &self.globals.scenes,
+                                                    &self.globals.bounding.world,
+                                                    frustum.as_ref());
+
         let items: Vec<MaterializedEntity<R, Material<R>>> =
             drawlist.iter()
                     .filter_map(|eid| self.globals.render.binding.get(eid).map(|x| (eid, x)))
@@ -192,11 +212,13 @@ impl<R> AbstractScene<R> for RenderContext<R>
 
             match (self.local.geometry_slice.get(&draw.0),
                    self.local.materials.get(&(draw.1)),
-                   self.globals.transform.world(*eid)) {
-                (Some(a), Some(b), Some(c)) => {
+                   self.globals.transform.world(*eid),
+                   self.globals.transform.position(*eid),
+                   self.globals.bounding.aabb.get(&draw.0)) {
+                (Some(a), Some(b), Some(c), Some(p), Some(aabb)) => {
                     Some(MaterializedEntity{
-                        aabb: self.globals.bounding.aabb[&draw.0],
-                        transform: AffineMatrix3{mat: c.0.into()},
+                        aabb: *aabb,
+                        transform: AffineMatrix3{mat: rebase(c.0, p, self.origin)},
                         mesh: a.mesh.clone(),
                         fragments: [gfx_scene::Fragment{
                             material: b.clone(),
@@ -221,19 +243,63 @@ impl<R> AbstractScene<R> for RenderContext<R>
     }
 }
 
+/// Returns true if the profile overlay is turned on by `show_profile`
+fn show_profile(globals: &Globals) -> bool {
+    match globals.config.read(&globals.config_show_profile) {
+        Some(&config::Config::Bool(en)) => en,
+        _ => false
+    }
+}
+
+/// Turn the recording of the system timeline on or off to match
+/// `show_profile`, the overlay has nothing to show without it
+fn record_profile(globals: &Globals) {
+    system::profile::timeline().enable(show_profile(globals));
+}
+
+/// Create the text for the profile overlay
+fn profile_overlay(globals: &Globals) -> Vec<DebugText> {
+    if !show_profile(globals) {
+        return Vec::new();
+    }
+
+    system::profile::timeline().latest()
+        .iter()
+        .enumerate()
+        .map(|(i, span)| {
+            DebugText {
+                text: format!("{:12} {:8.3}ms wait {:8.3}ms {:6} msgs thread {}",
+                    span.name,
+                    span.work() * 1000.,
+                    span.wait() * 1000.,
+                    span.messages,
+                    span.thread
+                ),
+                start: [400, 10 + 15 * i as i32],
+                color: [1., 1., 0., 1.]
+            }
+        })
+        .collect()
+}
+
 impl<F> RendererSystem<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer<gfx_device_gl::Resources>, Device, F>
     where F: gfx::Factory<gfx_device_gl::Resources>+Clone
 
 {
     #[cfg(feature="virtual_reality")]
-    pub fn new(sched: &mut Schedule,
+    /// Create the renderer, `bounding` must be created `with_world`
+    /// from `render` and followed by `spatial` as the entities are
+    /// culled by their world bounds
+    pub fn new(_: &mut Schedule,
                graphics: Graphics,
                transform: TransformSystem,
                scenes: SceneSystem,
                bounding: bounding::Bounding,
+               spatial: spatial::Spatial,
+               render: Renderer,
                mut name: name::NameSystem,
                mut config: config::ConfigSystem,
-               ra: engine::RenderArgs<Device, F>) -> (Renderer, RendererSystem<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer<gfx_device_gl::Resources>, Device, F>) {
+               ra: engine::RenderArgs<Device, F>) -> RendererSystem<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer<gfx_device_gl::Resources>, Device, F> {
 
         use gfx::tex::WrapMode::Tile;
 
@@ -278,20 +344,25 @@ impl<F> RendererSystem<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer<gf
             .write(&mut name);
         config_show_profile.bind(config::Config::Bool(false)).write(&mut config);
 
+        let config_origin_distance = Entity::new()
+            .bind(name::Name::new("origin_distance".to_string()).unwrap())
+            .write(&mut name);
+        config_origin_distance.bind(config::Config::Float(origin::ORIGIN_DISTANCE)).write(&mut config);
+
         config_show_aabb.bind(config::Config::Bool(false)).write(&mut config);
 
         let gfx_vr = vr.as_ref().map(|vr| gfx_vr::Render::new(&mut factory, vr));
 
-        let render = render_data::renderer(sched);
-
         let globals = Globals{
             config_show_aabb: config_show_aabb,
             config_show_profile: config_show_profile,
+            config_origin_distance: config_origin_distance,
             transform: transform,
             graphics: graphics,
             scenes: scenes,
             bounding: bounding,
-            render: render.clone(),
+            spatial: spatial,
+            render: render,
             config: config,
             name: name
         };
@@ -305,28 +376,34 @@ impl<F> RendererSystem<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer<gf
             aabb_debug: aabb_debug,
         };
 
-        (render.clone(),
-         RendererSystem {
+        RendererSystem {
             globals: Some(globals),
+            origin: Vector3::new(0., 0., 0.),
             gfx_data: Some(gfx_data),
+            lights: pipeline.phase.technique.lights.iter().map(|l| l.position).collect(),
             device: device,
             factory: factory,
             pipeline: Some(pipeline),
             text: text,
             ivr: vr,
             gvr: gfx_vr,
-        })
+        }
     }
 
     #[cfg(not(feature="virtual_reality"))]
-    pub fn new(sched: &mut Schedule,
+    /// Create the renderer, `bounding` must be created `with_world`
+    /// from `render` and followed by `spatial` as the entities are
+    /// culled by their world bounds
+    pub fn new(_: &mut Schedule,
                graphics: Graphics,
                transform: TransformSystem,
                scenes: SceneSystem,
                bounding: bounding::Bounding,
+               spatial: spatial::Spatial,
+               render: Renderer,
                mut name: name::NameSystem,
                mut config: config::ConfigSystem,
-               ra: engine::RenderArgs<Device, F>) -> (Renderer, RendererSystem<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer<gfx_device_gl::Resources>, Device, F>) {
+               ra: engine::RenderArgs<Device, F>) -> RendererSystem<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer<gfx_device_gl::Resources>, Device, F> {
 
         use gfx::tex::WrapMode::Tile;
         let (device, mut factory) = (ra.device, ra.factory);
@@ -370,16 +447,21 @@ impl<F> RendererSystem<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer<gf
             .write(&mut name);
         config_show_profile.bind(config::Config::Bool(false)).write(&mut config);
 
-        let render = render_data::renderer(sched);
+        let config_origin_distance = Entity::new()
+            .bind(name::Name::new("origin_distance".to_string()).unwrap())
+            .write(&mut name);
+        config_origin_distance.bind(config::Config::Float(origin::ORIGIN_DISTANCE)).write(&mut config);
 
         let globals = Globals{
             config_show_aabb: config_show_aabb,
             config_show_profile: config_show_profile,
+            config_origin_distance: config_origin_distance,
             transform: transform,
             graphics: graphics,
             scenes: scenes,
             bounding: bounding,
-            render: render.clone(),
+            spatial: spatial,
+            render: render,
             config: config,
             name: name
         };
@@ -393,16 +475,17 @@ impl<F> RendererSystem<gfx_device_gl::Resources, gfx_device_gl::CommandBuffer<gf
             aabb_debug: aabb_debug,
         };
 
-        (render,
-         RendererSystem {
+        RendererSystem {
             globals: Some(globals),
+            origin: Vector3::new(0., 0., 0.),
             gfx_data: Some(gfx_data),
+            lights: pipeline.phase.technique.lights.iter().map(|l| l.position).collect(),
             device: device,
             factory: factory,
             pipeline: Some(pipeline),
             text: text,
             phantom: std::marker::PhantomData
-        })
+        }
     }
 
 }
@@ -589,16 +672,21 @@ impl<R: Resources> GfxData<R> {
         drop(_g);        
     }
 
+    // The renderer is not a step of a `Graph`, it runs on the thread
+    // that owns the window. It waits for the frames the systems publish,
+    // whether they run as tasks or are stepped by a `FrameScheduler`.
     fn sync<F>(&mut self, globals: Globals, factory: &mut F) -> Globals
         where F: Factory<R>
     {
         let Globals{
             config_show_aabb,
             config_show_profile,
+            config_origin_distance,
             mut graphics,
             scenes,
             transform,
             mut bounding,
+            mut spatial,
             render,
             config,
             name
@@ -640,17 +728,25 @@ impl<R: Resources> GfxData<R> {
         let name = name.get().unwrap();
         drop(_g);
 
-        Globals {
+        let _g = hprof::enter("spatial-fetch");
+        spatial.next_frame();
+        drop(_g);
+
+        let globals = Globals {
             config_show_aabb: config_show_aabb,
             config_show_profile: config_show_profile,
+            config_origin_distance: config_origin_distance,
             graphics: graphics,
             scenes: scenes,
             transform: transform,
             bounding: bounding,
+            spatial: spatial,
             render: render,
             name: name,
             config: config
-        }
+        };
+        record_profile(&globals);
+        globals
     }
 
 }
@@ -663,86 +759,68 @@ impl<R, C, D, F> RendererSystem<R, C, D, F>
           F: gfx::Factory<R>+Clone
 
 {
-    #[cfg(feature="virtual_reality")]
-    pub fn draw(&mut self, _: &mut Schedule, window: &mut Window<D, R>) {
-        let mut globals = self.globals.take().unwrap();
-        let mut gfx_data = self.gfx_data.take().unwrap();
-
-        hprof::start_frame();
-        let _g = hprof::enter("sync");
-        globals = gfx_data.sync(globals, &mut self.factory);
-        drop(_g);
-
-        let camera = if let Some(cid) = globals.render.primary {
-            if let Some(c) = globals.render.cameras.get(&cid) {
-                Some((MaterializedCamera {
-                    projection: c.0.clone().into(),
-                    transform: globals.transform
-                                      .world(cid)
-                                      .map(|&x| AffineMatrix3{mat: x.0.into()})
-                                      .unwrap_or_else(|| AffineMatrix3::identity())
-                }, c.1))
-            } else {
-                None
-            }
-        } else {
-            None
+    /// Find the primary camera, the origin is moved to it if it is too
+    /// far away. The camera and the lights are made relative to the origin.
+    fn camera(&mut self, globals: &Globals) -> Option<(MaterializedCamera, Scene)> {
+        let cid = if let Some(cid) = globals.render.primary { cid } else { return None; };
+        let c = if let Some(c) = globals.render.cameras.get(&cid) { c } else { return None; };
+
+        let world = globals.transform.world(cid).map(|x| x.0).unwrap_or(Matrix4::identity());
+        let position = globals.transform.position(cid).unwrap_or(Vector3::new(0., 0., 0.));
+
+        let distance = match globals.config.read(&globals.config_origin_distance) {
+            Some(&config::Config::Float(d)) => d,
+            _ => origin::ORIGIN_DISTANCE
         };
+        move_origin(&mut self.origin, position, distance);
 
+        let pipeline = self.pipeline.as_mut().unwrap();
+        for (light, &pos) in pipeline.phase.technique.lights.iter_mut().zip(self.lights.iter()) {
+            light.position = rebase_point(pos, self.origin);
+        }
 
-        if let Some((mut camera, scene)) = camera {
-            let rc = RenderContext{
-                scene: scene,
-                local: gfx_data,
-                globals: globals
-            };
-
-            let mut pipeline = self.pipeline.take().unwrap();
-            let ivr = self.ivr.take();
-            let mut gvr = self.gvr.take();
-
-            match (&ivr, &mut gvr) {
-                (&Some(ref ivr), &mut Some(ref mut gvr)) => {
-                    let old = camera.transform.mat;
-                    gvr.render_into(&ivr, |s, p, v| {
-                        camera.projection = p;
-                        camera.transform.mat = old.mul_m(&v.invert().unwrap());
-                        pipeline.render(&rc, &camera, s).unwrap();
-                    });
-                    gvr.render_frame(&ivr, &mut self.device, window);
-                }
-                _ => {
-                    pipeline.render(&rc, &camera, window).unwrap();
-                }
-            }
-            self.pipeline = Some(pipeline);
-            self.ivr = ivr;
-            self.gvr = gvr;
+        Some((MaterializedCamera {
+            projection: c.0.clone().into(),
+            transform: AffineMatrix3{mat: rebase(world, position, self.origin)}
+        }, c.1))
+    }
 
-            for (_, text) in rc.globals.render.debug_text.iter() {
-                self.text.add(
-                    &text.text, text.start, text.color
-                );
+    #[cfg(feature="virtual_reality")]
+    fn render(&mut self,
+              pipeline: &mut forward::Pipeline<R>,
+              rc: &RenderContext<R>,
+              mut camera: MaterializedCamera,
+              window: &mut Window<D, R>) {
+        let ivr = self.ivr.take();
+        let mut gvr = self.gvr.take();
+
+        match (&ivr, &mut gvr) {
+            (&Some(ref ivr), &mut Some(ref mut gvr)) => {
+                let old = camera.transform.mat;
+                gvr.render_into(&ivr, |s, p, v| {
+                    camera.projection = p;
+                    camera.transform.mat = old.mul_m(&v.invert().unwrap());
+                    pipeline.render(rc, &camera, s).unwrap();
+                });
+                gvr.render_frame(&ivr, &mut self.device, window);
             }
-            self.text.draw(window).unwrap();
-            let _g = hprof::enter("present");
-            window.present(&mut self.device);
-            drop(_g);
-            hprof::end_frame();
-
-            if let Some(&config::Config::Bool(en)) = rc.globals.config.read(&rc.globals.config_show_profile) {
-                if en { hprof::profiler().print_timing() };
+            _ => {
+                pipeline.render(rc, &camera, window).unwrap();
             }
-            
-            self.globals = Some(rc.globals);
-            self.gfx_data = Some(rc.local);
-        } else {
-            self.globals = Some(globals);
-            self.gfx_data = Some(gfx_data);   
         }
+        self.ivr = ivr;
+        self.gvr = gvr;
     }
 
     #[cfg(not(feature="virtual_reality"))]
+    fn render(&mut self,
+              pipeline: &mut forward::Pipeline<R>,
+              rc: &RenderContext<R>,
+              camera: MaterializedCamera,
+              window: &mut Window<D, R>) {
+        pipeline.render(rc, &camera, window).unwrap();
+    }
+
     pub fn draw(&mut self, _: &mut Schedule, window: &mut Window<D, R>) {
         let mut globals = self.globals.take().unwrap();
         let mut gfx_data = self.gfx_data.take().unwrap();
@@ -752,31 +830,16 @@ impl<R, C, D, F> RendererSystem<R, C, D, F>
         globals = gfx_data.sync(globals, &mut self.factory);
         drop(_g);
 
-        let camera = if let Some(cid) = globals.render.primary {
-            if let Some(c) = globals.render.cameras.get(&cid) {
-                Some((MaterializedCamera {
-                    projection: c.0.clone().into(),
-                    transform: globals.transform
-                                      .world(cid)
-                                      .map(|&x| AffineMatrix3{mat: x.0.into()})
-                                      .unwrap_or_else(|| AffineMatrix3::identity())
-                }, c.1))
-            } else {
-                None
-            }
-        } else {
-            None
-        };
-
-        if let Some((camera, scene)) = camera {
+        if let Some((camera, scene)) = self.camera(&globals) {
             let rc = RenderContext{
                 scene: scene,
+                origin: self.origin,
                 local: gfx_data,
                 globals: globals
             };
 
             let mut pipeline = self.pipeline.take().unwrap();
-            pipeline.render(&rc, &camera, window).unwrap();
+            self.render(&mut pipeline, &rc, camera, window);
             self.pipeline = Some(pipeline);
 
             for (_, text) in rc.globals.render.debug_text.iter() {
@@ -784,6 +847,11 @@ impl<R, C, D, F> RendererSystem<R, C, D, F>
                     &text.text, text.start, text.color
                 );
             }
+            for text in profile_overlay(&rc.globals).iter() {
+                self.text.add(
+                    &text.text, text.start, text.color
+                );
+            }
             self.text.draw(window).unwrap();
             let _g = hprof::enter("present");
             window.present(&mut self.device);
@@ -798,10 +866,7 @@ impl<R, C, D, F> RendererSystem<R, C, D, F>
             self.gfx_data = Some(rc.local);
         } else {
             self.globals = Some(globals);
-            self.gfx_data = Some(gfx_data);   
+            self.gfx_data = Some(gfx_data);
         }
-
     }
 }
-
-
@@ -0,0 +1,56 @@
+use cgmath::{Matrix, Matrix4, Vector3, Vector4};
+use spatial::Frustum;
+
+/// The default for the `origin_distance` config
+pub const ORIGIN_DISTANCE: f64 = 1000.;
+
+/// Move the origin to the camera once the camera is further then
+/// `distance` from it, returns true if the origin was moved.
+/// Rendering relative to the origin keeps the transforms small enough
+/// that objects far from the world's origin do not jitter.
+pub fn move_origin(origin: &mut Vector3<f64>, camera: Vector3<f64>, distance: f64) -> bool {
+    let offset = Vector3::new(camera.x - origin.x,
+                              camera.y - origin.y,
+                              camera.z - origin.z);
+    if offset.x * offset.x + offset.y * offset.y + offset.z * offset.z > distance * distance {
+        *origin = camera;
+        true
+    } else {
+        false
+    }
+}
+
+/// Make an affine world transform relative to the origin. `position`
+/// is the translation of `mat` in double precision, the difference is
+/// taken before it is rounded so nothing is lost far from the origin.
+pub fn rebase(mat: Matrix4<f32>, position: Vector3<f64>, origin: Vector3<f64>) -> Matrix4<f32> {
+    let mut mat = mat;
+    mat.w.x = (position.x - origin.x) as f32;
+    mat.w.y = (position.y - origin.y) as f32;
+    mat.w.z = (position.z - origin.z) as f32;
+    mat
+}
+
+/// Make a point in world space relative to the origin, a
+/// direction (a `w` of 0) is not moved
+pub fn rebase_point(p: Vector4<f32>, origin: Vector3<f64>) -> Vector4<f32> {
+    let w = p.w as f64;
+    Vector4::new((p.x as f64 - origin.x * w) as f32,
+                 (p.y as f64 - origin.y * w) as f32,
+                 (p.z as f64 - origin.z * w) as f32,
+                 p.w)
+}
+
+/// Find the world space frustum of a camera, `transform` is the world
+/// transform of the camera relative to the origin. Returns None if the
+/// camera cannot be inverted.
+pub fn camera_frustum(transform: &Matrix4<f32>,
+                      projection: &Matrix4<f32>,
+                      origin: Vector3<f64>) -> Option<Frustum> {
+    // the camera is relative to the origin but the index is not
+    let mut world = *transform;
+    world.w.x += origin.x as f32 * world.w.w;
+    world.w.y += origin.y as f32 * world.w.w;
+    world.w.z += origin.z as f32 * world.w.w;
+    world.invert().map(|view| Frustum::from_matrix(&projection.mul_m(&view)))
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use cgmath::{Matrix, Matrix4, Point3, Vector3, Vector4};
+use entity::Entity;
+use graphics::{GraphicsStore, Primative};
+use scene::Scene;
+use transform::TransformData;
+use bounding;
+use spatial::{Ray, SpatialIndex};
+use render_data::{Camera, RenderData};
+
+/// The entity that a ray hit
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Hit {
+    pub entity: Entity,
+    /// How far along the ray the hit was
+    pub distance: f32,
+    /// Where the ray hit in world space
+    pub point: Point3<f32>
+}
+
+/// Find the ray from the camera through a cursor position. The cursor
+/// is in pixels from the top left of a window that is `size` pixels,
+/// `world` is the world transform of the camera. The ray's direction
+/// is one unit long. Returns None if the camera cannot be inverted
+/// or the window has no area.
+pub fn cursor_ray(camera: &Camera,
+                  world: &Matrix4<f32>,
+                  cursor: (f64, f64),
+                  size: (u32, u32)) -> Option<Ray> {
+    if size.0 == 0 || size.1 == 0 {
+        return None;
+    }
+
+    let projection: Matrix4<f32> = camera.0.clone().into();
+    let inverse = if let Some(view) = world.invert() {
+        projection.mul_m(&view).invert()
+    } else {
+        None
+    };
+    let inverse = if let Some(i) = inverse { i } else { return None; };
+
+    let x = (2. * cursor.0 / size.0 as f64 - 1.) as f32;
+    let y = (1. - 2. * cursor.1 / size.1 as f64) as f32;
+    let unproject = |z: f32| {
+        let p = inverse.mul_v(&Vector4::new(x, y, z, 1.));
+        Point3::new(p.x / p.w, p.y / p.w, p.z / p.w)
+    };
+
+    let (near, far) = (unproject(-1.), unproject(1.));
+    let dir = Vector3::new(far.x - near.x, far.y - near.y, far.z - near.z);
+    let len = (dir.x * dir.x + dir.y * dir.y + dir.z * dir.z).sqrt();
+    Some(Ray::new(near, Vector3::new(dir.x / len, dir.y / len, dir.z / len)))
+}
+
+/// Find the closest entity of the scene that is hit by the ray. The
+/// entities whose bounds are hit are found from the scene's tree in
+/// `index`, if `graphics` is supplied the triangles of each entity are
+/// tested too. `index` must be from the same frame as the other data.
+pub fn pick(ray: &Ray,
+            scene: Scene,
+            index: &SpatialIndex,
+            render: &RenderData,
+            transform: &TransformData,
+            graphics: Option<&GraphicsStore>) -> Option<Hit> {
+
+    let candidates = if let Some(bvh) = index.scene(scene) {
+        bvh.query_ray(ray)
+    } else {
+        return None;
+    };
+
+    let mut positions = HashMap::new();
+    let mut best: Option<(Entity, f32)> = None;
+    for (eid, d) in candidates {
+        // nothing further away can be closer then the best hit
+        if best.map(|b| b.1 <= d).unwrap_or(false) {
+            break;
+        }
+
+        let distance = match graphics {
+            Some(g) => triangle_hit(ray, eid, d, g, render, transform, &mut positions),
+            None => Some(d)
+        };
+        if let Some(distance) = distance {
+            if best.map(|b| distance < b.1).unwrap_or(true) {
+                best = Some((eid, distance));
+            }
+        }
+    }
+
+    best.map(|(eid, distance)| {
+        Hit {
+            entity: eid,
+            distance: distance,
+            point: ray.at(distance)
+        }
+    })
+}
+
+// Test the ray against the triangles of the entity's geometry. If the
+// geometry is not made of triangles, or its vertices can't be read,
+// the distance to its bounds is used.
+fn triangle_hit(ray: &Ray,
+                eid: Entity,
+                bounds: f32,
+                graphics: &GraphicsStore,
+                render: &RenderData,
+                transform: &TransformData,
+                positions: &mut HashMap<Entity, Option<Vec<[f32; 3]>>>) -> Option<f32> {
+
+    let geo = render.binding.get(&eid).and_then(|d| graphics.geometry.get(&d.0));
+    let (geo, world) = match (geo, transform.world(eid)) {
+        (Some(g), Some(w)) => (g, w.0),
+        _ => return None
+    };
+    if let Primative::Triangle = geo.primative {} else {
+        return Some(bounds);
+    }
+    let vb = if let Some(vb) = graphics.vertex_buffer.get(&geo.buffer.parent) {
+        vb
+    } else {
+        return Some(bounds);
+    };
+    let pos = positions.entry(geo.buffer.parent)
+                       .or_insert_with(|| bounding::positions(vb).ok());
+    let pos = if let Some(ref p) = *pos { p } else { return Some(bounds); };
+
+    // the distances along the ray are the same in the entity's space
+    let inverse = if let Some(i) = world.invert() { i } else { return None; };
+    let o = inverse.mul_v(&Vector4::new(ray.origin.x, ray.origin.y, ray.origin.z, 1.));
+    let d = inverse.mul_v(&Vector4::new(ray.direction.x, ray.direction.y, ray.direction.z, 0.));
+    let local = Ray::new(Point3::new(o.x / o.w, o.y / o.w, o.z / o.w), Vector3::new(d.x, d.y, d.z));
+
+    let start = geo.buffer.start as usize;
+    let end = start + geo.buffer.length as usize;
+    let index = |i: usize| -> Option<usize> {
+        let i = match vb.index {
+            Some(ref index) => index.get(i).map(|&x| x as usize),
+            None => Some(i)
+        };
+        i.and_then(|i| if i < pos.len() { Some(i) } else { None })
+    };
+    let point = |i: usize| Point3::new(pos[i][0], pos[i][1], pos[i][2]);
+
+    let mut best: Option<f32> = None;
+    let mut i = start;
+    while i + 3 <= end {
+        if let (Some(a), Some(b), Some(c)) = (index(i), index(i + 1), index(i + 2)) {
+            if let Some(t) = local.intersect_triangle(point(a), point(b), point(c)) {
+                if best.map(|b| t < b).unwrap_or(true) {
+                    best = Some(t);
+                }
+            }
+        }
+        i += 3;
+    }
+    best
+}
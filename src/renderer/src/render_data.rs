@@ -1,12 +1,13 @@
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use cgmath;
 use graphics::{Geometry, Material};
 use scene::Scene;
 use entity::{self, Entity, Operation};
 use snowstorm::mpsc::*;
 use system;
-use engine::fibe::{self, task};
+use bounding::GeometryBinding;
+use engine::fibe;
 
 
 /// This holds the binding between a geometry and the material
@@ -63,19 +64,19 @@ impl entity::WriteEntity<Entity, DebugText> for Renderer {
     }
 }
 
-impl entity::ReadEntity<Entity, DrawBinding> for Renderer {
+impl entity::ReadEntity<Entity, DrawBinding> for RenderData {
     fn read(&self, eid: &Entity) -> Option<&DrawBinding> {
         self.binding.get(eid)
     }
 }
 
-impl entity::ReadEntity<Entity, Camera> for Renderer {
+impl entity::ReadEntity<Entity, Camera> for RenderData {
     fn read(&self, eid: &Entity) -> Option<&Camera> {
         self.cameras.get(eid)
     }
 }
 
-impl entity::ReadEntity<Entity, DebugText> for Renderer {
+impl entity::ReadEntity<Entity, DebugText> for RenderData {
     fn read(&self, eid: &Entity) -> Option<&DebugText> {
         self.debug_text.get(eid)
     }
@@ -86,6 +87,8 @@ pub struct RenderData {
     pub cameras: HashMap<Entity, Camera>,
     pub debug_text: HashMap<Entity, DebugText>,
     pub binding: HashMap<Entity, DrawBinding>,
+    /// The entities whose `DrawBinding` was written or deleted this frame
+    pub binding_updated: HashSet<Entity>,
     pub primary: Option<Entity>,
 }
 
@@ -94,11 +97,21 @@ impl RenderData {
         RenderData {
             cameras: HashMap::new(),
             binding: HashMap::new(),
+            binding_updated: HashSet::new(),
             debug_text: HashMap::new(),
             primary: None
         }
     }
 
+    fn mark_updated(&mut self, msgs: &[Message]) {
+        self.binding_updated.clear();
+        for m in msgs.iter() {
+            if let &Message::Binding(ref op) = m {
+                self.binding_updated.insert(*op.key());
+            }
+        }
+    }
+
     fn apply_ingest(&mut self, msgs: &[Message]) {
         for m in msgs.iter() {
             match m {
@@ -133,35 +146,45 @@ impl RenderData {
     }
 }
 
-// Reads from the parent channel
-fn sync_ingest(ingest: &mut system::channel::Receiver<Message>) -> Vec<Message> {
-    let mut msgs: Vec<Message> = Vec::new();
-    while let Ok(op) = ingest.recv() {
-        msgs.push(op.clone());
+impl GeometryBinding for RenderData {
+    fn geometry(&self, eid: Entity) -> Option<Geometry> {
+        self.binding.get(&eid).map(|b| b.0)
+    }
+
+    fn rebound(&self) -> &HashSet<Entity> {
+        &self.binding_updated
     }
-    msgs
 }
 
+/// The `renderer` system holds what the renderer draws, it is
+/// created before the `RendererSystem` so the bounding system
+/// can follow it
 pub fn renderer(sched: &mut fibe::Schedule) -> Renderer {
     let rd = RenderData::new();
-    let (mut system, handle) = system::System::new(rd.clone(), rd);
+    let (system, handle) = system::System::named("render_data", rd.clone(), rd);
 
     let mut limsgs = Vec::new();
+    system.run(sched, move |scene, _, imsgs| update(scene, &mut limsgs, imsgs));
 
-    task(move |_| {
-        loop {
-            let s = system.update(|mut scene, _, mut msgs| {
-                let imsgs = sync_ingest(&mut msgs);
+    handle
+}
 
-                scene.apply_ingest(&limsgs[..]);
-                scene.apply_ingest(&imsgs[..]);
+/// Create the `renderer` system without starting it, the step
+/// does not depend on any other system
+pub fn renderer_step() -> (Renderer, system::schedule::Step) {
+    let rd = RenderData::new();
+    let (system, handle) = system::System::named("render_data", rd.clone(), rd);
 
-                limsgs = imsgs;
-                scene
-            });
-            system = if let Some(s) = s { s } else { return; };
-        }
-    }).start(sched);
+    let mut limsgs = Vec::new();
+    (handle, system.step(move |scene, _, imsgs| update(scene, &mut limsgs, imsgs)))
+}
 
-    handle
+// the back buffer is a frame behind, the last frame is replayed
+fn update(mut scene: RenderData, limsgs: &mut Vec<Message>, imsgs: Vec<Message>) -> RenderData {
+    scene.apply_ingest(&limsgs[..]);
+    scene.apply_ingest(&imsgs[..]);
+    scene.mark_updated(&imsgs[..]);
+
+    *limsgs = imsgs;
+    scene
 }
@@ -7,12 +7,14 @@ extern crate system;
 
 use std::collections::{HashSet, HashMap};
 use entity::{Entity, DeleteEntity};
-use engine::fibe::{task, Schedule};
-use parent::ParentSystem;
+use engine::fibe::Schedule;
+use parent::{ParentData, ParentSystem, Order};
 
 /// This holds an abstract of a scene
 ///     A scene may have 0-N children. The children are `bound` to it.
 ///     An entity may live in more then one scene.
+///     The descendants of a bound entity are also in the scene.
+///     A scene may include every entity of another scene.
 ///
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
 pub struct Scene(pub Entity);
@@ -23,39 +25,137 @@ pub enum Message {
     Bind(Scene, Entity),
     /// Child was removed form a scene
     Unbind(Scene, Entity),
+    /// Every entity of the second scene is in the first
+    Include(Scene, Scene),
+    /// Undo an `Include`
+    Exclude(Scene, Scene),
+    /// Turn a scene on or off, a disabled scene has no entities
+    Enable(Scene, bool),
+    /// Only entities with one of these layers are in the scene
+    Mask(Scene, u32),
+    /// Set the layers of an entity
+    Layers(Entity, Layers),
+}
+
+/// The layers an entity is on, each bit is a layer. An entity
+/// is on the first layer unless it was given other layers.
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Layers(pub u32);
+
+impl Default for Layers {
+    fn default() -> Layers { Layers(1) }
 }
 
 #[derive(Clone, Debug)]
 pub struct SceneData {
     parent_mesages: Vec<parent::Message>,
 
-    // entity is a member of x scenes
-    belongs_to: HashMap<Entity, HashSet<Entity>>,
+    // entity was bound to x scenes
+    bound_to: HashMap<Entity, HashSet<Entity>>,
 
     // entity has x in its scene
     contains: HashMap<Entity, HashSet<Entity>>,
-}
 
-// Reads from the parent channel
-fn sync_ingest(ingest: &mut system::channel::Receiver<Message>) -> Vec<Message> {
-    let mut msgs: Vec<Message> = Vec::new();
-    while let Ok(op) = ingest.recv() {
-        msgs.push(*op);
-    }
-    msgs
+    // scene includes the entities of x scenes
+    includes: HashMap<Entity, HashSet<Entity>>,
+
+    // scene is included by x scenes
+    included_by: HashMap<Entity, HashSet<Entity>>,
+
+    disabled: HashSet<Entity>,
+    masks: HashMap<Entity, u32>,
+    layers: HashMap<Entity, Layers>,
+
+    // every entity in the scene, from binds, parents and includes
+    members: HashMap<Entity, HashSet<Entity>>,
+
+    // entity is a member of x scenes, the reverse of `members`
+    member_of: HashMap<Entity, HashSet<Entity>>,
+
+    // the entities that joined or left a scene this frame, these
+    // are the changes to `members` so they also bring the other
+    // buffer up to date
+    added: HashMap<Entity, HashSet<Entity>>,
+    removed: HashMap<Entity, HashSet<Entity>>,
 }
 
 impl SceneData {
-    /// Get the entitires for a supplied scene
+    /// Get the entitires for a supplied scene, this includes the
+    /// descendants of bound entities and the entities of included
+    /// scenes. A disabled scene has no entities.
     pub fn scene_entities(&self, scene: Scene) -> Option<&HashSet<Entity>> {
+        self.members.get(&scene.0)
+    }
+
+    /// Get the scenes that the entity is in, this includes the scenes
+    /// it is in through its ancestors and through includes
+    pub fn belongs_to(&self, eid: Entity) -> Option<&HashSet<Entity>> {
+        self.member_of.get(&eid)
+    }
+
+    /// Get the entities that joined the scene this frame
+    pub fn added(&self, scene: Scene) -> Option<&HashSet<Entity>> {
+        self.added.get(&scene.0)
+    }
+
+    /// Get the entities that left the scene this frame, this includes
+    /// entities that were deleted and every entity of a deleted scene
+    pub fn removed(&self, scene: Scene) -> Option<&HashSet<Entity>> {
+        self.removed.get(&scene.0)
+    }
+
+    /// Get the scenes that had an entity join or leave this frame
+    pub fn changed_scenes(&self) -> HashSet<Scene> {
+        self.added.keys()
+            .chain(self.removed.keys())
+            .map(|&s| Scene(s))
+            .collect()
+    }
+
+    /// Get the entities that were bound directly to the scene
+    pub fn bound_entities(&self, scene: Scene) -> Option<&HashSet<Entity>> {
         self.contains.get(&scene.0)
     }
 
+    /// Get the scenes that were included by the scene
+    pub fn included_scenes(&self, scene: Scene) -> Option<&HashSet<Entity>> {
+        self.includes.get(&scene.0)
+    }
+
+    /// Check if the scene is enabled, scenes are enabled by default
+    pub fn is_enabled(&self, scene: Scene) -> bool {
+        !self.disabled.contains(&scene.0)
+    }
+
+    /// Get the layer mask of the scene, this is every layer by default
+    pub fn mask(&self, scene: Scene) -> u32 {
+        self.masks.get(&scene.0).cloned().unwrap_or(!0)
+    }
+
+    /// Get the layers of the entity. An entity that was not given any
+    /// layers is on the layers of its closest ancestor that was.
+    pub fn layers(&self, eid: Entity, parent: &ParentData) -> Layers {
+        Some(eid).into_iter()
+            .chain(parent.ancestors(eid))
+            .filter_map(|e| self.layers.get(&e).cloned())
+            .next()
+            .unwrap_or(Layers::default())
+    }
+
     fn new() -> SceneData {
         SceneData {
             parent_mesages: Vec::new(),
-            belongs_to: HashMap::new(),
+            bound_to: HashMap::new(),
             contains: HashMap::new(),
+            includes: HashMap::new(),
+            included_by: HashMap::new(),
+            disabled: HashSet::new(),
+            masks: HashMap::new(),
+            layers: HashMap::new(),
+            members: HashMap::new(),
+            member_of: HashMap::new(),
+            added: HashMap::new(),
+            removed: HashMap::new(),
         }
     }
 
@@ -65,20 +165,185 @@ impl SceneData {
             // as a series of unbinds
             if let Some(children) = self.contains.remove(&eid) {
                 for cid in children.into_iter() {
-                    if let Some(belongs) = self.belongs_to.get_mut(&cid) {
-                        belongs.remove(&eid);
+                    if let Some(bound) = self.bound_to.get_mut(&cid) {
+                        bound.remove(&eid);
                     }
                 }                        
             }
 
             // remove all the bindings that the child may have been in
-            if let Some(parents) = self.belongs_to.remove(&eid) {
+            if let Some(parents) = self.bound_to.remove(&eid) {
                 for pid in parents.into_iter() {
                     if let Some(contains) = self.contains.get_mut(&pid) {
                         contains.remove(&eid);
                     }
                 }     
             }
+
+            if let Some(included) = self.includes.remove(&eid) {
+                for other in included.into_iter() {
+                    if let Some(by) = self.included_by.get_mut(&other) {
+                        by.remove(&eid);
+                    }
+                }
+            }
+            if let Some(by) = self.included_by.remove(&eid) {
+                for scene in by.into_iter() {
+                    if let Some(included) = self.includes.get_mut(&scene) {
+                        included.remove(&eid);
+                    }
+                }
+            }
+            self.disabled.remove(&eid);
+            self.masks.remove(&eid);
+            self.layers.remove(&eid);
+        }
+    }
+
+    /// Bring the memberships up to `old`, this is the frame
+    /// after the one that was last written to this buffer
+    fn catch_up(&mut self, old: &SceneData) {
+        for (&scene, added) in old.added.iter() {
+            for &eid in added.iter() {
+                insert(&mut self.members, scene, eid);
+                insert(&mut self.member_of, eid, scene);
+            }
+        }
+        for (&scene, removed) in old.removed.iter() {
+            for &eid in removed.iter() {
+                remove(&mut self.members, scene, eid);
+                remove(&mut self.member_of, eid, scene);
+            }
+        }
+    }
+
+    /// The entities that could be in a scene through `scenes`, these are
+    /// the bound entities of the scenes and of every scene they include
+    /// and the descendants of those entities.
+    fn reachable(&self, scenes: &HashSet<Entity>, parent: &ParentData) -> HashSet<Entity> {
+        let mut visited = HashSet::new();
+        let mut pending: Vec<Entity> = scenes.iter().cloned().collect();
+        let mut entities = HashSet::new();
+        while let Some(s) = pending.pop() {
+            if !visited.insert(s) {
+                continue;
+            }
+            if let Some(bound) = self.contains.get(&s) {
+                for &eid in bound.iter() {
+                    entities.insert(eid);
+                    entities.extend(parent.descendants(eid, Order::DepthFirst));
+                }
+            }
+            if let Some(included) = self.includes.get(&s) {
+                pending.extend(included.iter().cloned());
+            }
+        }
+        entities
+    }
+
+    /// The enabled scenes that get the entities of `scene`, this is
+    /// `scene` and every scene that includes it through enabled scenes
+    fn included_into(&self, scene: Entity) -> Vec<Entity> {
+        let mut visited = HashSet::new();
+        let mut pending = vec![scene];
+        while let Some(s) = pending.pop() {
+            if self.disabled.contains(&s) || !visited.insert(s) {
+                continue;
+            }
+            if let Some(by) = self.included_by.get(&s) {
+                pending.extend(by.iter().cloned());
+            }
+        }
+        visited.into_iter().collect()
+    }
+
+    /// Find the scenes that `eid` is a member of and record how
+    /// that changed since the last frame
+    fn refresh(&mut self,
+               eid: Entity,
+               parent: &ParentData,
+               cache: &mut HashMap<Entity, Vec<Entity>>) {
+        let layers = self.layers(eid, parent).0;
+
+        let mut scenes = HashSet::new();
+        for a in Some(eid).into_iter().chain(parent.ancestors(eid)) {
+            let bound = if let Some(b) = self.bound_to.get(&a) { b } else { continue; };
+            for &t in bound.iter() {
+                if layers & self.mask(Scene(t)) == 0 {
+                    continue;
+                }
+                let into = cache.entry(t).or_insert_with(|| self.included_into(t));
+                for &s in into.iter() {
+                    if layers & self.mask(Scene(s)) != 0 {
+                        scenes.insert(s);
+                    }
+                }
+            }
+        }
+
+        let empty = HashSet::new();
+        let (joined, left): (Vec<Entity>, Vec<Entity>) = {
+            let last = self.member_of.get(&eid).unwrap_or(&empty);
+            (scenes.difference(last).cloned().collect(),
+             last.difference(&scenes).cloned().collect())
+        };
+        for s in joined {
+            insert(&mut self.members, s, eid);
+            insert(&mut self.added, s, eid);
+        }
+        for s in left {
+            remove(&mut self.members, s, eid);
+            insert(&mut self.removed, s, eid);
+        }
+        if scenes.is_empty() {
+            self.member_of.remove(&eid);
+        } else {
+            self.member_of.insert(eid, scenes);
+        }
+    }
+
+    /// Apply this frame's messages and parent changes, only the
+    /// entities that they could have moved in or out of a scene
+    /// are looked at again
+    fn update(&mut self, msgs: &[Message], parent: &ParentData) {
+        self.added.clear();
+        self.removed.clear();
+
+        // a change to a scene can change every entity that could be in it,
+        // these are found before and after the change
+        let mut scenes = HashSet::new();
+        let mut dirty = HashSet::new();
+        for m in msgs.iter() {
+            match m {
+                &Message::Include(Scene(s), _) |
+                &Message::Exclude(Scene(s), _) |
+                &Message::Enable(Scene(s), _) |
+                &Message::Mask(Scene(s), _) => { scenes.insert(s); }
+                &Message::Bind(_, eid) |
+                &Message::Unbind(_, eid) |
+                &Message::Layers(eid, _) => { dirty.insert(eid); }
+            }
+        }
+        for (&eid, _) in parent.deleted.iter() {
+            scenes.insert(eid);
+            dirty.insert(eid);
+        }
+        dirty.extend(parent.modified.keys().cloned());
+
+        let before = self.reachable(&scenes, parent);
+        self.apply_ingest(msgs);
+        self.delete(&parent.deleted);
+
+        let mut entities = before;
+        entities.extend(self.reachable(&scenes, parent));
+        for &eid in dirty.iter() {
+            entities.insert(eid);
+            entities.extend(parent.descendants(eid, Order::DepthFirst));
+        }
+
+        let mut cache = HashMap::new();
+        for eid in entities {
+            self.refresh(eid, parent, &mut cache);
         }
     }
 
@@ -87,44 +352,54 @@ impl SceneData {
         for op in msgs.iter() {
             match op {
                 &Message::Bind(Scene(scene), eid) => {
-                    self.contains
-                        .entry(scene)
-                        .or_insert_with(HashSet::new)
-                        .insert(eid);
-                    self.belongs_to
-                        .entry(eid)
-                        .or_insert_with(HashSet::new)
-                        .insert(scene);
+                    insert(&mut self.contains, scene, eid);
+                    insert(&mut self.bound_to, eid, scene);
                 }
                 &Message::Unbind(Scene(scene), eid) => {
-                    let len = self.contains
-                        .get_mut(&scene)
-                        .map(|c| {
-                            c.remove(&eid);
-                            c.len()
-                        });
-                    if let Some(len) = len {
-                        if len == 0 {
-                            self.contains.remove(&scene);
-                        }                        
-                    }
-                    let len = self.belongs_to
-                        .get_mut(&eid)
-                        .map(|c| {
-                            c.remove(&scene);
-                            c.len()
-                        });
-                    if let Some(len) = len {
-                        if len == 0 {
-                            self.belongs_to.remove(&eid);
-                        }                        
-                    }
+                    remove(&mut self.contains, scene, eid);
+                    remove(&mut self.bound_to, eid, scene);
+                }
+                &Message::Include(Scene(scene), Scene(other)) => {
+                    insert(&mut self.includes, scene, other);
+                    insert(&mut self.included_by, other, scene);
+                }
+                &Message::Exclude(Scene(scene), Scene(other)) => {
+                    remove(&mut self.includes, scene, other);
+                    remove(&mut self.included_by, other, scene);
+                }
+                &Message::Enable(Scene(scene), true) => {
+                    self.disabled.remove(&scene);
+                }
+                &Message::Enable(Scene(scene), false) => {
+                    self.disabled.insert(scene);
+                }
+                &Message::Mask(Scene(scene), mask) => {
+                    self.masks.insert(scene, mask);
+                }
+                &Message::Layers(eid, layers) => {
+                    self.layers.insert(eid, layers);
                 }
             }
         }
     }
 }
 
+// Add `value` to the set of `key`
+fn insert(map: &mut HashMap<Entity, HashSet<Entity>>, key: Entity, value: Entity) {
+    map.entry(key).or_insert_with(HashSet::new).insert(value);
+}
+
+// Remove `value` from the set of `key`, the set is removed once it is empty
+fn remove(map: &mut HashMap<Entity, HashSet<Entity>>, key: Entity, value: Entity) {
+    let len = map.get_mut(&key).map(|c| {
+        c.remove(&value);
+        c.len()
+    });
+    if len == Some(0) {
+        map.remove(&key);
+    }
+}
+
 /// A `Scene` is an entity that is used to manage
 impl Scene {
     /// Create a new Scene
@@ -143,6 +418,26 @@ impl Scene {
         src.send(Message::Unbind(*self, child))
     }
 
+    /// Add every entity of `other` to this scene
+    pub fn include(&self, other: Scene, src: &mut SceneSystem) {
+        src.send(Message::Include(*self, other))
+    }
+
+    /// Stop including the entities of `other`
+    pub fn exclude(&self, other: Scene, src: &mut SceneSystem) {
+        src.send(Message::Exclude(*self, other))
+    }
+
+    /// Turn the scene on or off
+    pub fn enable(&self, enabled: bool, src: &mut SceneSystem) {
+        src.send(Message::Enable(*self, enabled))
+    }
+
+    /// Only show entities that are on one of the layers in `mask`
+    pub fn set_mask(&self, mask: u32, src: &mut SceneSystem) {
+        src.send(Message::Mask(*self, mask))
+    }
+
     /// Delete this entity from a device
     pub fn delete<D>(&self, delete: &mut D) where D: DeleteEntity<Entity> {
         delete.delete(self.0);
@@ -161,6 +456,12 @@ impl entity::WriteEntity<Scene, Entity> for SceneSystem {
     }
 }
 
+impl entity::WriteEntity<Entity, Layers> for SceneSystem {
+    fn write(&mut self, eid: Entity, layers: Layers) {
+        self.send(Message::Layers(eid, layers));
+    }
+}
+
 /// Creates a new scene system. The scene system manages a relationship
 /// between Scene objects and entities. A Scene may contain 1 or more
 /// objects. An object may exist in more then one Scene.
@@ -172,35 +473,50 @@ impl entity::WriteEntity<Scene, Entity> for SceneSystem {
 /// into and out of the system.
 pub fn scene(sched: &mut Schedule, parents: ParentSystem) -> SceneSystem {
     let sd = SceneData::new();
-    let (mut system, handle) = system::System::new(sd.clone(), sd);
+    let (system, handle) = system::System::following("scene", &parents, sd.clone(), sd);
 
-    let mut limsgs = Vec::new();
+    let mut replay = Replay::new();
+    system.follow(sched, parents, move |scene, old, msgs, p, _| replay.update(scene, old, msgs, p));
 
-    task(move |_| {
-        let mut parents = Some(parents);
-        loop {
-            let s = system.update(|mut scene, _, mut msgs| {
-                let mut p = parents.take().unwrap();
-                let mut deleted = p.deleted.clone();
+    handle
+}
 
-                p = p.next_frame().get().unwrap();
-                for (&k, &v) in p.deleted.iter() { deleted.insert(k, v); }
-                let imsgs = sync_ingest(&mut msgs);
+/// Create the scene system without starting it, the step
+/// depends on the step of `parents`
+pub fn scene_step(parents: ParentSystem) -> (SceneSystem, system::schedule::Step) {
+    let sd = SceneData::new();
+    let (system, handle) = system::System::following("scene", &parents, sd.clone(), sd);
 
-                scene.apply_ingest(&limsgs[..]);
-                scene.apply_ingest(&imsgs[..]);
-                scene.delete(&deleted);
+    let mut replay = Replay::new();
+    (handle, system.follow_step(parents, move |scene, old, msgs, p, _| replay.update(scene, old, msgs, p)))
+}
 
-                limsgs = imsgs;
-                parents = Some(p);
-                scene
-            });
-            system = if let Some(s) = s { s } else { return; };
+// The back buffer is a frame behind, the last frame is
+// replayed onto it before the new messages are applied
+struct Replay {
+    msgs: Vec<Message>,
+    deleted: HashMap<Entity, Option<parent::Parent>>
+}
 
+impl Replay {
+    fn new() -> Replay {
+        Replay {
+            msgs: Vec::new(),
+            deleted: HashMap::new()
         }
-    }).start(sched);
+    }
 
-    handle
+    fn update(&mut self, mut scene: SceneData, old: &SceneData, msgs: Vec<Message>, p: &ParentSystem) -> SceneData {
+        scene.apply_ingest(&self.msgs[..]);
+        scene.delete(&self.deleted);
+        scene.catch_up(old);
+
+        scene.update(&msgs[..], p);
+
+        self.msgs = msgs;
+        self.deleted = p.deleted.clone();
+        scene
+    }
 }
 
 pub type SceneSystem = system::SystemHandle<Message, SceneData>;
\ No newline at end of file
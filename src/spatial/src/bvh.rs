@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use cgmath::{Aabb3, Point3};
+use entity::Entity;
+use bounding::Sphere;
+use {Frustum, Ray};
+
+/// How much a leaf's box is grown by, as a fraction of its size. An
+/// entity that moves inside of the grown box does not change the tree.
+const MARGIN: f32 = 0.1;
+
+#[derive(Clone, Debug)]
+enum Kind {
+    /// The entity and its exact bounds
+    Leaf(Entity, Aabb3<f32>),
+    Branch(usize, usize),
+    Free
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    aabb: Aabb3<f32>,
+    parent: Option<usize>,
+    kind: Kind
+}
+
+/// A dynamic bounding volume hierarchy. Entities can be added, moved
+/// and removed without rebuilding the tree.
+#[derive(Clone, Debug)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    root: Option<usize>,
+    leaves: HashMap<Entity, usize>
+}
+
+fn union(a: &Aabb3<f32>, b: &Aabb3<f32>) -> Aabb3<f32> {
+    Aabb3 {
+        min: Point3::new(a.min.x.min(b.min.x), a.min.y.min(b.min.y), a.min.z.min(b.min.z)),
+        max: Point3::new(a.max.x.max(b.max.x), a.max.y.max(b.max.y), a.max.z.max(b.max.z))
+    }
+}
+
+fn area(a: &Aabb3<f32>) -> f32 {
+    let (x, y, z) = (a.max.x - a.min.x, a.max.y - a.min.y, a.max.z - a.min.z);
+    2. * (x * y + y * z + z * x)
+}
+
+fn contains(outer: &Aabb3<f32>, inner: &Aabb3<f32>) -> bool {
+    outer.min.x <= inner.min.x && outer.min.y <= inner.min.y && outer.min.z <= inner.min.z &&
+    outer.max.x >= inner.max.x && outer.max.y >= inner.max.y && outer.max.z >= inner.max.z
+}
+
+/// Check if two boxes overlap, boxes that touch overlap
+pub fn overlaps(a: &Aabb3<f32>, b: &Aabb3<f32>) -> bool {
+    a.min.x <= b.max.x && a.max.x >= b.min.x &&
+    a.min.y <= b.max.y && a.max.y >= b.min.y &&
+    a.min.z <= b.max.z && a.max.z >= b.min.z
+}
+
+fn fatten(a: &Aabb3<f32>) -> Aabb3<f32> {
+    let (x, y, z) = ((a.max.x - a.min.x) * MARGIN,
+                     (a.max.y - a.min.y) * MARGIN,
+                     (a.max.z - a.min.z) * MARGIN);
+    Aabb3 {
+        min: Point3::new(a.min.x - x, a.min.y - y, a.min.z - z),
+        max: Point3::new(a.max.x + x, a.max.y + y, a.max.z + z)
+    }
+}
+
+impl Bvh {
+    pub fn new() -> Bvh {
+        Bvh {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: None,
+            leaves: HashMap::new()
+        }
+    }
+
+    /// The number of entities in the tree
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Check if the entity is in the tree
+    pub fn contains(&self, eid: Entity) -> bool {
+        self.leaves.contains_key(&eid)
+    }
+
+    /// Get the bounds the entity was added with
+    pub fn bounds(&self, eid: Entity) -> Option<&Aabb3<f32>> {
+        self.leaves.get(&eid).and_then(|&i| {
+            match self.nodes[i].kind {
+                Kind::Leaf(_, ref aabb) => Some(aabb),
+                _ => None
+            }
+        })
+    }
+
+    /// Every entity in the tree, in no particular order
+    pub fn entities(&self) -> Vec<Entity> {
+        self.leaves.keys().cloned().collect()
+    }
+
+    /// Add the entity to the tree or move it if it is already in the tree
+    pub fn insert(&mut self, eid: Entity, aabb: Aabb3<f32>) {
+        if let Some(&leaf) = self.leaves.get(&eid) {
+            if contains(&self.nodes[leaf].aabb, &aabb) {
+                self.nodes[leaf].kind = Kind::Leaf(eid, aabb);
+                return;
+            }
+            self.remove(eid);
+        }
+
+        let leaf = self.alloc(Node {
+            aabb: fatten(&aabb),
+            parent: None,
+            kind: Kind::Leaf(eid, aabb)
+        });
+        self.leaves.insert(eid, leaf);
+
+        let root = if let Some(root) = self.root {
+            root
+        } else {
+            self.root = Some(leaf);
+            return;
+        };
+
+        let sibling = self.find_sibling(root, &self.nodes[leaf].aabb.clone());
+        let old_parent = self.nodes[sibling].parent;
+        let branch = self.alloc(Node {
+            aabb: union(&self.nodes[sibling].aabb, &self.nodes[leaf].aabb),
+            parent: old_parent,
+            kind: Kind::Branch(sibling, leaf)
+        });
+        self.nodes[sibling].parent = Some(branch);
+        self.nodes[leaf].parent = Some(branch);
+
+        match old_parent {
+            Some(p) => {
+                self.replace_child(p, sibling, branch);
+                self.refit(p);
+            }
+            None => self.root = Some(branch)
+        }
+    }
+
+    /// Remove the entity from the tree, returns false if it was not in the tree
+    pub fn remove(&mut self, eid: Entity) -> bool {
+        let leaf = if let Some(leaf) = self.leaves.remove(&eid) {
+            leaf
+        } else {
+            return false;
+        };
+
+        let parent = self.nodes[leaf].parent;
+        self.release(leaf);
+
+        let parent = if let Some(p) = parent {
+            p
+        } else {
+            self.root = None;
+            return true;
+        };
+
+        let sibling = match self.nodes[parent].kind {
+            Kind::Branch(a, b) => if a == leaf { b } else { a },
+            _ => unreachable!()
+        };
+        let grand = self.nodes[parent].parent;
+        self.release(parent);
+        self.nodes[sibling].parent = grand;
+
+        match grand {
+            Some(g) => {
+                self.replace_child(g, parent, sibling);
+                self.refit(g);
+            }
+            None => self.root = Some(sibling)
+        }
+        true
+    }
+
+    /// Find every entity whose bounds overlap the box
+    pub fn query_aabb(&self, aabb: &Aabb3<f32>) -> Vec<Entity> {
+        self.query(|b| overlaps(b, aabb))
+    }
+
+    /// Find every entity whose bounds overlap the sphere
+    pub fn query_sphere(&self, sphere: &Sphere) -> Vec<Entity> {
+        self.query(|b| sphere.overlaps(b))
+    }
+
+    /// Find every entity whose bounds are at least partly inside the frustum
+    pub fn query_frustum(&self, frustum: &Frustum) -> Vec<Entity> {
+        self.query(|b| frustum.overlaps(b))
+    }
+
+    /// Find every entity whose bounds are hit by the ray, the
+    /// closest hit is first
+    pub fn query_ray(&self, ray: &Ray) -> Vec<(Entity, f32)> {
+        let mut hits = Vec::new();
+        self.visit(|b| ray.intersect(b).is_some(), |eid, b| {
+            if let Some(d) = ray.intersect(b) {
+                hits.push((eid, d));
+            }
+        });
+        hits.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        hits
+    }
+
+    fn query<F>(&self, test: F) -> Vec<Entity>
+        where F: Fn(&Aabb3<f32>) -> bool
+    {
+        let mut found = Vec::new();
+        self.visit(&test, |eid, b| {
+            if test(b) {
+                found.push(eid);
+            }
+        });
+        found
+    }
+
+    // Calls `leaf` for every leaf whose ancestors all passed `test`
+    fn visit<T, L>(&self, test: T, mut leaf: L)
+        where T: Fn(&Aabb3<f32>) -> bool,
+              L: FnMut(Entity, &Aabb3<f32>)
+    {
+        let mut pending: Vec<usize> = self.root.into_iter().collect();
+        while let Some(i) = pending.pop() {
+            let node = &self.nodes[i];
+            if !test(&node.aabb) {
+                continue;
+            }
+            match node.kind {
+                Kind::Leaf(eid, ref aabb) => leaf(eid, aabb),
+                Kind::Branch(a, b) => {
+                    pending.push(a);
+                    pending.push(b);
+                }
+                Kind::Free => ()
+            }
+        }
+    }
+
+    // Walk down the tree picking the child that grows the least,
+    // this keeps the surface area of the tree small
+    fn find_sibling(&self, root: usize, aabb: &Aabb3<f32>) -> usize {
+        let mut index = root;
+        loop {
+            let (a, b) = match self.nodes[index].kind {
+                Kind::Branch(a, b) => (a, b),
+                _ => return index
+            };
+
+            let combined = area(&union(&self.nodes[index].aabb, aabb));
+            let cost = 2. * combined;
+            let inherit = 2. * (combined - area(&self.nodes[index].aabb));
+
+            let child_cost = |c: usize| {
+                let node = &self.nodes[c];
+                let grown = area(&union(&node.aabb, aabb));
+                match node.kind {
+                    Kind::Leaf(..) => grown + inherit,
+                    _ => grown - area(&node.aabb) + inherit
+                }
+            };
+            let (cost_a, cost_b) = (child_cost(a), child_cost(b));
+
+            if cost < cost_a && cost < cost_b {
+                return index;
+            }
+            index = if cost_a < cost_b { a } else { b };
+        }
+    }
+
+    fn replace_child(&mut self, parent: usize, old: usize, new: usize) {
+        if let Kind::Branch(ref mut a, ref mut b) = self.nodes[parent].kind {
+            if *a == old { *a = new; } else { *b = new; }
+        }
+    }
+
+    // Recompute the bounds of `index` and its ancestors
+    fn refit(&mut self, index: usize) {
+        let mut current = Some(index);
+        while let Some(i) = current {
+            if let Kind::Branch(a, b) = self.nodes[i].kind {
+                self.nodes[i].aabb = union(&self.nodes[a].aabb, &self.nodes[b].aabb);
+            }
+            current = self.nodes[i].parent;
+        }
+    }
+
+    fn alloc(&mut self, node: Node) -> usize {
+        match self.free.pop() {
+            Some(i) => {
+                self.nodes[i] = node;
+                i
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, index: usize) {
+        self.nodes[index].kind = Kind::Free;
+        self.nodes[index].parent = None;
+        self.free.push(index);
+    }
+}
@@ -0,0 +1,234 @@
+extern crate cgmath;
+extern crate entity;
+extern crate scene;
+extern crate bounding;
+extern crate engine;
+extern crate system;
+extern crate lease;
+extern crate shared_future;
+
+use std::collections::{HashMap, HashSet};
+use entity::Entity;
+use scene::{Scene, SceneData, SceneSystem};
+use bounding::{Bounding, WorldBounds};
+use engine::fibe::*;
+
+mod bvh;
+mod shape;
+
+pub use bvh::{Bvh, overlaps};
+pub use shape::{Frustum, Ray};
+pub use bounding::Sphere;
+
+/// A `Bvh` for each scene, the trees hold the world space bounds
+/// of every entity in the scene that has a geometry. The index is
+/// updated from what changed in a frame so it must be updated with
+/// every frame of the systems it reads.
+#[derive(Clone)]
+pub struct SpatialIndex {
+    scenes: HashMap<Scene, Bvh>,
+
+    // the scenes each entity is in
+    members: HashMap<Entity, HashSet<Scene>>,
+}
+
+impl SpatialIndex {
+    pub fn new() -> SpatialIndex {
+        SpatialIndex {
+            scenes: HashMap::new(),
+            members: HashMap::new(),
+        }
+    }
+
+    /// Get the tree of a scene
+    pub fn scene(&self, scene: Scene) -> Option<&Bvh> {
+        self.scenes.get(&scene)
+    }
+
+    /// The entities of `scene` that are in `frustum`, or every entity
+    /// of the scene if there is no frustum. The entities that have no
+    /// world bounds are not in the index, they are always included.
+    pub fn visible(&self,
+                   scene: Scene,
+                   scenes: &SceneData,
+                   bounds: &WorldBounds,
+                   frustum: Option<&Frustum>) -> Vec<Entity> {
+        let members = scenes.scene_entities(scene);
+        let mut visible = match (self.scene(scene), frustum) {
+            (Some(bvh), Some(frustum)) => bvh.query_frustum(frustum),
+            (Some(bvh), None) => bvh.entities(),
+            (None, _) => Vec::new()
+        };
+        if let Some(members) = members {
+            visible.extend(members.iter().filter(|&&eid| bounds.aabb(eid).is_none()).cloned());
+        }
+        visible
+    }
+
+    /// Apply a frame's changes, `bounds` must already be updated
+    /// for the frame
+    pub fn update(&mut self, scenes: &SceneData, bounds: &WorldBounds) {
+        let mut refresh: HashSet<Entity> = HashSet::new();
+
+        for scene in scenes.changed_scenes() {
+            if let Some(removed) = scenes.removed(scene) {
+                for &eid in removed.iter() {
+                    self.leave(scene, eid);
+                }
+            }
+            if let Some(added) = scenes.added(scene) {
+                for &eid in added.iter() {
+                    self.members
+                        .entry(eid)
+                        .or_insert_with(HashSet::new)
+                        .insert(scene);
+                    refresh.insert(eid);
+                }
+            }
+        }
+
+        for eid in bounds.changed.iter() {
+            if self.members.contains_key(eid) {
+                refresh.insert(*eid);
+            }
+        }
+
+        for eid in refresh {
+            self.refresh(eid, bounds);
+        }
+    }
+
+    // Remove the entity from the scene's tree
+    fn leave(&mut self, scene: Scene, eid: Entity) {
+        let empty = if let Some(bvh) = self.scenes.get_mut(&scene) {
+            bvh.remove(eid);
+            bvh.len() == 0
+        } else {
+            false
+        };
+        if empty {
+            self.scenes.remove(&scene);
+        }
+
+        let gone = self.members.get_mut(&eid).map(|s| {
+            s.remove(&scene);
+            s.is_empty()
+        });
+        if gone == Some(true) {
+            self.members.remove(&eid);
+        }
+    }
+
+    // Move the entity to its current bounds in each of its scenes
+    fn refresh(&mut self, eid: Entity, bounds: &WorldBounds) {
+        let scenes = if let Some(s) = self.members.get(&eid) {
+            s
+        } else {
+            return;
+        };
+
+        for scene in scenes.iter() {
+            match bounds.aabb(eid) {
+                Some(aabb) => {
+                    self.scenes
+                        .entry(*scene)
+                        .or_insert_with(Bvh::new)
+                        .insert(eid, *aabb);
+                }
+                None => {
+                    if let Some(bvh) = self.scenes.get_mut(scene) {
+                        bvh.remove(eid);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The `spatial` system keeps a `SpatialIndex` of the scenes up to date
+/// with the world bounds of a `Bounding` system created `with_world`
+#[derive(Clone)]
+pub struct Spatial {
+    inner: Option<lease::Lease<SpatialIndex>>,
+    next: shared_future::Future<Spatial>,
+    shutdown: bool
+}
+
+impl Spatial {
+    /// Create a new spatial system
+    pub fn new(sched: &mut Schedule, scenes: SceneSystem, bounding: Bounding) -> Spatial {
+        let (mut front, l) = lease::lease(SpatialIndex::new());
+        let (mut back, _) = lease::lease(SpatialIndex::new());
+        let (future, mut set) = shared_future::Future::new();
+
+        let mut scenes = Some(scenes);
+        let mut bounding = bounding;
+        task(move |_| {
+            loop {
+                let start = system::profile::now();
+                let s = scenes.take().unwrap().next_frame();
+                bounding.next_frame();
+                let s = s.get().unwrap();
+                let mut inner = back.get();
+                let ready = system::profile::now();
+                inner.clone_from(&*front);
+
+                inner.update(&s, &bounding.world);
+                system::profile::timeline().record(system::profile::Span {
+                    name: "spatial",
+                    start: start,
+                    ready: ready,
+                    end: system::profile::now(),
+                    messages: bounding.world.changed.len(),
+                    thread: system::profile::thread_id()
+                });
+                let done = s.is_shutdown() || bounding.is_shutdown();
+                let (nown, nlease) = lease::lease(inner);
+                back = front;
+                front = nown;
+                let (next, nset) = shared_future::Future::new();
+                set.set(Spatial{
+                    inner: Some(nlease),
+                    next: next,
+                    shutdown: done
+                });
+
+                // an input published its last frame, so this is ours
+                if done {
+                    return;
+                }
+
+                set = nset;
+                scenes = Some(s);
+            }
+        }).start(sched);
+
+        Spatial {
+            inner: Some(l),
+            next: future,
+            shutdown: false
+        }
+    }
+
+    pub fn next_frame(&mut self) {
+        drop(self.inner.take());
+        let Spatial{inner, next, shutdown} = self.next.clone().get().unwrap();
+        self.inner = inner;
+        self.next = next;
+        self.shutdown = shutdown;
+    }
+
+    /// Returns true if this is the last frame, the spatial
+    /// system follows the systems it was created with
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown
+    }
+}
+
+impl std::ops::Deref for Spatial {
+    type Target = SpatialIndex;
+
+    fn deref(&self) -> &SpatialIndex {
+        self.inner.as_ref().unwrap()
+    }
+}
@@ -0,0 +1,124 @@
+use std::f32;
+use cgmath::{Aabb3, Matrix4, Point3, Vector3, Vector4};
+
+/// The space seen by a camera, this is six planes that
+/// each face into the frustum
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6]
+}
+
+impl Frustum {
+    /// Find the frustum of a projection * view matrix, anything
+    /// inside of it is in clip space after the transform
+    pub fn from_matrix(m: &Matrix4<f32>) -> Frustum {
+        fn get(v: &Vector4<f32>, i: usize) -> f32 {
+            match i { 0 => v.x, 1 => v.y, 2 => v.z, _ => v.w }
+        }
+        let row = |i: usize| Vector4::new(get(&m.x, i), get(&m.y, i), get(&m.z, i), get(&m.w, i));
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+        let add = |a: Vector4<f32>, b: Vector4<f32>| Vector4::new(a.x + b.x, a.y + b.y, a.z + b.z, a.w + b.w);
+        let sub = |a: Vector4<f32>, b: Vector4<f32>| Vector4::new(a.x - b.x, a.y - b.y, a.z - b.z, a.w - b.w);
+
+        Frustum {
+            planes: [
+                add(r3, r0), sub(r3, r0),
+                add(r3, r1), sub(r3, r1),
+                add(r3, r2), sub(r3, r2)
+            ]
+        }
+    }
+
+    /// Check if any part of the box may be inside the frustum, a box
+    /// near a corner of the frustum can be reported when it is outside
+    pub fn overlaps(&self, aabb: &Aabb3<f32>) -> bool {
+        self.planes.iter().all(|p| {
+            // the corner of the box that is furthest along the plane's normal
+            let x = if p.x >= 0. { aabb.max.x } else { aabb.min.x };
+            let y = if p.y >= 0. { aabb.max.y } else { aabb.min.y };
+            let z = if p.z >= 0. { aabb.max.z } else { aabb.min.z };
+            p.x * x + p.y * y + p.z * z + p.w >= 0.
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    /// The direction of the ray, distances are measured in its length
+    pub direction: Vector3<f32>
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Ray {
+        Ray {
+            origin: origin,
+            direction: direction
+        }
+    }
+
+    /// The point `distance` along the ray
+    pub fn at(&self, distance: f32) -> Point3<f32> {
+        Point3::new(self.origin.x + self.direction.x * distance,
+                    self.origin.y + self.direction.y * distance,
+                    self.origin.z + self.direction.z * distance)
+    }
+
+    /// Find how far along the ray it hits the triangle, both
+    /// sides of the triangle are hit. None if the ray misses.
+    pub fn intersect_triangle(&self, a: Point3<f32>, b: Point3<f32>, c: Point3<f32>) -> Option<f32> {
+        let sub = |a: Point3<f32>, b: Point3<f32>| Vector3::new(a.x - b.x, a.y - b.y, a.z - b.z);
+        let cross = |a: Vector3<f32>, b: Vector3<f32>| {
+            Vector3::new(a.y * b.z - a.z * b.y, a.z * b.x - a.x * b.z, a.x * b.y - a.y * b.x)
+        };
+        let dot = |a: Vector3<f32>, b: Vector3<f32>| a.x * b.x + a.y * b.y + a.z * b.z;
+
+        let (e1, e2) = (sub(b, a), sub(c, a));
+        let p = cross(self.direction, e2);
+        let det = dot(e1, p);
+        if det.abs() < 1e-8 {
+            return None;
+        }
+
+        let s = sub(self.origin, a);
+        let u = dot(s, p) / det;
+        if u < 0. || u > 1. {
+            return None;
+        }
+        let q = cross(s, e1);
+        let v = dot(self.direction, q) / det;
+        if v < 0. || u + v > 1. {
+            return None;
+        }
+        let t = dot(e2, q) / det;
+        if t >= 0. { Some(t) } else { None }
+    }
+
+    /// Find how far along the ray it enters the box, this is zero if
+    /// the ray starts inside of the box. None if the ray misses.
+    pub fn intersect(&self, aabb: &Aabb3<f32>) -> Option<f32> {
+        let origin = [self.origin.x, self.origin.y, self.origin.z];
+        let dir = [self.direction.x, self.direction.y, self.direction.z];
+        let min = [aabb.min.x, aabb.min.y, aabb.min.z];
+        let max = [aabb.max.x, aabb.max.y, aabb.max.z];
+
+        let mut near = 0f32;
+        let mut far = f32::INFINITY;
+        for i in 0..3 {
+            if dir[i] == 0. {
+                if origin[i] < min[i] || origin[i] > max[i] {
+                    return None;
+                }
+                continue;
+            }
+            let a = (min[i] - origin[i]) / dir[i];
+            let b = (max[i] - origin[i]) / dir[i];
+            near = near.max(a.min(b));
+            far = far.min(a.max(b));
+            if near > far {
+                return None;
+            }
+        }
+        Some(near)
+    }
+}
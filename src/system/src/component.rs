@@ -0,0 +1,104 @@
+use std::collections::HashSet;
+use ordered_vec::OrderedVec;
+use entity::{self, Entity, Operation};
+use fibe::Schedule;
+use {System, SystemHandle};
+use schedule::Step;
+
+/// Implemented by the data of a system that deletes entities, a
+/// `ComponentSystem` will remove the components of any entity that
+/// is deleted upstream.
+pub trait DeletedEntities {
+    /// The entities that were deleted in this frame
+    fn deleted_entities(&self) -> Vec<Entity>;
+}
+
+/// A store for a single component type
+#[derive(Clone)]
+pub struct ComponentData<T> {
+    /// The component for every entity that has one
+    pub current: OrderedVec<Entity, T>,
+    /// Components that were written during the last update
+    pub changed: OrderedVec<Entity, T>,
+    /// Entities that lost their component during the last update
+    pub deleted: HashSet<Entity>
+}
+
+impl<T: Clone> ComponentData<T> {
+    fn new() -> ComponentData<T> {
+        ComponentData {
+            current: OrderedVec::new(),
+            changed: OrderedVec::new(),
+            deleted: HashSet::new()
+        }
+    }
+
+    fn apply_ingest(&mut self, old: &ComponentData<T>, msgs: &[Operation<Entity, T>]) {
+        self.changed.clear();
+        self.deleted.clear();
+        self.current.clone_from_and_update(&old.current, msgs.iter().map(|x| x.clone()));
+        self.changed.apply_updates(msgs.iter().map(|x| x.clone()));
+
+        for m in msgs {
+            if let &Operation::Delete(eid) = m {
+                if old.current.get(&eid).is_some() && self.current.get(&eid).is_none() {
+                    self.deleted.insert(eid);
+                }
+            }
+        }
+    }
+}
+
+pub type ComponentSystem<T> = SystemHandle<Operation<Entity, T>, ComponentData<T>>;
+
+/// Create a system that stores a single component per entity. The
+/// system follows `upstream` frame by frame, removing the component
+/// of any entity that `upstream` reports as deleted and shutting
+/// down when `upstream` does.
+pub fn component<T, M, D>(sched: &mut Schedule,
+                          name: &'static str,
+                          upstream: SystemHandle<M, D>) -> ComponentSystem<T>
+    where T: Clone + Send + Sync + 'static,
+          M: Send + Sync + 'static,
+          D: DeletedEntities + Send + Sync + 'static
+{
+    let cd = ComponentData::new();
+    let (system, handle) = System::following(name, &upstream, cd.clone(), cd);
+    system.follow(sched, upstream, |data, old, msgs, u, _| update(data, old, msgs, u));
+    handle
+}
+
+/// Create a component system without starting it, the returned step
+/// must be added to a `Graph` with a dependency on `upstream`.
+pub fn component_step<T, M, D>(name: &'static str,
+                               upstream: SystemHandle<M, D>) -> (ComponentSystem<T>, Step)
+    where T: Clone + Send + Sync + 'static,
+          M: Send + Sync + 'static,
+          D: DeletedEntities + Send + Sync + 'static
+{
+    let cd = ComponentData::new();
+    let (system, handle) = System::following(name, &upstream, cd.clone(), cd);
+    (handle, system.follow_step(upstream, |data, old, msgs, u, _| update(data, old, msgs, u)))
+}
+
+fn update<T, M, D>(mut data: ComponentData<T>,
+                   old: &ComponentData<T>,
+                   mut msgs: Vec<Operation<Entity, T>>,
+                   upstream: &SystemHandle<M, D>) -> ComponentData<T>
+    where T: Clone + Send + Sync,
+          M: Send + Sync,
+          D: DeletedEntities + Send + Sync
+{
+    for d in upstream.deleted_entities() {
+        msgs.push(Operation::Delete(d));
+    }
+    msgs.sort_by(|a, b| a.key().cmp(b.key()));
+    data.apply_ingest(old, &msgs[..]);
+    data
+}
+
+impl<T> entity::ReadEntity<Entity, T> for ComponentData<T> {
+    fn read(&self, eid: &Entity) -> Option<&T> {
+        self.current.get(eid)
+    }
+}
@@ -3,26 +3,123 @@ extern crate lease;
 extern crate shared_future;
 extern crate snowstorm;
 extern crate entity;
+extern crate fibe;
+extern crate ordered_vec;
+extern crate time;
 
-use std::sync::Arc;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use fibe::{task, Schedule};
 
 pub mod channel {
     pub use snowstorm::channel::*;
 }
 
+pub mod component;
+pub mod schedule;
+pub mod profile;
+pub mod transaction;
+pub mod queue;
+pub use component::{component, component_step, ComponentData, ComponentSystem, DeletedEntities};
+pub use schedule::{Graph, FrameScheduler, ScheduleError};
+pub use transaction::{Transaction, TransactionError};
+pub use queue::{Backpressure, Coalesce, QueueStats};
+
+/// The messages that were sent to a system during a frame. Reading
+/// a message makes room for a sender that is held back by
+/// `Backpressure::Block`.
+pub struct Messages<M: Send + Sync> {
+    channel: channel::Receiver<M>,
+    pending: Arc<queue::Pending>,
+    ready: Arc<Mutex<Option<f64>>>
+}
+
+impl<M> Messages<M>
+    where M: Send + Sync
+{
+    /// Read the next message, this waits for a message until
+    /// every handle of the frame was released
+    pub fn recv(&mut self) -> Result<&M, ()> {
+        match self.channel.recv() {
+            Ok(m) => {
+                self.pending.release();
+                Ok(m)
+            }
+            Err(_) => {
+                // the last handle was released, the system has
+                // everything it needs for the frame
+                let mut ready = self.ready.lock().unwrap();
+                if ready.is_none() {
+                    *ready = Some(profile::now());
+                }
+                Err(())
+            }
+        }
+    }
+}
+
+/// Read every message that was sent to a system during the frame
+pub fn ingest<M: Clone + Send + Sync>(msgs: &mut Messages<M>) -> Vec<M> {
+    let mut out = Vec::new();
+    while let Ok(m) = msgs.recv() {
+        out.push(m.clone());
+    }
+    out
+}
+
+/// A flag that is shared by a system and all of its handles. Once
+/// it is signaled the system will publish one last frame and exit.
+#[derive(Clone)]
+pub struct Shutdown(Arc<AtomicBool>);
+
+impl Shutdown {
+    /// Create a new unsignaled flag
+    pub fn new() -> Shutdown {
+        Shutdown(Arc::new(AtomicBool::new(false)))
+    }
+
+    /// Ask the system to shutdown at the end of its current frame
+    pub fn signal(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Check if shutdown was requested
+    pub fn is_signaled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
 pub struct System<Message:Send+Sync, Data:Send+Sync> {
+    name: &'static str,
+    frame: u64,
     front: lease::Owner<Data>,
     back: lease::Owner<Data>,
     set: shared_future::Set<SystemHandle<Message, Data>>,
     count: Arc<AtomicUsize>,
+    sent: Arc<AtomicUsize>,
+    shutdown: Shutdown,
+    keep: Arc<AtomicUsize>,
+    history: VecDeque<Arc<Data>>,
+    spare: Vec<Arc<Data>>,
+    queue: Arc<queue::Queue<Message>>,
+    pending: Arc<queue::Pending>,
     input: channel::Receiver<Message>
 }
 
 pub struct SystemHandle<Message:Send+Sync, Data:Send+Sync>{
+    name: &'static str,
+    frame: u64,
     data: lease::Lease<Data>,
     channel: channel::Sender<Message>,
     count: Arc<AtomicUsize>,
+    sent: Arc<AtomicUsize>,
+    shutdown: Shutdown,
+    last: bool,
+    keep: Arc<AtomicUsize>,
+    history: Arc<Vec<Arc<Data>>>,
+    queue: Arc<queue::Queue<Message>>,
+    inbox: Arc<queue::Inbox<Message>>,
     next: shared_future::Future<SystemHandle<Message, Data>>
 }
 
@@ -32,10 +129,19 @@ impl<M, D> Clone for SystemHandle<M, D>
 {
     fn clone(&self) -> SystemHandle<M, D> {
         SystemHandle{
+            name: self.name,
+            frame: self.frame,
             data: self.data.clone(),
             channel: self.channel.clone(),
             next: self.next.clone(),
-            count: self.count.clone()
+            count: self.count.clone(),
+            sent: self.sent.clone(),
+            shutdown: self.shutdown.clone(),
+            last: self.last,
+            keep: self.keep.clone(),
+            history: self.history.clone(),
+            queue: self.queue.clone(),
+            inbox: self.inbox.clone()
         }
     }
 }
@@ -48,7 +154,7 @@ impl<M, D> SystemHandle<M, D>
     /// Flush all changes and try and fetch the next update for this system
     /// Returns true of the system was updated, false if it was not
     pub fn next_frame(self) -> shared_future::Future<SystemHandle<M, D>> {
-        let SystemHandle{data, channel, next, count} = self;
+        let SystemHandle{data, channel, next, count, ..} = self;
         count.fetch_add(1, Ordering::SeqCst);
         drop((data, channel));
         next
@@ -58,7 +164,149 @@ impl<M, D> SystemHandle<M, D>
     /// The channels are buffered and therefore the delivery is not
     /// guaranteed to occur immediately
     pub fn send(&mut self, m: M) {
-        self.channel.send(m);
+        // held back by a transaction
+        let m = match self.inbox.hold(m) {
+            Ok(()) => return,
+            Err(m) => m
+        };
+        deliver(&mut self.channel, &self.inbox, &self.queue, &self.sent, m);
+    }
+
+    /// Limit the number of messages that are queued for a frame,
+    /// the limit is shared by every handle to the system
+    pub fn set_backpressure(&self, mode: Backpressure) {
+        self.queue.set_mode(mode);
+    }
+
+    /// The current limit on the number of queued messages
+    pub fn backpressure(&self) -> Backpressure {
+        self.queue.mode()
+    }
+
+    /// Statistics about the messages the system has received
+    pub fn queue_stats(&self) -> QueueStats {
+        self.queue.stats()
+    }
+
+    /// The name of the system
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The number of the frame. A system that follows an upstream
+    /// system shares its frame numbers, otherwise the first frame is `0`
+    pub fn frame_number(&self) -> u64 {
+        self.frame
+    }
+
+    /// Ask the system to shutdown. The system will finish the frame
+    /// it is working on, publish it and exit. The published frame will
+    /// report `is_shutdown` so that any downstream systems can follow.
+    pub fn shutdown(&self) {
+        self.shutdown.signal();
+    }
+
+    /// Get a copy of the shutdown flag, signaling it has the same
+    /// effect as `shutdown` without holding on to the frame
+    pub fn shutdown_signal(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// Returns true if this is the last frame the system will publish.
+    /// The flag is fixed when the frame is published, a request made
+    /// after that is reported by the next frame.
+    pub fn is_shutdown(&self) -> bool {
+        self.last
+    }
+
+    /// Ask the system to keep snapshots of its last `n` frames, they
+    /// are available from frames published after the next update.
+    /// Every snapshot is a copy of the system's data, it is made once
+    /// and shared by every frame that can see it.
+    pub fn keep_history(&self, n: usize) {
+        self.keep.store(n, Ordering::SeqCst);
+    }
+
+    /// Read the data of a past frame, `0` is the current frame,
+    /// `-1` the frame before it and so on. Returns None if the
+    /// frame is not in the history.
+    pub fn frame(&self, offset: isize) -> Option<&D> {
+        if offset == 0 {
+            Some(&*self.data)
+        } else if offset < 0 {
+            self.history.get((-offset - 1) as usize).map(|d| &**d)
+        } else {
+            None
+        }
+    }
+
+    /// The snapshots of the past frames, most recent first
+    pub fn history(&self) -> &[Arc<D>] {
+        &self.history[..]
+    }
+
+    /// Shutdown the system and wait for it to publish its last frame
+    pub fn join(self) {
+        self.shutdown();
+        let mut handle = self;
+        while let Ok(next) = handle.next_frame().get() {
+            handle = next;
+        }
+    }
+}
+
+// Send a message that is not held by a transaction, it replaces an
+// earlier message if the queue coalesces and is limited by the
+// queue's backpressure
+fn deliver<M>(channel: &mut channel::Sender<M>,
+              inbox: &queue::Inbox<M>,
+              queue: &queue::Queue<M>,
+              sent: &AtomicUsize,
+              m: M)
+    where M: Send + Sync
+{
+    let m = match inbox.coalesce(m) {
+        Ok(()) => {
+            queue.coalesced();
+            return;
+        }
+        Err(m) => m
+    };
+
+    if let Backpressure::Drop(n) = queue.mode() {
+        if sent.load(Ordering::SeqCst) >= n {
+            queue.dropped();
+            return;
+        }
+    }
+
+    sent.fetch_add(1, Ordering::Relaxed);
+    if let Err(m) = inbox.push(m) {
+        if let Backpressure::Block(n) = queue.mode() {
+            if inbox.pending.is_full(n) {
+                queue.blocked();
+            }
+            inbox.pending.acquire(n);
+        } else {
+            inbox.pending.add();
+        }
+        channel.send(m);
+    }
+}
+
+impl<M, D> SystemHandle<M, D>
+    where M: Coalesce + Send + Sync + 'static,
+          D: Send + Sync
+{
+    /// Replace queued messages with later messages that have the same
+    /// key. The messages are held until every handle of the frame was
+    /// released, this starts with the next frame.
+    pub fn coalesce(&self, enabled: bool) {
+        self.queue.set_buffer(if enabled {
+            Some(queue::coalescer::<M>)
+        } else {
+            None
+        });
     }
 }
 
@@ -71,44 +319,153 @@ impl<M, D> std::ops::Deref for SystemHandle<M, D>
     fn deref(&self) -> &D { &self.data }
 }
 
+// Copy `data` for the history. A snapshot that fell out of the history
+// is reused once no handle can see it, so the copy does not allocate
+fn snapshot<D: Clone>(spare: &mut Vec<Arc<D>>, data: &D) -> D {
+    for i in 0..spare.len() {
+        if Arc::get_mut(&mut spare[i]).is_some() {
+            if let Ok(mut old) = Arc::try_unwrap(spare.swap_remove(i)) {
+                old.clone_from(data);
+                return old;
+            }
+            break;
+        }
+    }
+    data.clone()
+}
+
 impl<M, D> System<M, D>
     where M: Send + Sync,
-          D: Send + Sync
+          D: Send + Sync + Clone
 {
     /// Create a new system and a handle to it. That data is cloned
     /// into the front and back buffer of the channel
     pub fn new(front: D, back: D) -> (System<M, D>, SystemHandle<M, D>) {
+        System::named("system", front, back)
+    }
+
+    /// Create a new system with a name, the name is used
+    /// to identify the system in the `profile::timeline`
+    pub fn named(name: &'static str, front: D, back: D) -> (System<M, D>, SystemHandle<M, D>) {
+        System::numbered(name, 0, front, back)
+    }
+
+    /// Create a new system that is updated once for every frame of
+    /// `upstream`. The system starts on the same frame number as
+    /// `upstream`, so a `Transaction` can tell if handles of the
+    /// two systems belong to the same frame.
+    pub fn following<UM, UD>(name: &'static str,
+                             upstream: &SystemHandle<UM, UD>,
+                             front: D,
+                             back: D) -> (System<M, D>, SystemHandle<M, D>)
+        where UM: Send + Sync,
+              UD: Send + Sync
+    {
+        System::numbered(name, upstream.frame_number(), front, back)
+    }
+
+    fn numbered(name: &'static str, frame: u64, front: D, back: D) -> (System<M, D>, SystemHandle<M, D>) {
         let (front, l) = lease::lease(front);
         let (back, _) = lease::lease(back);
         let (future, set) = shared_future::Future::new();
         let (sender, input) = channel::channel();
 
         let count = Arc::new(AtomicUsize::new(0));
+        let sent = Arc::new(AtomicUsize::new(0));
+        let shutdown = Shutdown::new();
+        let keep = Arc::new(AtomicUsize::new(0));
+        let queue = Arc::new(queue::Queue::new());
+        let pending = Arc::new(queue::Pending::new());
+        let inbox = Arc::new(queue::Inbox::new(sender.clone(), None, pending.clone()));
 
         let system = System{
+            name: name,
+            frame: frame,
             front: front,
             back: back,
             set: set,
             input: input,
-            count: count.clone()
+            count: count.clone(),
+            sent: sent.clone(),
+            shutdown: shutdown.clone(),
+            keep: keep.clone(),
+            history: VecDeque::new(),
+            spare: Vec::new(),
+            queue: queue.clone(),
+            pending: pending
         };
 
         let handle = SystemHandle{
+            name: name,
+            frame: frame,
             data: l,
             channel: sender,
             next: future,
-            count: count
+            count: count,
+            sent: sent,
+            shutdown: shutdown,
+            last: false,
+            keep: keep,
+            history: Arc::new(Vec::new()),
+            queue: queue,
+            inbox: inbox
         };
 
         (system, handle)
     }
 
-    /// Update the system
+    /// Get a copy of the shutdown flag, this can be used by the
+    /// update function to shutdown the system if an upstream
+    /// system was shutdown
+    pub fn shutdown_signal(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// Update the system, returns None if the system
+    /// was shutdown or if all the handles were dropped
     pub fn update<F>(self, f: F) -> Option<System<M, D>>
-        where F: FnOnce(D, &D, channel::Receiver<M>) -> D
+        where F: FnOnce(D, &D, Messages<M>) -> D
     {
-        let System{front, back, set, input, count} = self;
-        let (next, l) = lease::lease(f(back.get(), &*front, input));
+        let System{name, frame, front, back, set, input, count, sent,
+                   shutdown, keep, mut history, mut spare, queue, pending} = self;
+
+        let data = back.get();
+        let start = profile::now();
+        let ready = Arc::new(Mutex::new(None));
+        let next = f(data, &*front, Messages{
+            channel: input,
+            pending: pending.clone(),
+            ready: ready.clone()
+        });
+        // anything that is sent from now on will never be read
+        pending.close();
+        queue.record(sent.load(Ordering::SeqCst));
+        // a system that follows an upstream has its frame once the
+        // messages are read, if they were not read none of the frame
+        // is counted as waiting
+        let ready = ready.lock().unwrap().unwrap_or(start);
+        profile::timeline().record(profile::Span {
+            name: name,
+            start: start,
+            ready: ready,
+            end: profile::now(),
+            messages: sent.load(Ordering::Relaxed),
+            thread: profile::thread_id()
+        });
+
+        // The published frame can see the frames before it,
+        // then it is added to the history for the frames after
+        let past = Arc::new(history.iter().map(|d| d.clone()).collect());
+        let n = keep.load(Ordering::SeqCst);
+        if n > 0 {
+            history.push_front(Arc::new(snapshot(&mut spare, &next)));
+        }
+        while history.len() > n {
+            spare.push(history.pop_back().unwrap());
+        }
+        spare.truncate(n);
+
+        let (next, l) = lease::lease(next);
 
         if count.load(Ordering::SeqCst) == 0 {
             return None;
@@ -117,23 +474,185 @@ impl<M, D> System<M, D>
         let (sender, input) = channel::channel();
         let (future, nset) = shared_future::Future::new();
         let count = Arc::new(AtomicUsize::new(0));
+        let sent = Arc::new(AtomicUsize::new(0));
+        let frame = frame + 1;
+        let pending = Arc::new(queue::Pending::new());
+        let inbox = Arc::new(queue::Inbox::new(sender.clone(), queue.buffer(), pending.clone()));
+
+        // Read the flag once, so the frame we publish and the decision
+        // to exit agree even if a handle signals it in between
+        let last = shutdown.is_signaled();
 
         // Show the updated state to the outside world
         set.set(SystemHandle{
+            name: name,
+            frame: frame,
             data: l,
             channel: sender,
             next: future,
-            count: count.clone()
+            count: count.clone(),
+            sent: sent.clone(),
+            shutdown: shutdown.clone(),
+            last: last,
+            keep: keep.clone(),
+            history: past,
+            queue: queue.clone(),
+            inbox: inbox
         });
 
+        // The last frame was published, dropping `nset` will
+        // wake anyone waiting for a frame after it
+        if last {
+            pending.close();
+            return None;
+        }
+
         Some(System{
+            name: name,
+            frame: frame,
             front: next,
             back: front,
             set: nset,
             input: input,
-            count: count
+            count: count,
+            sent: sent,
+            shutdown: shutdown,
+            keep: keep,
+            history: history,
+            spare: spare,
+            queue: queue,
+            pending: pending
+        })
+    }
+}
+
+impl<M, D> System<M, D>
+    where M: Clone + Send + Sync + 'static,
+          D: Send + Sync + Clone + 'static
+{
+    // Update the system once per frame until it is shutdown
+    fn start<F>(self, sched: &mut Schedule, mut f: F)
+        where F: FnMut(D, &D, Messages<M>, &mut Schedule) -> D + Send + 'static
+    {
+        task(move |sched| {
+            let mut system = self;
+            loop {
+                system = match system.update(|data, old, msgs| f(data, old, msgs, sched)) {
+                    Some(s) => s,
+                    None => return
+                };
+            }
+        }).start(sched);
+    }
+
+    // Update the system once per call of the step
+    fn stepper<F>(self, mut f: F) -> schedule::Step
+        where F: FnMut(D, &D, Messages<M>, &mut Schedule) -> D + Send + 'static
+    {
+        let mut system = Some(self);
+        Box::new(move |sched| {
+            system = match system.take() {
+                Some(s) => s.update(|data, old, msgs| f(data, old, msgs, sched)),
+                None => None
+            };
+            system.is_some()
         })
     }
+
+    /// Run the system as a task. `f` is called once per frame with
+    /// the back buffer, the last frame and the messages of the frame.
+    pub fn run<F>(self, sched: &mut Schedule, mut f: F)
+        where F: FnMut(D, &D, Vec<M>) -> D + Send + 'static
+    {
+        self.start(sched, move |data, old, mut msgs, _| f(data, old, ingest(&mut msgs)));
+    }
+
+    /// Turn the system into a `Step` that can be added to a
+    /// `schedule::Graph`, every call runs one update with `f`. The
+    /// step returns false once the system was shutdown.
+    pub fn step<F>(self, mut f: F) -> schedule::Step
+        where F: FnMut(D, &D, Vec<M>) -> D + Send + 'static
+    {
+        self.stepper(move |data, old, mut msgs, _| f(data, old, ingest(&mut msgs)))
+    }
+
+    /// Run the system as a task that follows `upstream` frame by frame.
+    /// Each update waits for the next frame of `upstream` and passes it
+    /// to `f`, the system shuts down with `upstream`.
+    pub fn follow<UM, UD, F>(self, sched: &mut Schedule, upstream: SystemHandle<UM, UD>, f: F)
+        where UM: Send + Sync + 'static,
+              UD: Send + Sync + 'static,
+              F: FnMut(D, &D, Vec<M>, &SystemHandle<UM, UD>, &mut Schedule) -> D + Send + 'static
+    {
+        let mut follow = Follow::new(self.shutdown_signal(), upstream, f);
+        self.start(sched, move |data, old, msgs, sched| follow.update(data, old, msgs, sched));
+    }
+
+    /// The `Step` version of `follow`. The step must be added to the
+    /// `schedule::Graph` with a dependency on `upstream`'s step, so the
+    /// frame it waits for has already been published.
+    pub fn follow_step<UM, UD, F>(self, upstream: SystemHandle<UM, UD>, f: F) -> schedule::Step
+        where UM: Send + Sync + 'static,
+              UD: Send + Sync + 'static,
+              F: FnMut(D, &D, Vec<M>, &SystemHandle<UM, UD>, &mut Schedule) -> D + Send + 'static
+    {
+        let mut follow = Follow::new(self.shutdown_signal(), upstream, f);
+        self.stepper(move |data, old, msgs, sched| follow.update(data, old, msgs, sched))
+    }
+}
+
+// The state of a system that follows an upstream system. The
+// upstream frame is released as soon as the update is done with it,
+// so the upstream can start its next frame before this system does.
+struct Follow<UM: Send + Sync, UD: Send + Sync, F> {
+    shutdown: Shutdown,
+    upstream: Option<shared_future::Future<SystemHandle<UM, UD>>>,
+    f: F
+}
+
+impl<UM, UD, F> Follow<UM, UD, F>
+    where UM: Send + Sync,
+          UD: Send + Sync
+{
+    fn new(shutdown: Shutdown, upstream: SystemHandle<UM, UD>, f: F) -> Follow<UM, UD, F> {
+        Follow {
+            shutdown: shutdown,
+            upstream: Some(upstream.next_frame()),
+            f: f
+        }
+    }
+
+    fn update<M, D>(&mut self, data: D, old: &D, mut msgs: Messages<M>, sched: &mut Schedule) -> D
+        where M: Clone + Send + Sync,
+              F: FnMut(D, &D, Vec<M>, &SystemHandle<UM, UD>, &mut Schedule) -> D
+    {
+        let u = self.upstream.take().unwrap().get().unwrap();
+        if u.is_shutdown() {
+            self.shutdown.signal();
+        }
+        let data = (self.f)(data, old, ingest(&mut msgs), &u, sched);
+        self.upstream = Some(u.next_frame());
+        data
+    }
+}
+
+impl<T, U, V> entity::WriteEntity<T, U> for SystemHandle<entity::Operation<T, U>, V>
+    where T: Send+Sync,
+          U: Send+Sync,
+          V: Send+Sync
+{
+    fn write(&mut self, eid: T, value: U) {
+        self.send(entity::Operation::Upsert(eid, value));
+    }
+}
+
+impl<K, V, M, D> entity::ReadEntity<K, V> for SystemHandle<M, D>
+    where M: Send+Sync,
+          D: entity::ReadEntity<K, V> + Send+Sync
+{
+    fn read(&self, eid: &K) -> Option<&V> {
+        self.data.read(eid)
+    }
 }
 
 impl<T, U, V> entity::DeleteEntity<T> for SystemHandle<entity::Operation<T, U>, V> 
@@ -0,0 +1,167 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+use std::sync::{Mutex, Once, ONCE_INIT};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use time;
+
+/// A single frame of a single system
+#[derive(Clone, Debug)]
+pub struct Span {
+    /// The name of the system
+    pub name: &'static str,
+    /// When the frame started in seconds
+    pub start: f64,
+    /// When the upstream frames and the messages were ready, the
+    /// system was waiting on them from `start` until then
+    pub ready: f64,
+    /// When the frame finished in seconds
+    pub end: f64,
+    /// The number of messages the system received for this frame
+    pub messages: usize,
+    /// The thread that the system ran on
+    pub thread: usize
+}
+
+impl Span {
+    /// How long the frame took in seconds, including the wait
+    pub fn duration(&self) -> f64 {
+        self.end - self.start
+    }
+
+    /// How long the system waited for its upstream and its messages
+    pub fn wait(&self) -> f64 {
+        self.ready - self.start
+    }
+
+    /// How long the system worked on the frame once it was ready
+    pub fn work(&self) -> f64 {
+        self.end - self.ready
+    }
+}
+
+/// Records the most recent `Span`s of every system
+pub struct Timeline {
+    enabled: AtomicBool,
+    capacity: AtomicUsize,
+    spans: Mutex<VecDeque<Span>>
+}
+
+static NEXT_THREAD: AtomicUsize = ATOMIC_USIZE_INIT;
+thread_local!(static THREAD: usize = NEXT_THREAD.fetch_add(1, Ordering::SeqCst));
+
+/// A small number that is unique to the current thread
+pub fn thread_id() -> usize {
+    THREAD.with(|&id| id)
+}
+
+/// Get the current time in seconds
+pub fn now() -> f64 {
+    time::precise_time_s()
+}
+
+/// Get the process wide timeline
+pub fn timeline() -> &'static Timeline {
+    static INIT: Once = ONCE_INIT;
+    static mut TIMELINE: *const Timeline = 0 as *const Timeline;
+
+    unsafe {
+        INIT.call_once(|| {
+            TIMELINE = Box::into_raw(Box::new(Timeline::new()));
+        });
+        &*TIMELINE
+    }
+}
+
+impl Timeline {
+    fn new() -> Timeline {
+        Timeline {
+            enabled: AtomicBool::new(false),
+            capacity: AtomicUsize::new(10_000),
+            spans: Mutex::new(VecDeque::new())
+        }
+    }
+
+    /// Turn recording on or off, it is off by default
+    pub fn enable(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Returns true if spans are being recorded
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    /// Set the maximum number of spans that are kept, the oldest
+    /// spans are dropped first
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::SeqCst);
+    }
+
+    /// Record a span if the timeline is enabled
+    pub fn record(&self, span: Span) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let capacity = self.capacity.load(Ordering::SeqCst);
+        let mut spans = self.spans.lock().unwrap();
+        while spans.len() >= capacity && spans.len() > 0 {
+            spans.pop_front();
+        }
+        spans.push_back(span);
+    }
+
+    /// Remove all the recorded spans
+    pub fn clear(&self) {
+        self.spans.lock().unwrap().clear();
+    }
+
+    /// Copy the recorded spans, oldest first
+    pub fn spans(&self) -> Vec<Span> {
+        self.spans.lock().unwrap().iter().map(|s| s.clone()).collect()
+    }
+
+    /// The most recent span of each system, sorted by name
+    pub fn latest(&self) -> Vec<Span> {
+        let mut latest = HashMap::new();
+        for span in self.spans.lock().unwrap().iter() {
+            latest.insert(span.name, span.clone());
+        }
+        let mut latest: Vec<Span> = latest.into_iter().map(|(_, v)| v).collect();
+        latest.sort_by(|a, b| a.name.cmp(b.name));
+        latest
+    }
+
+    /// Write the recorded spans in the Chrome trace event format,
+    /// this can be loaded by `chrome://tracing`. The wait and the work
+    /// of a span are written as separate events, the wait is in the
+    /// `wait` category and is left out if there was none.
+    pub fn write_chrome_trace<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let spans = self.spans();
+        try!(write!(out, "{{\"traceEvents\":["));
+        for (i, span) in spans.iter().enumerate() {
+            if i != 0 {
+                try!(write!(out, ","));
+            }
+            let name = span.name.replace("\\", "\\\\").replace("\"", "\\\"");
+            if span.wait() > 0. {
+                try!(write!(out,
+                    "{{\"name\":\"{}\",\"cat\":\"wait\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":0,\"tid\":{}}},",
+                    name,
+                    (span.start * 1_000_000.) as u64,
+                    (span.wait() * 1_000_000.) as u64,
+                    span.thread
+                ));
+            }
+            try!(write!(out,
+                "{{\"name\":\"{}\",\"cat\":\"system\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":0,\"tid\":{},\"args\":{{\"messages\":{}}}}}",
+                name,
+                (span.ready * 1_000_000.) as u64,
+                (span.work() * 1_000_000.) as u64,
+                span.thread,
+                span.messages
+            ));
+        }
+        write!(out, "]}}")
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem;
+use std::sync::{Arc, Mutex, Condvar};
+use entity::Operation;
+use channel;
+
+/// How a system's message queue behaves once it has filled up
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Every message is queued, this is the default
+    Unbounded,
+    /// Once this many messages are waiting to be read by the system
+    /// `send` will block until the system has read some of them.
+    /// Messages that are held to be coalesced are not counted.
+    ///
+    /// The system only reads its messages once its upstream frames are
+    /// published. A thread that goes over the limit while it still holds
+    /// a handle of one of those upstream systems blocks forever, a single
+    /// threaded writer must release its upstream frames first.
+    Block(usize),
+    /// Once this many messages are queued for a frame any
+    /// further messages are dropped
+    Drop(usize)
+}
+
+/// Statistics about the messages a system received
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// The number of frames that were recorded
+    pub frames: u64,
+    /// The number of messages in the last frame
+    pub messages: usize,
+    /// The size of the messages in the last frame, this does
+    /// not include anything the messages allocated
+    pub bytes: usize,
+    /// The most messages that were queued for a single frame
+    pub peak: usize,
+    /// The number of messages received over every frame
+    pub total: u64,
+    /// The number of messages that were dropped by `Backpressure::Drop`
+    pub dropped: u64,
+    /// The number of messages that replaced an earlier message
+    pub coalesced: u64,
+    /// The number of sends that found the queue full and had
+    /// to wait because of `Backpressure::Block`
+    pub blocked: u64
+}
+
+/// Messages that can replace an earlier message with the same key
+pub trait Coalesce {
+    type Key: Hash + Eq + Send;
+
+    /// The key of the message, messages without a key are never
+    /// replaced and are never moved ahead of
+    fn coalesce_key(&self) -> Option<Self::Key>;
+}
+
+impl<K, V> Coalesce for Operation<K, V>
+    where K: Hash + Eq + Clone + Send
+{
+    type Key = K;
+
+    fn coalesce_key(&self) -> Option<K> {
+        match self {
+            &Operation::Upsert(ref k, _) => Some(k.clone()),
+            &Operation::Delete(_) => None
+        }
+    }
+}
+
+/// Holds the messages of a frame until every handle was released
+pub trait Buffer<M>: Send {
+    /// Replace an earlier message with the same key, returns
+    /// the message if there was nothing to replace
+    fn coalesce(&mut self, m: M) -> Result<(), M>;
+
+    /// Add a new message to the end of the buffer
+    fn push(&mut self, m: M);
+
+    /// Remove all of the buffered messages
+    fn drain(&mut self) -> Vec<M>;
+}
+
+struct Coalescer<M: Coalesce> {
+    msgs: Vec<M>,
+    index: HashMap<M::Key, usize>
+}
+
+impl<M> Buffer<M> for Coalescer<M>
+    where M: Coalesce + Send
+{
+    fn coalesce(&mut self, m: M) -> Result<(), M> {
+        if let Some(k) = m.coalesce_key() {
+            if let Some(&i) = self.index.get(&k) {
+                self.msgs[i] = m;
+                return Ok(());
+            }
+        }
+        Err(m)
+    }
+
+    fn push(&mut self, m: M) {
+        match m.coalesce_key() {
+            Some(k) => { self.index.insert(k, self.msgs.len()); }
+            // nothing after this message may be moved in front of it
+            None => self.index.clear()
+        }
+        self.msgs.push(m);
+    }
+
+    fn drain(&mut self) -> Vec<M> {
+        self.index.clear();
+        mem::replace(&mut self.msgs, Vec::new())
+    }
+}
+
+/// Create a buffer that coalesces messages
+pub fn coalescer<M>() -> Box<Buffer<M>>
+    where M: Coalesce + Send + 'static
+{
+    Box::new(Coalescer {
+        msgs: Vec::new(),
+        index: HashMap::new()
+    })
+}
+
+/// The settings and statistics of a system's queue, this is
+/// shared by the system and every handle to it
+pub struct Queue<M> {
+    mode: Mutex<Backpressure>,
+    buffer: Mutex<Option<fn() -> Box<Buffer<M>>>>,
+    stats: Mutex<QueueStats>
+}
+
+impl<M> Queue<M> {
+    pub fn new() -> Queue<M> {
+        Queue {
+            mode: Mutex::new(Backpressure::Unbounded),
+            buffer: Mutex::new(None),
+            stats: Mutex::new(QueueStats::default())
+        }
+    }
+
+    pub fn mode(&self) -> Backpressure {
+        *self.mode.lock().unwrap()
+    }
+
+    pub fn set_mode(&self, mode: Backpressure) {
+        *self.mode.lock().unwrap() = mode;
+    }
+
+    pub fn set_buffer(&self, buffer: Option<fn() -> Box<Buffer<M>>>) {
+        *self.buffer.lock().unwrap() = buffer;
+    }
+
+    pub fn buffer(&self) -> Option<Box<Buffer<M>>> {
+        self.buffer.lock().unwrap().map(|f| f())
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Record the messages a frame received
+    pub fn record(&self, messages: usize) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.frames += 1;
+        stats.messages = messages;
+        stats.bytes = messages * mem::size_of::<M>();
+        stats.total += messages as u64;
+        if messages > stats.peak {
+            stats.peak = messages;
+        }
+    }
+
+    pub fn dropped(&self) {
+        self.stats.lock().unwrap().dropped += 1;
+    }
+
+    pub fn coalesced(&self) {
+        self.stats.lock().unwrap().coalesced += 1;
+    }
+
+    pub fn blocked(&self) {
+        self.stats.lock().unwrap().blocked += 1;
+    }
+}
+
+/// Counts the messages of a frame that were queued but not read by the
+/// system yet, this is what `Backpressure::Block` waits on
+pub struct Pending {
+    state: Mutex<(usize, bool)>,
+    ready: Condvar
+}
+
+impl Pending {
+    pub fn new() -> Pending {
+        Pending {
+            state: Mutex::new((0, false)),
+            ready: Condvar::new()
+        }
+    }
+
+    /// Add a message, waits until there are less then `limit`
+    /// unread messages first
+    pub fn acquire(&self, limit: usize) {
+        let mut state = self.state.lock().unwrap();
+        while state.0 >= limit && !state.1 {
+            state = self.ready.wait(state).unwrap();
+        }
+        state.0 += 1;
+    }
+
+    /// Returns true if `acquire` would have to wait
+    pub fn is_full(&self, limit: usize) -> bool {
+        let state = self.state.lock().unwrap();
+        state.0 >= limit && !state.1
+    }
+
+    /// Add a message without waiting
+    pub fn add(&self) {
+        self.state.lock().unwrap().0 += 1;
+    }
+
+    /// The system read a message
+    pub fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.0 > 0 {
+            state.0 -= 1;
+        }
+        self.ready.notify_all();
+    }
+
+    /// The system will not read any more messages, nobody waits from now on
+    pub fn close(&self) {
+        self.state.lock().unwrap().1 = true;
+        self.ready.notify_all();
+    }
+}
+
+/// The messages of a single frame, any buffered messages are
+/// sent once every handle of the frame was released
+pub struct Inbox<M: Send + Sync> {
+    channel: Mutex<channel::Sender<M>>,
+    buffer: Mutex<Option<Box<Buffer<M>>>>,
+    // messages held back by open transactions, the last one
+    // staged gets any message that is sent
+    staged: Mutex<Vec<(usize, Vec<M>)>>,
+    pub pending: Arc<Pending>
+}
+
+impl<M> Inbox<M>
+    where M: Send + Sync
+{
+    pub fn new(channel: channel::Sender<M>,
+               buffer: Option<Box<Buffer<M>>>,
+               pending: Arc<Pending>) -> Inbox<M> {
+        Inbox {
+            channel: Mutex::new(channel),
+            buffer: Mutex::new(buffer),
+            staged: Mutex::new(Vec::new()),
+            pending: pending
+        }
+    }
+
+    /// Hold back the messages of the frame for the transaction `id`,
+    /// returns false if another transaction was already holding them
+    pub fn stage(&self, id: usize) -> bool {
+        let mut staged = self.staged.lock().unwrap();
+        let free = staged.is_empty();
+        staged.push((id, Vec::new()));
+        free
+    }
+
+    /// Hold a message for the last transaction that was staged,
+    /// returns the message if there is no open transaction
+    pub fn hold(&self, m: M) -> Result<(), M> {
+        match self.staged.lock().unwrap().last_mut() {
+            Some(&mut (_, ref mut msgs)) => {
+                msgs.push(m);
+                Ok(())
+            }
+            None => Err(m)
+        }
+    }
+
+    /// Stop holding messages for the transaction `id`, returns
+    /// the messages that were held for it
+    pub fn release(&self, id: usize) -> Vec<M> {
+        let mut staged = self.staged.lock().unwrap();
+        let mut msgs = Vec::new();
+        let mut i = 0;
+        while i < staged.len() {
+            if staged[i].0 == id {
+                msgs.extend(staged.remove(i).1);
+            } else {
+                i += 1;
+            }
+        }
+        msgs
+    }
+
+    /// Replace a buffered message, returns the message if
+    /// it could not be replaced
+    pub fn coalesce(&self, m: M) -> Result<(), M> {
+        match *self.buffer.lock().unwrap() {
+            Some(ref mut buffer) => buffer.coalesce(m),
+            None => Err(m)
+        }
+    }
+
+    /// Buffer a message, returns the message if there is no buffer
+    pub fn push(&self, m: M) -> Result<(), M> {
+        match *self.buffer.lock().unwrap() {
+            Some(ref mut buffer) => {
+                buffer.push(m);
+                Ok(())
+            }
+            None => Err(m)
+        }
+    }
+}
+
+impl<M> Drop for Inbox<M>
+    where M: Send + Sync
+{
+    fn drop(&mut self) {
+        if let Some(mut buffer) = self.buffer.lock().unwrap().take() {
+            let mut channel = self.channel.lock().unwrap();
+            for m in buffer.drain() {
+                self.pending.add();
+                channel.send(m);
+            }
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Condvar};
+use std::time::Duration;
+use std::fmt;
+use fibe::{task, Schedule};
+
+/// A single frame of a system, returns false when the system has
+/// finished and should not be run again. The step is run as a task,
+/// it is passed the task's scheduler.
+pub type Step = Box<FnMut(&mut Schedule) -> bool + Send>;
+
+/// Errors that are reported while building or running a `FrameScheduler`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScheduleError {
+    /// Two systems were added with the same name
+    Duplicate(String),
+    /// A system depends on a system that was never added
+    UnknownDependency{system: String, dependency: String},
+    /// The systems form a cycle, the first system is repeated
+    /// at the end of the list
+    Cycle(Vec<String>),
+    /// These systems did not finish their frame within the timeout
+    Deadlock(Vec<String>)
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ScheduleError::Duplicate(ref name) => {
+                write!(f, "system {} was added twice", name)
+            }
+            &ScheduleError::UnknownDependency{ref system, ref dependency} => {
+                write!(f, "system {} depends on unknown system {}", system, dependency)
+            }
+            &ScheduleError::Cycle(ref path) => {
+                write!(f, "systems form a cycle {}", path.join(" -> "))
+            }
+            &ScheduleError::Deadlock(ref waiting) => {
+                write!(f, "systems did not finish their frame {}", waiting.join(", "))
+            }
+        }
+    }
+}
+
+struct Node {
+    name: String,
+    deps: Vec<String>,
+    step: Option<Step>,
+    finished: bool
+}
+
+/// Describes a set of systems and the systems they depend on
+pub struct Graph {
+    nodes: Vec<Node>
+}
+
+impl Graph {
+    pub fn new() -> Graph {
+        Graph {
+            nodes: Vec::new()
+        }
+    }
+
+    /// Add a system to the graph. The `step` will be run once per frame
+    /// after every system listed in `deps` has finished its frame.
+    pub fn add<F>(&mut self, name: &str, deps: &[&str], mut step: F) -> &mut Graph
+        where F: FnMut() -> bool + Send + 'static
+    {
+        self.add_step(name, deps, Box::new(move |_: &mut Schedule| step()))
+    }
+
+    /// Add a system that is already boxed, like the `Step` that
+    /// is returned by `System::step`
+    pub fn add_step(&mut self, name: &str, deps: &[&str], step: Step) -> &mut Graph {
+        self.nodes.push(Node {
+            name: name.to_string(),
+            deps: deps.iter().map(|d| d.to_string()).collect(),
+            step: Some(step),
+            finished: false
+        });
+        self
+    }
+
+    /// Sort the systems into levels, every system in a level only depends on
+    /// systems in an earlier level. Systems in the same level can be run in
+    /// parallel.
+    pub fn resolve(&self) -> Result<Vec<Vec<String>>, ScheduleError> {
+        self.levels().map(|levels| {
+            levels.into_iter()
+                  .map(|l| l.into_iter().map(|i| self.nodes[i].name.clone()).collect())
+                  .collect()
+        })
+    }
+
+    fn levels(&self) -> Result<Vec<Vec<usize>>, ScheduleError> {
+        let mut index = HashMap::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            if index.insert(&node.name[..], i).is_some() {
+                return Err(ScheduleError::Duplicate(node.name.clone()));
+            }
+        }
+
+        let mut deps = Vec::with_capacity(self.nodes.len());
+        for node in self.nodes.iter() {
+            let mut d = Vec::new();
+            for dep in node.deps.iter() {
+                match index.get(&dep[..]) {
+                    Some(&i) => d.push(i),
+                    None => return Err(ScheduleError::UnknownDependency{
+                        system: node.name.clone(),
+                        dependency: dep.clone()
+                    })
+                }
+            }
+            deps.push(d);
+        }
+
+        #[derive(Copy, Clone, PartialEq)]
+        enum Mark {
+            New,
+            Visiting,
+            Done(usize)
+        }
+
+        // depth first search, the level of a node is one more
+        // then the deepest level of its dependencies
+        fn visit(i: usize,
+                 deps: &[Vec<usize>],
+                 marks: &mut [Mark],
+                 path: &mut Vec<usize>) -> Result<usize, Vec<usize>> {
+            match marks[i] {
+                Mark::Done(level) => return Ok(level),
+                Mark::Visiting => {
+                    let start = path.iter().position(|&p| p == i).unwrap();
+                    let mut cycle: Vec<usize> = path[start..].to_vec();
+                    cycle.push(i);
+                    return Err(cycle);
+                }
+                Mark::New => ()
+            }
+
+            marks[i] = Mark::Visiting;
+            path.push(i);
+            let mut level = 0;
+            for &d in deps[i].iter() {
+                let l = try!(visit(d, deps, marks, path));
+                if l + 1 > level {
+                    level = l + 1;
+                }
+            }
+            path.pop();
+            marks[i] = Mark::Done(level);
+            Ok(level)
+        }
+
+        let mut marks = vec![Mark::New; self.nodes.len()];
+        let mut levels: Vec<Vec<usize>> = Vec::new();
+        for i in 0..self.nodes.len() {
+            let level = match visit(i, &deps[..], &mut marks[..], &mut Vec::new()) {
+                Ok(level) => level,
+                Err(cycle) => return Err(ScheduleError::Cycle(
+                    cycle.into_iter().map(|i| self.nodes[i].name.clone()).collect()
+                ))
+            };
+            while levels.len() <= level {
+                levels.push(Vec::new());
+            }
+            levels[level].push(i);
+        }
+        Ok(levels)
+    }
+
+    /// Resolve the graph into a `FrameScheduler`
+    pub fn build(self) -> Result<FrameScheduler, ScheduleError> {
+        let levels = try!(self.levels());
+        let mut nodes: Vec<Option<Node>> = self.nodes.into_iter().map(|n| Some(n)).collect();
+        let levels = levels.into_iter()
+            .map(|l| l.into_iter().map(|i| nodes[i].take().unwrap()).collect())
+            .collect();
+
+        Ok(FrameScheduler {
+            levels: levels,
+            timeout: Duration::from_secs(10),
+            error: None
+        })
+    }
+}
+
+/// Runs a resolved `Graph` frame by frame
+pub struct FrameScheduler {
+    levels: Vec<Vec<Node>>,
+    timeout: Duration,
+    error: Option<ScheduleError>
+}
+
+impl FrameScheduler {
+    /// The order the systems are run in, systems in the same
+    /// level are run in parallel
+    pub fn order(&self) -> Vec<Vec<&str>> {
+        self.levels.iter()
+            .map(|l| l.iter().map(|n| &n.name[..]).collect())
+            .collect()
+    }
+
+    /// Set how long a level may take before the scheduler
+    /// reports a deadlock, the default is 10 seconds
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Run a single frame of every system. Returns Ok(false) if any of the
+    /// systems has finished. A system that has finished is not run again.
+    ///
+    /// If a level does not complete within the timeout the systems that are
+    /// still running are reported as a `Deadlock`. The scheduler cannot
+    /// recover from this and will report the same error from then on.
+    ///
+    /// The calling thread blocks until every level has finished. Do not
+    /// call this from inside a task of `sched`, the blocked task holds
+    /// one of the pool's threads and the steps may never get to run.
+    pub fn run_frame(&mut self, sched: &mut Schedule) -> Result<bool, ScheduleError> {
+        if let Some(ref err) = self.error {
+            return Err(err.clone());
+        }
+
+        let mut running = true;
+        for level in self.levels.iter_mut() {
+            let done = Arc::new((Mutex::new(Vec::new()), Condvar::new()));
+
+            let mut started = 0;
+            for (i, node) in level.iter_mut().enumerate() {
+                if node.finished {
+                    running = false;
+                    continue;
+                }
+                started += 1;
+                let mut step = node.step.take().unwrap();
+                let done = done.clone();
+                task(move |sched| {
+                    let res = step(sched);
+                    let &(ref lock, ref cvar) = &*done;
+                    lock.lock().unwrap().push((i, step, res));
+                    cvar.notify_one();
+                }).start(sched);
+            }
+
+            let &(ref lock, ref cvar) = &*done;
+            let mut finished = lock.lock().unwrap();
+            while finished.len() < started {
+                let (f, timeout) = cvar.wait_timeout(finished, self.timeout).unwrap();
+                finished = f;
+                if timeout.timed_out() && finished.len() < started {
+                    break;
+                }
+            }
+
+            for (i, step, res) in finished.drain(..) {
+                // a finished step is dropped, it is never run again
+                if res {
+                    level[i].step = Some(step);
+                } else {
+                    level[i].finished = true;
+                }
+                running &= res;
+            }
+
+            let waiting: Vec<String> = level.iter()
+                .filter(|n| !n.finished && n.step.is_none())
+                .map(|n| n.name.clone())
+                .collect();
+            if waiting.len() != 0 {
+                let err = ScheduleError::Deadlock(waiting);
+                self.error = Some(err.clone());
+                return Err(err);
+            }
+        }
+        Ok(running)
+    }
+}
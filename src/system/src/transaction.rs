@@ -0,0 +1,155 @@
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use channel;
+use queue::{Inbox, Queue};
+use SystemHandle;
+
+// Every transaction gets its own id so that the messages it
+// holds can be told apart from the ones of other transactions
+static NEXT_ID: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Errors that are reported when committing a `Transaction`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TransactionError {
+    /// A system was staged from a different frame then the
+    /// other systems in the transaction
+    FrameMismatch{system: &'static str, expected: u64, found: u64},
+    /// The system was already staged in another transaction
+    AlreadyStaged(&'static str)
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &TransactionError::FrameMismatch{system, expected, found} => {
+                write!(f, "system {} is on frame {} but the transaction is on frame {}",
+                    system, found, expected)
+            }
+            &TransactionError::AlreadyStaged(system) => {
+                write!(f, "system {} is already part of a transaction", system)
+            }
+        }
+    }
+}
+
+trait Commit: Send {
+    /// Send the staged messages to the system
+    fn commit(&mut self);
+
+    /// Throw away the staged messages
+    fn abort(&mut self);
+}
+
+struct Part<M: Send + Sync> {
+    id: usize,
+    inbox: Arc<Inbox<M>>,
+    channel: channel::Sender<M>,
+    queue: Arc<Queue<M>>,
+    sent: Arc<AtomicUsize>
+}
+
+impl<M> Commit for Part<M>
+    where M: Send + Sync
+{
+    /// The messages are sent the same way as `SystemHandle::send`
+    /// sends them, so they are coalesced and limited by backpressure
+    fn commit(&mut self) {
+        for m in self.inbox.release(self.id) {
+            ::deliver(&mut self.channel, &self.inbox, &self.queue, &self.sent, m);
+        }
+    }
+
+    fn abort(&mut self) {
+        self.inbox.release(self.id);
+    }
+}
+
+/// Collects writes to several systems and sends them together. Every
+/// system must be staged from the same frame, so either all of the
+/// writes are seen on the following frame or none of them are.
+///
+/// While a handle is staged any message sent through it, or through
+/// any other handle of the same frame, is held back by the transaction.
+/// Dropping the transaction without committing it throws the held
+/// messages away. A staged system will not finish its next frame until
+/// the transaction is committed or dropped.
+pub struct Transaction {
+    id: usize,
+    frame: Option<u64>,
+    error: Option<TransactionError>,
+    parts: Vec<Box<Commit>>
+}
+
+impl Transaction {
+    pub fn new() -> Transaction {
+        Transaction {
+            id: NEXT_ID.fetch_add(1, Ordering::SeqCst),
+            frame: None,
+            error: None,
+            parts: Vec::new()
+        }
+    }
+
+    /// Hold back the messages sent through `handle` until the
+    /// transaction is committed. The handle is staged even if it
+    /// can't be part of the transaction, so that none of its
+    /// messages are sent when the commit fails.
+    pub fn stage<M, D>(&mut self, handle: &mut SystemHandle<M, D>) -> &mut Transaction
+        where M: Send + Sync + 'static,
+              D: Send + Sync
+    {
+        let found = handle.frame_number();
+        let expected = *self.frame.get_or_insert(found);
+        let free = handle.inbox.stage(self.id);
+        self.parts.push(Box::new(Part{
+            id: self.id,
+            inbox: handle.inbox.clone(),
+            channel: handle.channel.clone(),
+            queue: handle.queue.clone(),
+            sent: handle.sent.clone()
+        }));
+
+        if self.error.is_some() {
+            return self;
+        }
+        if expected != found {
+            self.error = Some(TransactionError::FrameMismatch{
+                system: handle.name(),
+                expected: expected,
+                found: found
+            });
+        } else if !free {
+            self.error = Some(TransactionError::AlreadyStaged(handle.name()));
+        }
+        self
+    }
+
+    /// The frame the staged systems were on, the writes will be
+    /// seen on the frame after it
+    pub fn frame(&self) -> Option<u64> {
+        self.frame
+    }
+
+    /// Send every staged message. If any of the systems could not be
+    /// staged nothing is sent to any of them and the error is returned.
+    pub fn commit(mut self) -> Result<(), TransactionError> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+
+        for part in self.parts.iter_mut() {
+            part.commit();
+        }
+        self.parts.clear();
+        Ok(())
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        for part in self.parts.iter_mut() {
+            part.abort();
+        }
+    }
+}
@@ -6,14 +6,20 @@ extern crate cgmath;
 extern crate pulse;
 extern crate system;
 extern crate ordered_vec;
+extern crate constraint;
+extern crate shared_future;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, Condvar};
+use std::mem;
 use entity::*;
-use parent::{parent, ParentSystem, Parent};
+use parent::{parent, ParentSystem, Parent, Order};
+use constraint::{ConstraintData, ConstraintSystem};
 use engine::fibe::*;
 use snowstorm::channel::*;
 use cgmath::*;
 use ordered_vec::OrderedVec;
+use system::{Transaction, TransactionError};
 
 type Message = Operation<Entity, Local>;
 
@@ -21,7 +27,9 @@ type Message = Operation<Entity, Local>;
 struct TransformEntry {
     dirty: bool,
     local: Local,
-    world: World
+    world: World,
+    // the translation of `world`, summed in double precision
+    position: Vector3<f64>
 }
 
 
@@ -29,19 +37,109 @@ struct TransformEntry {
 pub struct TransformData {
     // All entities that need to be updated 
     entries: OrderedVec<Entity, TransformEntry>,
+    /// The entities whose `World` changed this frame, this
+    /// includes entities that got a transform this frame
+    pub changed: HashSet<Entity>,
+    /// The entities whose transform was deleted this frame
+    pub removed: HashSet<Entity>,
+    velocity: HashMap<Entity, Velocity>
 }
 
-// Recursively adds all eid + all children of eid to the dirty set
+// Adds eid + all children of eid to the dirty set. A child that is
+// already dirty has already had its children marked and is skipped.
 fn mark_dirty(solved: &mut OrderedVec<Entity, TransformEntry>,
               parent: &ParentSystem,
               eid: Entity) {
 
     solved.get_mut(&eid).map(|e| { e.dirty = true; });
-    if let Some(children) = parent.parent_to_children.get(&eid).map(|x| x.clone()) {
-        for &child in children.iter() {
-            mark_dirty(solved, parent, child);
+    let mut stack = vec![eid];
+    while let Some(eid) = stack.pop() {
+        for child in parent.children(eid) {
+            if let Some(e) = solved.get_mut(&child) {
+                if e.dirty {
+                    continue;
+                }
+                e.dirty = true;
+            }
+            stack.push(child);
         }
-    }   
+    }
+}
+
+/// Levels larger then this are split up and solved across tasks
+const CHUNK: usize = 1024;
+
+/// An entity, the world and position of its parent and its local transform
+type Solve = (Entity, Matrix4<f32>, Vector3<f64>, Local);
+type Solved = (Entity, Matrix4<f32>, Vector3<f64>);
+
+fn solve((eid, parent, position, local): Solve) -> Solved {
+    let local = local.to_mat();
+    let world = parent.mul_m(&local);
+
+    // the rotation and scale of the parent are applied to the local
+    // translation, it is only added to the parent's position once it
+    // is in double precision so it is not rounded to the world's f32
+    let d = parent.mul_v(&Vector4::new(local.w.x, local.w.y, local.w.z, 0.));
+    let position = Vector3::new(position.x + d.x as f64,
+                                position.y + d.y as f64,
+                                position.z + d.z as f64);
+    (eid, world, position)
+}
+
+/// A level of the hierarchy that is being solved
+struct Level {
+    chunks: Mutex<Vec<Vec<Solve>>>,
+    done: Mutex<(usize, Vec<Solved>)>,
+    finished: Condvar
+}
+
+impl Level {
+    /// Solve chunks until there are none left
+    fn drain(&self) {
+        loop {
+            let chunk = if let Some(chunk) = self.chunks.lock().unwrap().pop() {
+                chunk
+            } else {
+                return;
+            };
+
+            let solved: Vec<Solved> = chunk.into_iter().map(solve).collect();
+
+            let mut done = self.done.lock().unwrap();
+            done.0 += 1;
+            done.1.extend(solved.into_iter());
+            self.finished.notify_all();
+        }
+    }
+}
+
+/// Solve every entity of a level, the chunks are shared with the tasks
+/// so that this thread can solve all of them if no task gets to run
+fn solve_level(level: Vec<Solve>, sched: &mut Schedule) -> Vec<Solved> {
+    if level.len() <= CHUNK {
+        return level.into_iter().map(solve).collect();
+    }
+
+    let chunks: Vec<Vec<Solve>> = level.chunks(CHUNK).map(|c| c.to_vec()).collect();
+    let count = chunks.len();
+    let level = Arc::new(Level {
+        chunks: Mutex::new(chunks),
+        done: Mutex::new((0, Vec::new())),
+        finished: Condvar::new()
+    });
+
+    for _ in 1..count {
+        let level = level.clone();
+        task(move |_| level.drain()).start(sched);
+    }
+    level.drain();
+
+    let mut done = level.done.lock().unwrap();
+    while done.0 < count {
+        done = level.finished.wait(done).unwrap();
+    }
+    mem::replace(&mut done.1, Vec::new())
 }
 
 impl TransformData {
@@ -55,14 +153,67 @@ impl TransformData {
         self.entries.get(&eid).map(|e| &e.world)
     }
 
+    /// Get the translation of the world transform of the entity in
+    /// double precision. The `World` is only single precision, far from
+    /// the origin use this to find where the entity is.
+    pub fn position(&self, eid: Entity) -> Option<Vector3<f64>> {
+        self.entries.get(&eid).map(|e| e.position)
+    }
+
+    /// Get how far `eid` moved since the last frame. This is zero if
+    /// the entity did not move or only got its transform this frame.
+    pub fn velocity(&self, eid: Entity) -> Option<Velocity> {
+        if self.entries.get(&eid).is_none() {
+            return None;
+        }
+        Some(self.velocity.get(&eid).cloned().unwrap_or(Velocity::zero()))
+    }
+
+    /// Get the `Local` that keeps `eid` at its current `World` once it is
+    /// moved to `parent`. Returns None if `eid` has no transform, there is
+    /// nothing to keep in place. A new parent without a transform, or with
+    /// a world that cannot be inverted, is an error.
+    pub fn reparent_local(&self, eid: Entity, parent: Parent) -> Result<Option<Local>, ReparentError> {
+        let world = if let Some(w) = self.world(eid) {
+            w.0
+        } else {
+            return Ok(None);
+        };
+
+        match parent {
+            Parent::Root => Ok(Some(Local::from_mat(world))),
+            Parent::Child(p) => {
+                let parent = match self.world(p) {
+                    Some(w) => w.0,
+                    None => return Err(ReparentError::NoTransform(p))
+                };
+                match parent.invert() {
+                    Some(inv) => Ok(Some(Local::from_mat(inv.mul_m(&world)))),
+                    None => Err(ReparentError::Singular(p))
+                }
+            }
+        }
+    }
+
     fn new() -> TransformData {
         TransformData {
-            entries: OrderedVec::new()
+            entries: OrderedVec::new(),
+            changed: HashSet::new(),
+            removed: HashSet::new(),
+            velocity: HashMap::new()
         }
     }
 
     fn apply_ingest(&mut self, old: &TransformData, parent: &ParentSystem, msg: &[Operation<Entity, TransformEntry>]) {
         let mut invalidate = HashMap::new();
+        self.removed.clear();
+        for m in msg {
+            if let &Operation::Delete(eid) = m {
+                if old.entries.get(&eid).is_some() {
+                    self.removed.insert(eid);
+                }
+            }
+        }
         self.entries.clone_from_and_update(&old.entries, msg.iter().map(|x| x.clone()));
         for m in msg {
             invalidate.insert(*m.key(), None);
@@ -76,121 +227,546 @@ impl TransformData {
         }
     }
 
-    fn update(&mut self, parent: &ParentSystem) {
-        fn solve (parent: &ParentSystem,
-                  entries: &mut OrderedVec<Entity, TransformEntry>,
-                  eid: Entity) ->  Decomposed<f32, Vector3<f32>, Quaternion<f32>> {
+    // The world and position of the parent of `eid` and the local of `eid`
+    fn input(&self, parent: &ParentSystem, eid: Entity) -> Solve {
+        let local = self.entries.get(&eid).unwrap().local;
+        let (world, position) = match parent.child_to_parent.get(&eid) {
+            Some(&Parent::Child(p)) => {
+                self.entries.get(&p)
+                    .map(|e| (e.world.0, e.position))
+                    .unwrap_or((Matrix4::identity(), Vector3::new(0., 0., 0.)))
+            }
+            _ => (Matrix4::identity(), Vector3::new(0., 0., 0.))
+        };
+        (eid, world, position, local)
+    }
+
+    /// Solve every dirty entity. The dirty entities are sorted into levels,
+    /// a level only depends on the levels before it so every entity in it
+    /// can be solved in parallel.
+    ///
+    /// The constraints are applied once the hierarchy was solved, they
+    /// replace the `World` of the constrained entities and the descendants
+    /// of those entities are solved again. The entities are visited from
+    /// the top of the hierarchy down, so a constraint sees the constrained
+    /// worlds of the entities above it.
+    fn update(&mut self,
+              old: &TransformData,
+              parent: &ParentSystem,
+              constraints: Option<&ConstraintData>,
+              sched: &mut Schedule) {
+        self.changed.clear();
+        self.velocity.clear();
+
+        let dirty: HashSet<Entity> = self.entries.iter()
+            .filter(|&(_, v)| v.dirty)
+            .map(|(k, _)| *k)
+            .collect();
 
-            if let Some(v) = entries.get(&eid) {
-                if !v.dirty {
-                    return v.world.0;
+        // the level of an entity is the number of dirty ancestors it has
+        let mut level: HashMap<Entity, usize> = HashMap::new();
+        let mut levels: Vec<Vec<Entity>> = Vec::new();
+        for &eid in dirty.iter() {
+            let mut chain = Vec::new();
+            let mut current = eid;
+            let mut next = loop {
+                if let Some(&l) = level.get(&current) {
+                    break l + 1;
                 }
-            } else {
-                return Decomposed::identity();
+                chain.push(current);
+                match parent.child_to_parent.get(&current) {
+                    Some(&Parent::Child(p)) if dirty.contains(&p) => current = p,
+                    _ => break 0
+                }
+            };
+
+            for &e in chain.iter().rev() {
+                level.insert(e, next);
+                while levels.len() <= next {
+                    levels.push(Vec::new());
+                }
+                levels[next].push(e);
+                next += 1;
             }
+        }
 
-            // check to see if I have a parent
-            let parent = if let Some(Parent::Child(p)) = parent.child_to_parent.get(&eid).map(|x| *x) {
-                solve(parent, entries, p)
-            } else {
-                Decomposed::identity()
-            };
+        for eids in levels {
+            let work: Vec<Solve> = eids.into_iter().map(|eid| self.input(parent, eid)).collect();
 
-            let v = entries.get_mut(&eid).unwrap();
-            v.world.0 = parent.concat(&v.local.0);
-            v.dirty = false;
-            v.world.0
-        };
+            for (eid, world, position) in solve_level(work, sched) {
+                let v = self.entries.get_mut(&eid).unwrap();
+                v.world.0 = world;
+                v.position = position;
+                v.dirty = false;
+            }
+        }
 
-        let keys: Vec<Entity> = self.entries.iter()
-            .filter(|&(_, v)| v.dirty)
-            .map(|(k, _)| *k)
+        let mut solved = dirty;
+        if let Some(constraints) = constraints {
+            self.constrain(parent, constraints, &mut solved);
+        }
+
+        for eid in solved {
+            let world = self.entries.get(&eid).unwrap().world.0;
+            match old.world(eid) {
+                Some(last) if last.0 == world => (),
+                Some(last) => {
+                    self.velocity.insert(eid, Velocity::between(&last.0, &world));
+                    self.changed.insert(eid);
+                }
+                None => { self.changed.insert(eid); }
+            }
+        }
+    }
+
+    // Apply the constraints and solve the descendants of the constrained
+    // entities, every entity that was solved is added to `solved`
+    fn constrain(&mut self,
+                 parent: &ParentSystem,
+                 constraints: &ConstraintData,
+                 solved: &mut HashSet<Entity>) {
+        let mut affected = HashSet::new();
+        for (&eid, _) in constraints.constraints.iter() {
+            if self.entries.get(&eid).is_none() || !affected.insert(eid) {
+                continue;
+            }
+            for child in parent.descendants(eid, Order::DepthFirst) {
+                if self.entries.get(&child).is_some() {
+                    affected.insert(child);
+                }
+            }
+        }
+
+        let mut order: Vec<(usize, Entity)> = affected.into_iter()
+            .map(|eid| (parent.depth(eid).unwrap_or(0), eid))
             .collect();
+        order.sort();
+
+        for (_, eid) in order {
+            let (eid, mut world, mut position) = solve(self.input(parent, eid));
+            if let Some(c) = constraints.constraints.get(&eid) {
+                let constrained = c.apply(&world, |t| self.world(t).map(|w| w.0));
+
+                // the position is the parent's position plus the local
+                // offset, only the distance the constraint moved the
+                // entity is added so the double precision is kept
+                position = Vector3::new(position.x + (constrained.w.x - world.w.x) as f64,
+                                        position.y + (constrained.w.y - world.w.y) as f64,
+                                        position.z + (constrained.w.z - world.w.z) as f64);
+                world = constrained;
+            }
 
-        for eid in keys {
-            solve(parent, &mut self.entries, eid);
+            let v = self.entries.get_mut(&eid).unwrap();
+            v.world.0 = world;
+            v.position = position;
+            v.dirty = false;
+            solved.insert(eid);
         }
     }
 }
 
 
+/// A transform relative to the parent of the entity
 #[derive(Debug, Clone, Copy)]
-pub struct Local(pub Decomposed<f32, Vector3<f32>, Quaternion<f32>>);
+pub enum Local {
+    /// A translation, rotation and uniform scale
+    Decomposed(Decomposed<f32, Vector3<f32>, Quaternion<f32>>),
+    /// A translation, rotation and a scale for each axis. The
+    /// scale is applied first then the rotation and translation.
+    Scaled {
+        scale: Vector3<f32>,
+        rot: Quaternion<f32>,
+        disp: Vector3<f32>
+    },
+    /// Any affine transform, this can include shear
+    Matrix(Matrix4<f32>)
+}
+
+/// How far apart two values can be and still be treated as equal
+/// when a matrix is decomposed
+const EPSILON: f32 = 1e-5;
+
+// Split `m` into a scale, rotation and translation. Returns None if
+// `m` has shear, mirrors an axis or is not affine.
+fn decompose(m: &Matrix4<f32>) -> Option<(Vector3<f32>, Quaternion<f32>, Vector3<f32>)> {
+    if m.x.w.abs() > EPSILON || m.y.w.abs() > EPSILON ||
+       m.z.w.abs() > EPSILON || (m.w.w - 1.).abs() > EPSILON {
+        return None;
+    }
+
+    let x = m.x.truncate();
+    let y = m.y.truncate();
+    let z = m.z.truncate();
+    let scale = Vector3::new(x.length(), y.length(), z.length());
+    if scale.x < EPSILON || scale.y < EPSILON || scale.z < EPSILON {
+        return None;
+    }
+
+    let (x, y, z) = (x.div_s(scale.x), y.div_s(scale.y), z.div_s(scale.z));
+    if x.dot(&y).abs() > EPSILON || y.dot(&z).abs() > EPSILON ||
+       z.dot(&x).abs() > EPSILON || x.cross(&y).dot(&z) < 0. {
+        return None;
+    }
+
+    // r[row][col], the columns are the axes
+    let r = [[x.x, y.x, z.x],
+             [x.y, y.y, z.y],
+             [x.z, y.z, z.z]];
+    let trace = r[0][0] + r[1][1] + r[2][2];
+    let rot = if trace > 0. {
+        let s = (trace + 1.).sqrt() * 2.;
+        Quaternion::new(0.25 * s,
+                        (r[2][1] - r[1][2]) / s,
+                        (r[0][2] - r[2][0]) / s,
+                        (r[1][0] - r[0][1]) / s)
+    } else if r[0][0] > r[1][1] && r[0][0] > r[2][2] {
+        let s = (1. + r[0][0] - r[1][1] - r[2][2]).sqrt() * 2.;
+        Quaternion::new((r[2][1] - r[1][2]) / s,
+                        0.25 * s,
+                        (r[0][1] + r[1][0]) / s,
+                        (r[0][2] + r[2][0]) / s)
+    } else if r[1][1] > r[2][2] {
+        let s = (1. + r[1][1] - r[0][0] - r[2][2]).sqrt() * 2.;
+        Quaternion::new((r[0][2] - r[2][0]) / s,
+                        (r[0][1] + r[1][0]) / s,
+                        0.25 * s,
+                        (r[1][2] + r[2][1]) / s)
+    } else {
+        let s = (1. + r[2][2] - r[0][0] - r[1][1]).sqrt() * 2.;
+        Quaternion::new((r[1][0] - r[0][1]) / s,
+                        (r[0][2] + r[2][0]) / s,
+                        (r[1][2] + r[2][1]) / s,
+                        0.25 * s)
+    };
+
+    Some((scale, rot, m.w.truncate()))
+}
+
+// True if every axis is scaled by the same amount
+fn uniform(scale: &Vector3<f32>) -> bool {
+    let max = scale.x.max(scale.y).max(scale.z);
+    (scale.x - scale.y).abs() <= EPSILON * max &&
+    (scale.y - scale.z).abs() <= EPSILON * max
+}
+
+impl Local {
+    /// Create the simplest `Local` that represents `m`. A matrix
+    /// without shear is turned into a `Decomposed` if it has a uniform
+    /// scale or a `Scaled` if it does not, anything else is kept as
+    /// a `Matrix`.
+    pub fn from_mat(m: Matrix4<f32>) -> Local {
+        match decompose(&m) {
+            Some((scale, rot, disp)) if uniform(&scale) => {
+                Local::Decomposed(Decomposed{scale: scale.x, rot: rot, disp: disp})
+            }
+            Some((scale, rot, disp)) => Local::Scaled{scale: scale, rot: rot, disp: disp},
+            None => Local::Matrix(m)
+        }
+    }
 
+    pub fn to_mat(&self) -> Matrix4<f32> {
+        match self {
+            &Local::Decomposed(d) => From::from(d),
+            &Local::Scaled{scale, rot, disp} => {
+                let r: Matrix3<f32> = From::from(rot);
+                Matrix4::new(
+                    r.x.x * scale.x, r.x.y * scale.x, r.x.z * scale.x, 0.,
+                    r.y.x * scale.y, r.y.y * scale.y, r.y.z * scale.y, 0.,
+                    r.z.x * scale.z, r.z.y * scale.z, r.z.z * scale.z, 0.,
+                    disp.x, disp.y, disp.z, 1.
+                )
+            }
+            &Local::Matrix(m) => m
+        }
+    }
+
+    /// Get the transform as a `Decomposed` if it only has a uniform scale
+    /// and no shear, this is what is needed to interpolate it
+    pub fn decomposed(&self) -> Option<Decomposed<f32, Vector3<f32>, Quaternion<f32>>> {
+        let (scale, rot, disp) = match self {
+            &Local::Decomposed(d) => return Some(d),
+            &Local::Scaled{scale, rot, disp} => (scale, rot, disp),
+            &Local::Matrix(ref m) => {
+                if let Some(d) = decompose(m) {
+                    d
+                } else {
+                    return None;
+                }
+            }
+        };
+
+        if uniform(&scale) {
+            Some(Decomposed{scale: scale.x, rot: rot, disp: disp})
+        } else {
+            None
+        }
+    }
+}
+
+impl From<Decomposed<f32, Vector3<f32>, Quaternion<f32>>> for Local {
+    fn from(d: Decomposed<f32, Vector3<f32>, Quaternion<f32>>) -> Local {
+        Local::Decomposed(d)
+    }
+}
+
+/// The transform of an entity relative to the world, this is
+/// the `Local` transforms of the entity and its ancestors
 #[derive(Debug, Clone, Copy)]
-pub struct World(pub Decomposed<f32, Vector3<f32>, Quaternion<f32>>);
+pub struct World(pub Matrix4<f32>);
 
 impl World {
     pub fn to_mat(&self) -> Matrix4<f32> {
-        From::from(self.0)
+        self.0
     }
 }
 
-// Reads from the parent channel
-fn sync_ingest(ingest: &mut system::channel::Receiver<Message>) -> Vec<Operation<Entity, TransformEntry>> {
-    let mut msgs: Vec<Operation<Entity, TransformEntry>> = Vec::new();
-    while let Ok(op) = ingest.recv() {
-        msgs.push(match op {
-            &Operation::Upsert(eid, local) => {
+/// How far an entity moved between two frames. The velocity is
+/// measured per frame, divide it by the frame's time step to get
+/// the velocity per second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Velocity {
+    /// The change in the world position
+    pub linear: Vector3<f32>,
+    /// The change in the world rotation as an axis scaled by
+    /// the angle in radians
+    pub angular: Vector3<f32>
+}
+
+impl Velocity {
+    pub fn zero() -> Velocity {
+        Velocity {
+            linear: Vector3::new(0., 0., 0.),
+            angular: Vector3::new(0., 0., 0.)
+        }
+    }
+
+    /// The velocity that moves `from` to `to`, any scale or
+    /// shear is ignored when finding the rotation
+    pub fn between(from: &Matrix4<f32>, to: &Matrix4<f32>) -> Velocity {
+        let a = rotation(from);
+        let b = rotation(to);
+
+        // the rotation from a to b is b * transpose(a), r[row][col]
+        let mut r = [[0f32; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                for k in 0..3 {
+                    r[i][j] += b[i][k] * a[j][k];
+                }
+            }
+        }
+
+        let cos = ((r[0][0] + r[1][1] + r[2][2] - 1.) / 2.).max(-1.).min(1.);
+        let angle = cos.acos();
+        let axis = Vector3::new(r[2][1] - r[1][2], r[0][2] - r[2][0], r[1][0] - r[0][1]);
+        let sin = angle.sin();
+        let angular = if angle < 1e-6 {
+            Vector3::new(0., 0., 0.)
+        } else if sin < 1e-6 {
+            // a half turn, the axis is the column of r + I that is not zero
+            let mut best = 0;
+            for i in 1..3 {
+                if r[i][i] > r[best][best] {
+                    best = i;
+                }
+            }
+            let col = Vector3::new(
+                r[0][best] + if best == 0 { 1. } else { 0. },
+                r[1][best] + if best == 1 { 1. } else { 0. },
+                r[2][best] + if best == 2 { 1. } else { 0. }
+            );
+            col.normalize().mul_s(angle)
+        } else {
+            axis.mul_s(angle / (2. * sin))
+        };
+
+        Velocity {
+            linear: Vector3::new(to.w.x - from.w.x, to.w.y - from.w.y, to.w.z - from.w.z),
+            angular: angular
+        }
+    }
+}
+
+// The rotation part of `m` as rows, each axis is normalized to remove the scale
+fn rotation(m: &Matrix4<f32>) -> [[f32; 3]; 3] {
+    let mut r = [[0f32; 3]; 3];
+    for (j, c) in [m.x, m.y, m.z].iter().enumerate() {
+        let len = (c.x * c.x + c.y * c.y + c.z * c.z).sqrt();
+        let len = if len == 0. { 1. } else { len };
+        r[0][j] = c.x / len;
+        r[1][j] = c.y / len;
+        r[2][j] = c.z / len;
+    }
+    r
+}
+
+// Turn the messages into entries that need to be solved
+fn entries(msgs: Vec<Message>) -> Vec<Operation<Entity, TransformEntry>> {
+    msgs.into_iter().map(|op| {
+        match op {
+            Operation::Upsert(eid, local) => {
                 Operation::Upsert(eid, TransformEntry{
                     dirty: true,
                     local: local,
-                    world: World(Decomposed::identity())
+                    world: World(Matrix4::identity()),
+                    position: Vector3::new(0., 0., 0.)
                 })
             }
-            &Operation::Delete(eid) => Operation::Delete(eid)
-        });
-    }
-    msgs
+            Operation::Delete(eid) => Operation::Delete(eid)
+        }
+    }).collect()
 }
 
 pub fn transform(sched: &mut Schedule, parents: ParentSystem) -> TransformSystem {
+    start(sched, parents, None)
+}
+
+/// Create a transform system that applies the constraints of
+/// `constraints` to the solved transforms. `constraints` must follow
+/// the same `parents`.
+pub fn constrained(sched: &mut Schedule,
+                   parents: ParentSystem,
+                   constraints: ConstraintSystem) -> TransformSystem {
+    start(sched, parents, Some(constraints))
+}
+
+/// Create the transform system without starting it, the step
+/// depends on the step of `parents`
+pub fn transform_step(parents: ParentSystem) -> (TransformSystem, system::schedule::Step) {
+    let (system, handle, mut solver) = create(&parents, None);
+    (handle, system.follow_step(parents, move |t, old, msgs, p, sched| solver.update(t, old, msgs, p, sched)))
+}
+
+/// Create the constrained transform system without starting it, the
+/// step depends on the steps of `parents` and of `constraints`
+pub fn constrained_step(parents: ParentSystem,
+                        constraints: ConstraintSystem) -> (TransformSystem, system::schedule::Step) {
+    let (system, handle, mut solver) = create(&parents, Some(constraints));
+    (handle, system.follow_step(parents, move |t, old, msgs, p, sched| solver.update(t, old, msgs, p, sched)))
+}
+
+fn start(sched: &mut Schedule,
+         parents: ParentSystem,
+         constraints: Option<ConstraintSystem>) -> TransformSystem {
+    let (system, handle, mut solver) = create(&parents, constraints);
+    system.follow(sched, parents, move |t, old, msgs, p, sched| solver.update(t, old, msgs, p, sched));
+    handle
+}
+
+fn create(parents: &ParentSystem,
+          constraints: Option<ConstraintSystem>) -> (system::System<Message, TransformData>, TransformSystem, Solver) {
     let td = TransformData::new();
-    let (mut system, handle) = system::System::new(td.clone(), td);
+    let (system, handle) = system::System::following("transform", parents, td.clone(), td);
+    let solver = Solver {
+        shutdown: system.shutdown_signal(),
+        constraints: constraints.map(|c| c.next_frame())
+    };
+    (system, handle, solver)
+}
 
-    task(move |_| {
-        let mut parents = Some(parents);
-        loop {
-            let s = system.update(|mut transform, old, mut msgs| {
-                let mut p = parents.take().unwrap().next_frame().get().unwrap();
+// Applies a frame of messages and solves the transforms. The frame
+// of the constraints is released once the transforms are solved.
+struct Solver {
+    shutdown: system::Shutdown,
+    constraints: Option<shared_future::Future<ConstraintSystem>>
+}
 
-                let mut imsgs = sync_ingest(&mut msgs);
-                for &d in p.deleted.keys() {
-                    imsgs.push(Operation::Delete(d));
+impl Solver {
+    fn update(&mut self,
+              mut transform: TransformData,
+              old: &TransformData,
+              msgs: Vec<Message>,
+              p: &ParentSystem,
+              sched: &mut Schedule) -> TransformData {
+        let c = match self.constraints.take().map(|c| c.get()) {
+            Some(Ok(c)) => {
+                if c.is_shutdown() {
+                    self.shutdown.signal();
                 }
-                imsgs.sort_by(|a, b| a.key().cmp(b.key()));
-
-                transform.apply_ingest(&old, &p, &imsgs[..]);
-                transform.invalidate(&p, &p.modified);
-                transform.update(&mut p);
+                Some(c)
+            }
+            // constraint has already published its last frame
+            Some(Err(_)) => {
+                self.shutdown.signal();
+                None
+            }
+            None => None
+        };
 
-                parents = Some(p);
-                transform
-            });
-            system = if let Some(s) = s { s } else { return; };
+        let mut imsgs = entries(msgs);
+        for &d in p.deleted.keys() {
+            imsgs.push(Operation::Delete(d));
         }
-    }).start(sched);
+        imsgs.sort_by(|a, b| a.key().cmp(b.key()));
 
-    handle
+        transform.apply_ingest(old, p, &imsgs[..]);
+        transform.invalidate(p, &p.modified);
+        transform.update(old, p, c.as_ref().map(|c| &**c), sched);
+        self.constraints = c.map(|c| c.next_frame());
+        transform
+    }
+}
+
+/// Errors that are reported by `reparent`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReparentError {
+    /// The new parent has no transform
+    NoTransform(Entity),
+    /// The world of the new parent cannot be inverted
+    Singular(Entity),
+    /// The parent and the local could not be committed together
+    Transaction(TransactionError)
 }
 
-impl entity::WriteEntity<Entity, Local> for TransformSystem {
-    fn write(&mut self, eid: Entity, delta: Local) {
-        self.send(Operation::Upsert(eid, delta));
+impl From<TransactionError> for ReparentError {
+    fn from(err: TransactionError) -> ReparentError {
+        ReparentError::Transaction(err)
     }
 }
 
-impl entity::ReadEntity<Entity, Local> for TransformSystem {
+impl std::fmt::Display for ReparentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            &ReparentError::NoTransform(p) => {
+                write!(f, "the new parent {:?} has no transform", p)
+            }
+            &ReparentError::Singular(p) => {
+                write!(f, "the world of the new parent {:?} cannot be inverted", p)
+            }
+            &ReparentError::Transaction(ref err) => write!(f, "{}", err)
+        }
+    }
+}
+
+/// Move `eid` to `parent` without moving it in the world. The parent and
+/// the new `Local` are sent in a single `Transaction` so they are applied
+/// on the same frame, `parents` and `transforms` must be on the same frame.
+/// Nothing is written if the `Local` cannot be found, see `reparent_local`.
+pub fn reparent(parents: &mut ParentSystem,
+                transforms: &mut TransformSystem,
+                eid: Entity,
+                parent: Parent) -> Result<(), ReparentError> {
+    let local = try!(transforms.reparent_local(eid, parent));
+
+    let mut tx = Transaction::new();
+    tx.stage(parents).stage(transforms);
+    parents.write(eid, parent);
+    if let Some(local) = local {
+        transforms.write(eid, local);
+    }
+    Ok(try!(tx.commit()))
+}
+
+impl entity::ReadEntity<Entity, Local> for TransformData {
     fn read(&self, eid: &Entity) -> Option<&Local> {
         self.entries.get(eid).map(|x| &x.local)
     }
 }
 
-impl entity::ReadEntity<Entity, World> for TransformSystem {
+impl entity::ReadEntity<Entity, World> for TransformData {
     fn read(&self, eid: &Entity) -> Option<&World> {
         self.entries.get(eid).map(|x| &x.world)
     }
 }
 
-
 pub type TransformSystem = system::SystemHandle<Message, TransformData>;
@@ -0,0 +1,54 @@
+extern crate engine;
+extern crate parent;
+extern crate transform;
+extern crate animation;
+extern crate entity;
+extern crate cgmath;
+
+use engine::Engine;
+use entity::*;
+use parent::{parent, Parent};
+use transform::*;
+use animation::{animation, Lerp};
+use cgmath::*;
+
+fn at(x: f32, y: f32, z: f32) -> Decomposed<f32, Vector3<f32>, Quaternion<f32>> {
+    Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(x, y, z)}
+}
+
+#[test]
+fn animate_after_reparent() {
+    let mut engine = Engine::headless();
+    let input = engine.input_channel();
+    let mut parents = parent(engine.sched());
+    let mut transforms = transform(engine.sched(), parents.clone());
+    let mut anim = animation(engine.sched(), input, parents.clone(), transforms.clone());
+
+    let a = Entity::new().bind(Parent::Root).write(&mut parents);
+    let b = Entity::new().bind(Parent::Root).write(&mut parents);
+    let c = Entity::new().bind(Parent::Child(a)).write(&mut parents);
+    a.bind(Local::Decomposed(at(1., 0., 0.))).write(&mut transforms);
+    b.bind(Local::Decomposed(at(0., 2., 0.))).write(&mut transforms);
+    c.bind(Local::Decomposed(at(1., 0., 0.))).write(&mut transforms);
+
+    for i in 0..8 {
+        match i {
+            2 => reparent(&mut parents, &mut transforms, c, Parent::Child(b)).unwrap(),
+            // the lerp is already due, the next update moves c to it
+            4 => c.bind(Lerp::new(0., at(0., 0., 3.))).write(&mut anim),
+            _ => ()
+        }
+
+        engine.run_frames(1);
+        let p = parents.next_frame();
+        let an = anim.next_frame();
+        let t = transforms.next_frame();
+        parents = p.get().unwrap();
+        anim = an.get().unwrap();
+        transforms = t.get().unwrap();
+    }
+
+    assert_eq!(parents.read(&c), Some(&Parent::Child(b)));
+    assert_eq!(transforms.local(c).unwrap().to_mat().w, Vector4::new(0., 0., 3., 1.));
+    assert_eq!(transforms.world(c).unwrap().0.w, Vector4::new(0., 2., 3., 1.));
+}
@@ -0,0 +1,351 @@
+extern crate bounding;
+extern crate cgmath;
+extern crate graphics;
+extern crate gfx_mesh;
+extern crate entity;
+extern crate parent;
+extern crate transform;
+extern crate system;
+extern crate fibe;
+extern crate gfx;
+
+use std::mem;
+use std::collections::{HashMap, HashSet};
+use cgmath::*;
+use bounding::{Bounding, GeometryBinding, Sphere, BoundsError, positions, read_positions, half_to_f32};
+use gfx::attrib::{Format, Type, FloatSubType, FloatSize, IntSubType, IntSize, SignFlag};
+use graphics::{Graphics, Geometry, Primative, VertexBuffer, VertexBufferData, POSITION, TEX0};
+use gfx_mesh::{Attribute, BuildInterlaced};
+use entity::*;
+use parent::{parent, Parent, ParentSystem};
+use transform::*;
+use fibe::*;
+
+// A stand in for the renderer's draw bindings
+#[derive(Clone)]
+struct Bindings {
+    geometry: HashMap<Entity, Geometry>,
+    rebound: HashSet<Entity>
+}
+
+impl GeometryBinding for Bindings {
+    fn geometry(&self, eid: Entity) -> Option<Geometry> {
+        self.geometry.get(&eid).map(|g| *g)
+    }
+
+    fn rebound(&self) -> &HashSet<Entity> {
+        &self.rebound
+    }
+}
+
+type BindingSystem = system::SystemHandle<Operation<Entity, Geometry>, Bindings>;
+
+fn bindings(sched: &mut Schedule) -> BindingSystem {
+    let b = Bindings{geometry: HashMap::new(), rebound: HashSet::new()};
+    let (system, handle) = system::System::named("bindings", b.clone(), b);
+
+    let mut last: Vec<Operation<Entity, Geometry>> = Vec::new();
+    system.run(sched, move |mut b, _, msgs| {
+        b.rebound.clear();
+        for &m in last.iter().chain(msgs.iter()) {
+            match m {
+                Operation::Upsert(eid, geo) => { b.geometry.insert(eid, geo); }
+                Operation::Delete(eid) => { b.geometry.remove(&eid); }
+            }
+        }
+        for m in msgs.iter() {
+            b.rebound.insert(*m.key());
+        }
+        last = msgs;
+        b
+    });
+
+    handle
+}
+
+struct Systems {
+    graphics: Graphics,
+    parents: ParentSystem,
+    transforms: TransformSystem,
+    bindings: BindingSystem,
+    bounding: Bounding
+}
+
+impl Systems {
+    fn new(sched: &mut Frontend) -> Systems {
+        let graphics = Graphics::new(sched);
+        let parents = parent(sched);
+        let transforms = transform(sched, parents.clone());
+        let bindings = bindings(sched);
+        let bounding = Bounding::with_world(sched,
+                                            graphics.clone(),
+                                            parents.clone(),
+                                            transforms.clone(),
+                                            bindings.clone());
+        Systems {
+            graphics: graphics,
+            parents: parents,
+            transforms: transforms,
+            bindings: bindings,
+            bounding: bounding
+        }
+    }
+
+    fn next_frame(self) -> Systems {
+        let Systems{graphics, parents, transforms, bindings, mut bounding} = self;
+        let graphics = graphics.next_frame();
+        let parents = parents.next_frame();
+        let transforms = transforms.next_frame();
+        let bindings = bindings.next_frame();
+        bounding.next_frame();
+        Systems {
+            graphics: graphics.get().unwrap(),
+            parents: parents.get().unwrap(),
+            transforms: transforms.get().unwrap(),
+            bindings: bindings.get().unwrap(),
+            bounding: bounding
+        }
+    }
+}
+
+// A geometry whose box is from -1 to 1 on every axis
+fn unit_box(graphics: &mut Graphics) -> Geometry {
+    let vertex = [Attribute::f32(POSITION, 3)]
+        .build(vec![([-1f32, -1., -1.],), ([1., 1., 1.],), ([1., -1., 1.],)].into_iter())
+        .unwrap()
+        .owned_attributes();
+    let vb = VertexBuffer::new().bind(vertex).write(graphics);
+    Geometry::new().bind(vb.geometry(Primative::Triangle)).write(graphics)
+}
+
+// A tightly packed attribute with three elements
+fn format(ty: Type) -> Format {
+    Format {
+        elem_count: 3,
+        elem_type: ty,
+        offset: 0,
+        stride: 0,
+        instance_rate: 0
+    }
+}
+
+fn at(x: f32, y: f32, z: f32) -> Local {
+    Local::Decomposed(Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(x, y, z)})
+}
+
+fn aabb(min: [f32; 3], max: [f32; 3]) -> Aabb3<f32> {
+    Aabb3::new(Point3::new(min[0], min[1], min[2]), Point3::new(max[0], max[1], max[2]))
+}
+
+#[test]
+fn sphere_transform() {
+    let sphere = Sphere::new(Point3::new(1., 0., 0.), 2.);
+    let mat = Matrix4::new(1., 0., 0., 0.,
+                           0., 3., 0., 0.,
+                           0., 0., 2., 0.,
+                           5., 0., 0., 1.);
+    let world = sphere.transform(&mat);
+    assert_eq!(world.center, Point3::new(6., 0., 0.));
+    // grown by the largest scale so it still holds the geometry
+    assert_eq!(world.radius, 6.);
+}
+
+#[test]
+fn sphere_overlaps() {
+    let sphere = Sphere::new(Point3::new(0., 0., 0.), 1.);
+    let near = Aabb3::new(Point3::new(0.5, 0.5, -1.), Point3::new(2., 2., 1.));
+    let far = Aabb3::new(Point3::new(0.8, 0.8, 0.8), Point3::new(2., 2., 2.));
+    assert!(sphere.overlaps(&near));
+    assert!(!sphere.overlaps(&far));
+}
+
+#[test]
+fn position_by_name() {
+    // the position is not the first attribute
+    let vertex = [Attribute::f32(TEX0, 2), Attribute::f32(POSITION, 3)]
+        .build(vec![([0f32, 1.], [1f32, 2., 3.]), ([1., 0.], [4., 5., 6.])].into_iter())
+        .unwrap()
+        .owned_attributes();
+    let vb = VertexBufferData{vertex: vec![vertex], index: None};
+    assert_eq!(positions(&vb), Ok(vec![[1., 2., 3.], [4., 5., 6.]]));
+}
+
+#[test]
+fn missing_position() {
+    let vertex = [Attribute::f32(TEX0, 2)]
+        .build(vec![([0f32, 1.],)].into_iter())
+        .unwrap()
+        .owned_attributes();
+    let vb = VertexBufferData{vertex: vec![vertex], index: None};
+    assert_eq!(positions(&vb), Err(BoundsError::NoPosition));
+
+    let empty = VertexBufferData{vertex: vec![], index: None};
+    assert_eq!(positions(&empty), Err(BoundsError::NoVertices));
+}
+
+#[test]
+fn half_floats() {
+    assert_eq!(half_to_f32(0x0000), 0.);
+    assert_eq!(half_to_f32(0x3c00), 1.);
+    assert_eq!(half_to_f32(0x3800), 0.5);
+    assert_eq!(half_to_f32(0xc000), -2.);
+    assert_eq!(half_to_f32(0x7bff), 65504.);
+    // the smallest subnormal
+    assert_eq!(half_to_f32(0x0001), (2f32).powi(-24));
+    assert_eq!(half_to_f32(0x7c00), std::f32::INFINITY);
+    assert_eq!(half_to_f32(0xfc00), std::f32::NEG_INFINITY);
+    assert!(half_to_f32(0x7e00).is_nan());
+
+    let f16 = format(Type::Float(FloatSubType::Default, FloatSize::F16));
+    let data = [0x00, 0x3c, 0x00, 0xc0, 0x00, 0x38];
+    assert_eq!(read_positions(&data, 1, &f16), Ok(vec![[1., -2., 0.5]]));
+}
+
+#[test]
+fn normalized_integers() {
+    let unorm8 = format(Type::Int(IntSubType::Normalized, IntSize::U8, SignFlag::Unsigned));
+    assert_eq!(read_positions(&[0, 255, 51], 1, &unorm8), Ok(vec![[0., 1., 0.2]]));
+
+    // the smallest value is clamped to -1
+    let snorm8 = format(Type::Int(IntSubType::Normalized, IntSize::U8, SignFlag::Signed));
+    assert_eq!(read_positions(&[0x7f, 0x81, 0x80], 1, &snorm8), Ok(vec![[1., -1., -1.]]));
+
+    let unorm16 = format(Type::Int(IntSubType::Normalized, IntSize::U16, SignFlag::Unsigned));
+    let data = [0xff, 0xff, 0x00, 0x00, 0x33, 0x33];
+    assert_eq!(read_positions(&data, 1, &unorm16), Ok(vec![[1., 0., 0.2]]));
+
+    let snorm16 = format(Type::Int(IntSubType::Normalized, IntSize::U16, SignFlag::Signed));
+    let data = [0xff, 0x7f, 0x01, 0x80, 0x00, 0x00,
+                0x00, 0x80, 0x00, 0x00, 0x00, 0x00];
+    assert_eq!(read_positions(&data, 2, &snorm16), Ok(vec![[1., -1., 0.], [-1., 0., 0.]]));
+
+    // integers that are not normalized are read as they are
+    let raw16 = format(Type::Int(IntSubType::Raw, IntSize::U16, SignFlag::Signed));
+    let data = [0xfe, 0xff, 0x02, 0x00, 0x00, 0x00];
+    assert_eq!(read_positions(&data, 1, &raw16), Ok(vec![[-2., 2., 0.]]));
+
+    // the buffer is too short for a second vertex
+    assert_eq!(read_positions(&data, 2, &raw16), Err(BoundsError::OutOfRange));
+}
+
+#[test]
+fn double_positions() {
+    let f64s = format(Type::Float(FloatSubType::Default, FloatSize::F64));
+    let mut data = Vec::new();
+    for &v in [1.5f64, -2.25, 1e6].iter() {
+        let bits: u64 = unsafe { mem::transmute(v) };
+        for i in 0..8 {
+            data.push((bits >> (i * 8)) as u8);
+        }
+    }
+    assert_eq!(read_positions(&data, 1, &f64s), Ok(vec![[1.5, -2.25, 1e6]]));
+}
+
+#[test]
+fn empty_geometry() {
+    let mut sched = Frontend::new();
+    let mut graphics = Graphics::new(&mut sched);
+    let mut bounding = Bounding::new(&mut sched, graphics.clone());
+
+    let vertex = [Attribute::f32(POSITION, 3)]
+        .build(vec![([0f32, 0., 0.],)].into_iter())
+        .unwrap()
+        .owned_attributes();
+    let vb = VertexBuffer::new().bind(vertex).write(&mut graphics);
+    let empty = Geometry::new().bind(vb.subbuffer(0, 0).geometry(Primative::Triangle)).write(&mut graphics);
+
+    let graphics = graphics.next_frame();
+    bounding.next_frame();
+    graphics.get().unwrap();
+    assert_eq!(bounding.errors.get(&empty), Some(&BoundsError::Empty));
+    assert_eq!(bounding.aabb.get(&empty), None);
+}
+
+#[test]
+fn world_bounds_update() {
+    let mut sched = Frontend::new();
+    let mut s = Systems::new(&mut sched);
+
+    let geo = unit_box(&mut s.graphics);
+    let a = Entity::new().bind(Parent::Root).write(&mut s.parents);
+    let b = Entity::new().bind(Parent::Root).write(&mut s.parents);
+    a.bind(at(5., 0., 0.)).write(&mut s.transforms);
+    b.bind(at(0., 0., 0.)).write(&mut s.transforms);
+    a.bind(geo).write(&mut s.bindings);
+    b.bind(geo).write(&mut s.bindings);
+
+    let mut s = s.next_frame();
+    assert_eq!(s.bounding.world.aabb(a), Some(&aabb([4., -1., -1.], [6., 1., 1.])));
+    assert_eq!(s.bounding.world.aabb(b), Some(&aabb([-1., -1., -1.], [1., 1., 1.])));
+    assert!(s.bounding.world.changed.contains(&a));
+
+    // moving the entity moves its bounds
+    a.bind(at(0., 2., 0.)).write(&mut s.transforms);
+    let mut s = s.next_frame();
+    assert_eq!(s.bounding.world.aabb(a), Some(&aabb([-1., 1., -1.], [1., 3., 1.])));
+    assert_eq!(s.bounding.world.sphere(a).map(|s| s.center), Some(Point3::new(0., 2., 0.)));
+    assert!(s.bounding.world.changed.contains(&a));
+    assert!(!s.bounding.world.changed.contains(&b));
+
+    // without a geometry there are no bounds
+    s.bindings.delete(a);
+    let mut s = s.next_frame();
+    assert_eq!(s.bounding.world.aabb(a), None);
+    assert_eq!(s.bounding.world.sphere(a), None);
+    assert!(s.bounding.world.changed.contains(&a));
+
+    // nor without a transform
+    s.parents.delete(b);
+    let s = s.next_frame();
+    assert_eq!(s.bounding.world.aabb(b), None);
+    assert!(s.bounding.world.changed.contains(&b));
+
+    let s = s.next_frame();
+    assert!(s.bounding.world.changed.is_empty());
+}
+
+#[test]
+fn subtree_bounds() {
+    let mut sched = Frontend::new();
+    let mut s = Systems::new(&mut sched);
+
+    let geo = unit_box(&mut s.graphics);
+    let root = Entity::new().bind(Parent::Root).write(&mut s.parents);
+    let other = Entity::new().bind(Parent::Root).write(&mut s.parents);
+    let left = Entity::new().bind(Parent::Child(root)).write(&mut s.parents);
+    let right = Entity::new().bind(Parent::Child(root)).write(&mut s.parents);
+    let leaf = Entity::new().bind(Parent::Child(right)).write(&mut s.parents);
+    root.bind(at(0., 0., 0.)).write(&mut s.transforms);
+    other.bind(at(0., 0., 0.)).write(&mut s.transforms);
+    left.bind(at(-5., 0., 0.)).write(&mut s.transforms);
+    right.bind(at(5., 0., 0.)).write(&mut s.transforms);
+    leaf.bind(at(0., 5., 0.)).write(&mut s.transforms);
+    left.bind(geo).write(&mut s.bindings);
+    right.bind(geo).write(&mut s.bindings);
+    leaf.bind(geo).write(&mut s.bindings);
+
+    let mut s = s.next_frame();
+    assert_eq!(s.bounding.world.subtree(root), Some(&aabb([-6., -1., -1.], [6., 6., 1.])));
+    assert_eq!(s.bounding.world.subtree(right), Some(&aabb([4., -1., -1.], [6., 6., 1.])));
+    assert_eq!(s.bounding.world.subtree(leaf), Some(&aabb([4., 4., -1.], [6., 6., 1.])));
+    assert_eq!(s.bounding.world.subtree(other), None);
+
+    // a move is seen by every ancestor
+    leaf.bind(at(0., 10., 0.)).write(&mut s.transforms);
+    let mut s = s.next_frame();
+    assert_eq!(s.bounding.world.subtree(root), Some(&aabb([-6., -1., -1.], [6., 11., 1.])));
+    assert_eq!(s.bounding.world.subtree(right), Some(&aabb([4., -1., -1.], [6., 11., 1.])));
+
+    // a reparented subtree leaves its old parent for the new one
+    right.bind(Parent::Child(other)).write(&mut s.parents);
+    let mut s = s.next_frame();
+    assert_eq!(s.bounding.world.subtree(root), Some(&aabb([-6., -1., -1.], [-4., 1., 1.])));
+    assert_eq!(s.bounding.world.subtree(other), Some(&aabb([4., -1., -1.], [6., 11., 1.])));
+
+    // a deleted subtree is removed from its parent's box
+    s.parents.delete(left);
+    let s = s.next_frame();
+    assert_eq!(s.bounding.world.subtree(root), None);
+    assert_eq!(s.bounding.world.subtree(left), None);
+    assert_eq!(s.bounding.world.subtree(other), Some(&aabb([4., -1., -1.], [6., 11., 1.])));
+}
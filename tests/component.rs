@@ -0,0 +1,86 @@
+extern crate parent;
+extern crate system;
+extern crate fibe;
+extern crate entity;
+
+use entity::*;
+use parent::{parent, Parent, ParentSystem};
+use system::{component, ComponentSystem};
+use fibe::*;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Health(u32);
+
+fn next_frame(parent: ParentSystem, health: ComponentSystem<Health>)
+    -> (ParentSystem, ComponentSystem<Health>) {
+
+    let p = parent.next_frame();
+    let h = health.next_frame();
+    (p.get().unwrap(), h.get().unwrap())
+}
+
+#[test]
+fn write_read() {
+    let mut sched = Frontend::new();
+    let mut parent = parent(&mut sched);
+    let mut health = component(&mut sched, "health", parent.clone());
+
+    let eid = Entity::new().bind(Health(10)).write(&mut health);
+    let (p, h) = next_frame(parent, health);
+    parent = p;
+    health = h;
+
+    assert_eq!(health.read(&eid), Some(&Health(10)));
+    assert_eq!(health.changed.get(&eid), Some(&Health(10)));
+
+    let (_, h) = next_frame(parent, health);
+    health = h;
+
+    assert_eq!(health.read(&eid), Some(&Health(10)));
+    assert_eq!(health.changed.get(&eid), None);
+}
+
+#[test]
+fn delete() {
+    let mut sched = Frontend::new();
+    let mut parent = parent(&mut sched);
+    let mut health = component(&mut sched, "health", parent.clone());
+
+    let eid = Entity::new().bind(Health(10)).write(&mut health);
+    let (p, h) = next_frame(parent, health);
+    parent = p;
+    health = h;
+
+    eid.delete(&mut health);
+    let (_, h) = next_frame(parent, health);
+    health = h;
+
+    assert_eq!(health.read(&eid), None);
+    assert!(health.deleted.contains(&eid));
+}
+
+#[test]
+fn deleted_with_parent() {
+    let mut sched = Frontend::new();
+    let mut parent = parent(&mut sched);
+    let mut health = component(&mut sched, "health", parent.clone());
+
+    let root = Entity::new().bind(Parent::Root).write(&mut parent);
+    let child = Entity::new().bind(Parent::Child(root)).write(&mut parent);
+    root.bind(Health(10)).write(&mut health);
+    child.bind(Health(5)).write(&mut health);
+
+    let (p, h) = next_frame(parent, health);
+    parent = p;
+    health = h;
+    assert_eq!(health.read(&child), Some(&Health(5)));
+
+    root.delete(&mut parent);
+    let (_, h) = next_frame(parent, health);
+    health = h;
+
+    assert_eq!(health.read(&root), None);
+    assert_eq!(health.read(&child), None);
+    assert!(health.deleted.contains(&root));
+    assert!(health.deleted.contains(&child));
+}
@@ -0,0 +1,145 @@
+extern crate parent;
+extern crate transform;
+extern crate constraint;
+extern crate fibe;
+extern crate entity;
+extern crate cgmath;
+
+use entity::*;
+use parent::{parent, Parent, ParentSystem};
+use transform::*;
+use constraint::*;
+use fibe::*;
+use cgmath::*;
+
+fn next_frame(parents: ParentSystem,
+              transforms: TransformSystem,
+              constraints: ConstraintSystem) -> (ParentSystem, TransformSystem, ConstraintSystem) {
+    let parents = parents.next_frame();
+    let transforms = transforms.next_frame();
+    let constraints = constraints.next_frame();
+    (parents.get().unwrap(), transforms.get().unwrap(), constraints.get().unwrap())
+}
+
+fn at(x: f32, y: f32, z: f32) -> Local {
+    Local::Decomposed(Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(x, y, z)})
+}
+
+fn assert_close(a: Vector4<f32>, b: Vector4<f32>) {
+    assert!((a.x - b.x).abs() < 1e-5 && (a.y - b.y).abs() < 1e-5 &&
+            (a.z - b.z).abs() < 1e-5 && (a.w - b.w).abs() < 1e-5,
+            "{:?} != {:?}", a, b);
+}
+
+#[test]
+fn look_at_and_copy_position() {
+    let mut sched = Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut constraints = constraint(&mut sched, parents.clone());
+    let mut transforms = constrained(&mut sched, parents.clone(), constraints.clone());
+
+    let target = Entity::new().bind(Parent::Root).write(&mut parents);
+    let turret = Entity::new().bind(Parent::Root).write(&mut parents);
+    let follow = Entity::new().bind(Parent::Root).write(&mut parents);
+    target.bind(at(5., 0., 0.)).write(&mut transforms);
+    turret.bind(at(0., 0., 0.)).write(&mut transforms);
+    follow.bind(at(0., 3., 0.)).write(&mut transforms);
+    turret.bind(Constraint::LookAt(target)).write(&mut constraints);
+    follow.bind(Constraint::CopyPosition(target)).write(&mut constraints);
+
+    // the constraints are applied on the same frame
+    let (p, t, c) = next_frame(parents, transforms, constraints);
+
+    // -Z points at the target
+    let world = t.world(turret).unwrap().0;
+    assert_close(world.z, Vector4::new(-1., 0., 0., 0.));
+    assert_close(world.w, Vector4::new(0., 0., 0., 1.));
+    assert_close(t.world(follow).unwrap().0.w, Vector4::new(5., 0., 0., 1.));
+
+    // the locals are left alone
+    assert_close(t.local(follow).unwrap().to_mat().w, Vector4::new(0., 3., 0., 1.));
+
+    // nothing moved so nothing changed
+    let (_, t, _) = next_frame(p, t, c);
+    assert!(!t.changed.contains(&turret));
+    assert!(!t.changed.contains(&follow));
+}
+
+#[test]
+fn orbit_with_distance_limit() {
+    let mut sched = Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut constraints = constraint(&mut sched, parents.clone());
+    let mut transforms = constrained(&mut sched, parents.clone(), constraints.clone());
+
+    let target = Entity::new().bind(Parent::Root).write(&mut parents);
+    let camera = Entity::new().bind(Parent::Root).write(&mut parents);
+    target.bind(at(0., 1., 0.)).write(&mut transforms);
+    camera.bind(at(0., 0., 0.)).write(&mut transforms);
+    camera.bind(
+        Constraints::new(Constraint::Orbit{target: target, distance: 10., yaw: 0., pitch: 0.})
+            .then(Constraint::Distance{target: target, min: 0., max: 4.})
+    ).write(&mut constraints);
+
+    let (_, t, _) = next_frame(parents, transforms, constraints);
+
+    let world = t.world(camera).unwrap().0;
+    assert_close(world.w, Vector4::new(0., 1., 4., 1.));
+    assert_close(world.z, Vector4::new(0., 0., 1., 0.));
+}
+
+#[test]
+fn children_follow_constraint() {
+    let mut sched = Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut constraints = constraint(&mut sched, parents.clone());
+    let mut transforms = constrained(&mut sched, parents.clone(), constraints.clone());
+
+    let target = Entity::new().bind(Parent::Root).write(&mut parents);
+    let follow = Entity::new().bind(Parent::Root).write(&mut parents);
+    let child = Entity::new().bind(Parent::Child(follow)).write(&mut parents);
+    target.bind(at(5., 0., 0.)).write(&mut transforms);
+    follow.bind(at(0., 0., 0.)).write(&mut transforms);
+    child.bind(at(0., 1., 0.)).write(&mut transforms);
+    follow.bind(Constraint::CopyPosition(target)).write(&mut constraints);
+
+    // the child is solved against the constrained parent
+    let (p, mut t, c) = next_frame(parents, transforms, constraints);
+    assert_close(t.world(follow).unwrap().0.w, Vector4::new(5., 0., 0., 1.));
+    assert_close(t.world(child).unwrap().0.w, Vector4::new(5., 1., 0., 1.));
+
+    // moving the target moves both on the same frame
+    target.bind(at(-2., 0., 0.)).write(&mut t);
+    let (_, t, _) = next_frame(p, t, c);
+    assert_close(t.world(follow).unwrap().0.w, Vector4::new(-2., 0., 0., 1.));
+    assert_close(t.world(child).unwrap().0.w, Vector4::new(-2., 1., 0., 1.));
+    assert!(t.changed.contains(&child));
+    assert_eq!(t.velocity(follow).unwrap().linear, Vector3::new(-7., 0., 0.));
+}
+
+#[test]
+fn constraint_keeps_double_position() {
+    let mut sched = Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut constraints = constraint(&mut sched, parents.clone());
+    let mut transforms = constrained(&mut sched, parents.clone(), constraints.clone());
+
+    // the offset of the child is lost in the f32 world
+    let far = Entity::new().bind(Parent::Root).write(&mut parents);
+    let turret = Entity::new().bind(Parent::Child(far)).write(&mut parents);
+    let child = Entity::new().bind(Parent::Child(turret)).write(&mut parents);
+    let target = Entity::new().bind(Parent::Root).write(&mut parents);
+    far.bind(at(16777216., 0., 0.)).write(&mut transforms);
+    turret.bind(at(0.5, 0., 0.)).write(&mut transforms);
+    child.bind(at(0., 0.5, 0.)).write(&mut transforms);
+    target.bind(at(16777216., 100., 0.)).write(&mut transforms);
+    turret.bind(Constraint::LookAt(target)).write(&mut constraints);
+
+    let (_, t, _) = next_frame(parents, transforms, constraints);
+    assert_eq!(t.position(turret), Some(Vector3::new(16777216.5, 0., 0.)));
+
+    // the child is solved from the turret's double position
+    let (a, b) = (t.position(turret).unwrap(), t.position(child).unwrap());
+    let d = ((b.x - a.x).powi(2) + (b.y - a.y).powi(2) + (b.z - a.z).powi(2)).sqrt();
+    assert!((d - 0.5).abs() < 1e-5, "{}", d);
+}
@@ -0,0 +1,174 @@
+extern crate engine;
+extern crate parent;
+extern crate graphics;
+
+use engine::{Engine, Clock, Tick, HEADLESS_FRAME_TIME};
+use engine::event::WindowEvent;
+
+#[test]
+fn headless_timestamps() {
+    let mut engine = Engine::headless();
+    let mut input = engine.input_channel();
+
+    assert!(engine.is_headless());
+    assert!(engine.run_frames(3));
+
+    for i in 0..3 {
+        let mut events = Vec::new();
+        while let Ok(e) = input.recv() {
+            if let &WindowEvent::TimeStamp(_) = e {
+                events.push(*e);
+            }
+        }
+        assert_eq!(events, vec![WindowEvent::TimeStamp(i as f64 * HEADLESS_FRAME_TIME)]);
+        input.next_frame();
+    }
+}
+
+#[test]
+fn headless_send_event() {
+    let mut engine = Engine::headless();
+    let mut input = engine.input_channel();
+
+    engine.send_event(WindowEvent::Focus(true));
+    engine.run_frames(1);
+
+    assert_eq!(input.recv().ok().map(|x| *x), Some(WindowEvent::Focus(true)));
+    assert_eq!(input.recv().ok().map(|x| *x), Some(WindowEvent::TimeStamp(0.)));
+}
+
+#[test]
+fn input_channel_after_run() {
+    let mut engine = Engine::headless();
+    engine.run_frames(2);
+
+    // a copy taken after the engine has run starts at the next frame
+    let mut input = engine.input_channel();
+    engine.run_frames(1);
+
+    let mut events = Vec::new();
+    while let Ok(e) = input.recv() {
+        if let &WindowEvent::TimeStamp(_) = e {
+            events.push(*e);
+        }
+    }
+    assert_eq!(events, vec![WindowEvent::TimeStamp(2. * HEADLESS_FRAME_TIME)]);
+
+    engine.start_input_processor(|_, _| ());
+}
+
+#[test]
+fn headless_render() {
+    use std::rc::Rc;
+    use std::cell::Cell;
+
+    let mut engine = Engine::headless();
+    let frames = Rc::new(Cell::new(0));
+    let f = frames.clone();
+    engine.start_headless_render(move |_| f.set(f.get() + 1));
+
+    engine.run_frames(5);
+    assert_eq!(frames.get(), 5);
+}
+
+#[test]
+fn shutdown_stops_systems() {
+    let mut engine = Engine::headless();
+    let mut parent = parent::parent(engine.sched());
+    let mut graphics = graphics::Graphics::new(engine.sched());
+    engine.on_shutdown(parent.shutdown_signal());
+    engine.on_shutdown(graphics.shutdown_signal());
+
+    assert!(engine.run_frames(1));
+    engine.shutdown();
+    assert!(engine.is_shutdown());
+    assert!(!engine.run_frames(1));
+
+    // both systems publish a last frame that says so, then stop
+    while let Ok(next) = parent.next_frame().get() {
+        parent = next;
+    }
+    assert!(parent.is_shutdown());
+    while let Ok(next) = graphics.next_frame().get() {
+        graphics = next;
+    }
+    assert!(graphics.is_shutdown());
+}
+
+#[test]
+fn headless_ticks() {
+    let mut engine = Engine::headless();
+    let mut input = engine.input_channel();
+    engine.run_frames(4);
+
+    let mut ticks = Vec::new();
+    for _ in 0..4 {
+        while let Ok(e) = input.recv() {
+            if let &WindowEvent::Tick(t) = e {
+                ticks.push(t.index);
+            }
+        }
+        input.next_frame();
+    }
+    assert_eq!(ticks, vec![0, 1, 2]);
+}
+
+#[test]
+fn clock_fixed_step() {
+    let mut clock = Clock::new(0.25);
+
+    let (ticks, alpha) = clock.advance(0.625);
+    assert_eq!(ticks, vec![Tick{index: 0, dt: 0.25, time: 0.25},
+                           Tick{index: 1, dt: 0.25, time: 0.5}]);
+    assert_eq!(alpha, 0.5);
+
+    let (ticks, _) = clock.advance(0.125);
+    assert_eq!(ticks, vec![Tick{index: 2, dt: 0.25, time: 0.75}]);
+}
+
+#[test]
+fn headless_one_tick_per_frame() {
+    let mut engine = Engine::headless();
+    let mut input = engine.input_channel();
+    engine.run_frames(600);
+
+    for i in 0..600 {
+        let mut ticks = 0;
+        while let Ok(e) = input.recv() {
+            if let &WindowEvent::Tick(_) = e {
+                ticks += 1;
+            }
+        }
+        input.next_frame();
+        assert_eq!(ticks, if i == 0 { 0 } else { 1 });
+    }
+}
+
+#[test]
+fn clock_time_after_rate_change() {
+    let mut clock = Clock::new(0.25);
+    let (ticks, _) = clock.advance(0.5);
+    assert_eq!(ticks.last().map(|t| t.time), Some(0.5));
+
+    // the time carries on from where it was, not from index * dt
+    clock.set_dt(0.5);
+    let (ticks, _) = clock.advance(0.5);
+    assert_eq!(ticks, vec![Tick{index: 2, dt: 0.5, time: 1.}]);
+}
+
+#[test]
+fn clock_pause_and_step() {
+    let mut clock = Clock::new(0.25);
+    let control = clock.control();
+
+    control.pause();
+    assert_eq!(clock.advance(1.).0.len(), 0);
+
+    control.step();
+    assert_eq!(clock.advance(1.).0, vec![Tick{index: 0, dt: 0.25, time: 0.25}]);
+    assert_eq!(clock.advance(1.).0.len(), 0);
+
+    control.resume();
+    control.set_time_scale(0.5);
+    assert_eq!(clock.advance(1.).0.len(), 2);
+}
@@ -3,11 +3,13 @@ extern crate fibe;
 #[macro_use(route, router)]
 extern crate entity;
 extern crate name;
+extern crate system;
 
 use entity::*;
 use parent::{parent, Parent, ParentSystem};
 use fibe::*;
 use name::*;
+use system::{Transaction, TransactionError};
 
 router!{
     struct Router {
@@ -381,3 +383,147 @@ fn get_names() {
     assert_eq!("foo.bar.dog", rtr.full_path(&child3).unwrap());
 
 }
+
+#[test]
+fn shutdown_follows_parent() {
+    let mut sched = Frontend::new();
+    let parent = parent(&mut sched);
+    let mut name = name(&mut sched, parent.clone());
+
+    parent.join();
+
+    // name will stop publishing frames once it sees the parent shutdown
+    while let Ok(next) = name.next_frame().get() {
+        name = next;
+    }
+    assert!(name.is_shutdown());
+}
+
+#[test]
+fn transaction() {
+    let mut sched = Frontend::new();
+    let parent = parent(&mut sched);
+    let name = name(&mut sched, parent.clone());
+
+    let mut rtr = Router{
+        name: name,
+        parent: parent
+    };
+
+    let mut tx = Transaction::new();
+    tx.stage(&mut rtr.name).stage(&mut rtr.parent);
+
+    let parent = Entity::new()
+        .bind(Name::new("foo".to_string()).unwrap())
+        .bind(Parent::Root)
+        .write(&mut rtr);
+    let child = Entity::new()
+        .bind(Name::new("bar".to_string()).unwrap())
+        .bind(Parent::Child(parent))
+        .write(&mut rtr);
+
+    assert_eq!(tx.frame(), Some(0));
+    assert_eq!(tx.commit(), Ok(()));
+    rtr = rtr.next_frame();
+
+    assert_eq!(rtr.name.frame_number(), 1);
+    assert_eq!(rtr.parent.frame_number(), 1);
+    let x: &Entity = rtr.name.read(&ChildByName(parent, "bar")).unwrap();
+    assert_eq!(*x, child);
+}
+
+#[test]
+fn transaction_frame_mismatch() {
+    let mut sched = Frontend::new();
+    let parent = parent(&mut sched);
+    let name = name(&mut sched, parent.clone());
+
+    let mut rtr = Router{
+        name: name,
+        parent: parent.next_frame().get().unwrap()
+    };
+
+    let mut tx = Transaction::new();
+    tx.stage(&mut rtr.name).stage(&mut rtr.parent);
+
+    let eid = Entity::new()
+        .bind(Name::new("foo".to_string()).unwrap())
+        .bind(Parent::Root)
+        .write(&mut rtr);
+
+    assert_eq!(tx.commit(), Err(TransactionError::FrameMismatch{
+        system: "parent",
+        expected: 0,
+        found: 1
+    }));
+    rtr = rtr.next_frame();
+
+    let n: Option<&Name> = rtr.read(&eid);
+    let p: Option<&Parent> = rtr.read(&eid);
+    assert!(n.is_none());
+    assert!(p.is_none());
+}
+
+#[test]
+fn transaction_already_staged() {
+    let mut sched = Frontend::new();
+    let parent = parent(&mut sched);
+    let name = name(&mut sched, parent.clone());
+
+    let mut rtr = Router{
+        name: name,
+        parent: parent
+    };
+
+    let mut first = Transaction::new();
+    first.stage(&mut rtr.name);
+
+    let mut second = Transaction::new();
+    second.stage(&mut rtr.parent).stage(&mut rtr.name);
+
+    let eid = Entity::new()
+        .bind(Name::new("foo".to_string()).unwrap())
+        .bind(Parent::Root)
+        .write(&mut rtr);
+
+    // the writes went to the second transaction, none of them are sent
+    assert_eq!(second.commit(), Err(TransactionError::AlreadyStaged("name")));
+    assert_eq!(first.commit(), Ok(()));
+    rtr = rtr.next_frame();
+
+    let n: Option<&Name> = rtr.read(&eid);
+    let p: Option<&Parent> = rtr.read(&eid);
+    assert!(n.is_none());
+    assert!(p.is_none());
+}
+
+#[test]
+fn transaction_cloned_handle() {
+    let mut sched = Frontend::new();
+    let parent = parent(&mut sched).next_frame().get().unwrap();
+    let name = name(&mut sched, parent.clone());
+
+    // the name system starts on the frame of the parent system
+    assert_eq!(name.frame_number(), 1);
+    assert_eq!(parent.frame_number(), 1);
+
+    let mut rtr = Router{
+        name: name,
+        parent: parent
+    };
+
+    let mut tx = Transaction::new();
+    tx.stage(&mut rtr.name).stage(&mut rtr.parent);
+
+    // a copy of a staged handle is held back by the transaction as well
+    let mut copy = rtr.name.clone();
+    let eid = Entity::new()
+        .bind(Name::new("foo".to_string()).unwrap())
+        .write(&mut copy);
+    drop(copy);
+    drop(tx);
+    rtr = rtr.next_frame();
+
+    let n: Option<&Name> = rtr.read(&eid);
+    assert!(n.is_none());
+}
@@ -0,0 +1,80 @@
+extern crate renderer;
+extern crate cgmath;
+
+use cgmath::*;
+use renderer::{move_origin, rebase, rebase_point, camera_frustum};
+
+#[test]
+fn origin_follows_camera() {
+    let mut origin = Vector3::new(0., 0., 0.);
+
+    // close enough, the origin stays
+    assert!(!move_origin(&mut origin, Vector3::new(10., 0., 0.), 100.));
+    assert_eq!(origin, Vector3::new(0., 0., 0.));
+
+    assert!(move_origin(&mut origin, Vector3::new(0., 150., 0.), 100.));
+    assert_eq!(origin, Vector3::new(0., 150., 0.));
+
+    // the distance is measured from the new origin
+    assert!(!move_origin(&mut origin, Vector3::new(0., 200., 0.), 100.));
+    assert_eq!(origin, Vector3::new(0., 150., 0.));
+}
+
+#[test]
+fn rebase_far_from_origin() {
+    // a millimetre is lost when this is rounded to f32
+    let position = Vector3::new(10_000_000.001, 0., -10_000_000.001);
+    let origin = Vector3::new(10_000_000., 0., -10_000_000.);
+    let world = Matrix4::from_translation(&Vector3::new(position.x as f32, 0., position.z as f32));
+
+    let mat = rebase(world, position, origin);
+    assert!((mat.w.x - 0.001).abs() < 1e-6);
+    assert!((mat.w.z + 0.001).abs() < 1e-6);
+    assert_eq!(mat.w.w, 1.);
+    assert_eq!(mat.x, world.x);
+}
+
+#[test]
+fn rebase_point_is_not_cumulative() {
+    let light = Vector4::new(1., 1., 1., 1.);
+    let origin = Vector3::new(100., 0., 0.);
+
+    // rebasing the same world position gives the same result every frame
+    assert_eq!(rebase_point(light, origin), Vector4::new(-99., 1., 1., 1.));
+    assert_eq!(rebase_point(light, origin), Vector4::new(-99., 1., 1., 1.));
+
+    // a direction is not moved
+    let dir = Vector4::new(0., 1., 0., 0.);
+    assert_eq!(rebase_point(dir, origin), dir);
+}
+
+#[test]
+fn frustum_from_rebased_camera() {
+    let projection: Matrix4<f32> = PerspectiveFov{fovy: deg(90.), aspect: 1., near: 1., far: 100.}.into();
+    let ahead = Aabb3::new(Point3::new(-1., -1., 949.), Point3::new(1., 1., 951.));
+    let behind = Aabb3::new(Point3::new(-1., -1., 1049.), Point3::new(1., 1., 1051.));
+    let beside = Aabb3::new(Point3::new(200., -1., 949.), Point3::new(202., 1., 951.));
+    let too_far = Aabb3::new(Point3::new(-1., -1., 800.), Point3::new(1., 1., 802.));
+
+    // the camera is at 1000 on Z looking down -Z, the origin was moved
+    // to it so its transform is relative to the origin
+    let origin = Vector3::new(0., 0., 1000.);
+    let frustum = camera_frustum(&Matrix4::identity(), &projection, origin).unwrap();
+    assert!(frustum.overlaps(&ahead));
+    assert!(!frustum.overlaps(&behind));
+    assert!(!frustum.overlaps(&beside));
+    assert!(!frustum.overlaps(&too_far));
+
+    // the same camera without moving the origin
+    let world = Matrix4::from_translation(&Vector3::new(0., 0., 1000.));
+    let frustum = camera_frustum(&world, &projection, Vector3::new(0., 0., 0.)).unwrap();
+    assert!(frustum.overlaps(&ahead));
+    assert!(!frustum.overlaps(&behind));
+
+    // a camera that cannot be inverted has no frustum
+    let flat = Matrix4::new(0., 0., 0., 0.,
+                            0., 0., 0., 0.,
+                            0., 0., 0., 0.,
+                            0., 0., 0., 0.);
+    assert!(camera_frustum(&flat, &projection, origin).is_none());
+}
@@ -1,9 +1,13 @@
 extern crate parent;
 extern crate fibe;
 extern crate entity;
+extern crate system;
 
 use entity::*;
-use parent::{parent, Parent};
+use std::mem;
+use std::collections::HashMap;
+use parent::{parent, Parent, ParentSystem, Message, Rejected, Bind, Order};
+use system::Backpressure;
 use fibe::*;
 
 #[test]
@@ -74,3 +78,362 @@ fn huge_number_of_children() {
         assert_eq!(parent.read(child), None);
     }
 }
+
+#[test]
+fn shutdown() {
+    let mut sched = Frontend::new();
+    let mut parent = parent(&mut sched);
+
+    let root = Entity::new().bind(Parent::Root).write(&mut parent);
+    parent.shutdown();
+
+    while let Ok(next) = parent.next_frame().get() {
+        parent = next;
+    }
+    assert!(parent.is_shutdown());
+    assert_eq!(parent.read(&root).unwrap(), &Parent::Root);
+}
+
+#[test]
+fn history() {
+    let mut sched = Frontend::new();
+    let mut parent = parent(&mut sched);
+    parent.keep_history(2);
+
+    let root = Entity::new().bind(Parent::Root).write(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+
+    let child = Entity::new().bind(Parent::Child(root)).write(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+
+    child.delete(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+
+    assert_eq!(parent.read(&child), None);
+    assert_eq!(parent.frame(-1).and_then(|p| p.child_to_parent.get(&child)),
+               Some(&Parent::Child(root)));
+    assert_eq!(parent.frame(-2).and_then(|p| p.child_to_parent.get(&child)), None);
+    assert!(parent.frame(-2).unwrap().child_to_parent.get(&root).is_some());
+    assert!(parent.frame(-3).is_none());
+    assert!(parent.frame(1).is_none());
+    assert_eq!(parent.history().len(), 2);
+}
+
+#[test]
+fn queue_stats() {
+    let mut sched = Frontend::new();
+    let mut parent = parent(&mut sched);
+
+    for _ in 0..10 {
+        Entity::new().bind(Parent::Root).write(&mut parent);
+    }
+    parent = parent.next_frame().get().unwrap();
+
+    let stats = parent.queue_stats();
+    assert_eq!(stats.frames, 1);
+    assert_eq!(stats.messages, 10);
+    assert_eq!(stats.peak, 10);
+    assert_eq!(stats.total, 10);
+    assert_eq!(stats.bytes, 10 * mem::size_of::<Message>());
+}
+
+#[test]
+fn backpressure_drop() {
+    let mut sched = Frontend::new();
+    let mut parent = parent(&mut sched);
+    parent.set_backpressure(Backpressure::Drop(5));
+
+    for _ in 0..10 {
+        Entity::new().bind(Parent::Root).write(&mut parent);
+    }
+    parent = parent.next_frame().get().unwrap();
+
+    assert_eq!(parent.child_to_parent.len(), 5);
+    assert_eq!(parent.queue_stats().dropped, 5);
+}
+
+#[test]
+fn coalesce() {
+    let mut sched = Frontend::new();
+    let mut parent = parent(&mut sched);
+    parent.coalesce(true);
+    parent = parent.next_frame().get().unwrap();
+
+    let a = Entity::new().bind(Parent::Root).write(&mut parent);
+    let b = Entity::new().bind(Parent::Root).write(&mut parent);
+    let child = Entity::new().bind(Parent::Child(a)).write(&mut parent);
+    child.bind(Parent::Child(b)).write(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+
+    assert_eq!(parent.read(&child).unwrap(), &Parent::Child(b));
+    let stats = parent.queue_stats();
+    assert_eq!(stats.messages, 3);
+    assert_eq!(stats.coalesced, 1);
+}
+
+#[test]
+fn reject_cycles() {
+    let mut sched = Frontend::new();
+    let mut parent = parent(&mut sched);
+
+    let root = Entity::new().bind(Parent::Root).write(&mut parent);
+    let child = Entity::new().bind(Parent::Child(root)).write(&mut parent);
+    let grandchild = Entity::new().bind(Parent::Child(child)).write(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+    assert!(parent.rejected.is_empty());
+
+    root.bind(Parent::Child(grandchild)).write(&mut parent);
+    child.bind(Parent::Child(child)).write(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+
+    assert_eq!(parent.rejected, vec![
+        Rejected::Cycle{child: root, parent: grandchild},
+        Rejected::Cycle{child: child, parent: child}
+    ]);
+    assert_eq!(parent.read(&root).unwrap(), &Parent::Root);
+    assert_eq!(parent.read(&child).unwrap(), &Parent::Child(root));
+
+    parent = parent.next_frame().get().unwrap();
+    assert!(parent.rejected.is_empty());
+}
+
+#[test]
+fn reparent_then_delete_old_parent() {
+    let mut sched = Frontend::new();
+    let mut parent = parent(&mut sched);
+
+    let a = Entity::new().bind(Parent::Root).write(&mut parent);
+    let b = Entity::new().bind(Parent::Root).write(&mut parent);
+    let child = Entity::new().bind(Parent::Child(a)).write(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+
+    child.bind(Parent::Child(b)).write(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+    assert!(!parent.children(a).any(|c| c == child));
+    assert!(parent.children(b).any(|c| c == child));
+
+    a.delete(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+    assert_eq!(parent.read(&a), None);
+    assert_eq!(parent.read(&child).unwrap(), &Parent::Child(b));
+
+    child.delete(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+    assert!(parent.parent_to_children.get(&b).unwrap().is_empty());
+}
+
+#[test]
+fn root_keeps_children() {
+    let mut sched = Frontend::new();
+    let mut parent = parent(&mut sched);
+
+    let a = Entity::new().bind(Parent::Root).write(&mut parent);
+    let child = Entity::new().bind(Parent::Child(a)).write(&mut parent);
+    a.bind(Parent::Root).write(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+
+    assert!(parent.children(a).any(|c| c == child));
+}
+
+/// A small xorshift generator so the random tests are repeatable
+struct Rng(u32);
+
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as usize % n
+    }
+}
+
+fn model_is_ancestor(model: &HashMap<Entity, Parent>, ancestor: Entity, eid: Entity) -> bool {
+    let mut eid = eid;
+    loop {
+        if eid == ancestor {
+            return true;
+        }
+        match model.get(&eid) {
+            Some(&Parent::Child(p)) => eid = p,
+            _ => return false
+        }
+    }
+}
+
+fn model_delete(model: &mut HashMap<Entity, Parent>, eid: Entity) {
+    let children: Vec<Entity> = model.iter()
+        .filter(|&(_, p)| *p == Parent::Child(eid))
+        .map(|(&c, _)| c)
+        .collect();
+    for c in children {
+        model_delete(model, c);
+    }
+    model.remove(&eid);
+}
+
+fn check(parent: &ParentSystem, model: &HashMap<Entity, Parent>) {
+    assert_eq!(&parent.child_to_parent, model);
+    for (&c, p) in parent.child_to_parent.iter() {
+        if let &Parent::Child(p) = p {
+            assert!(parent.children(p).any(|x| x == c));
+        }
+    }
+    for (&p, list) in parent.parent_to_children.iter() {
+        assert_eq!(parent.children(p).count(), list.len);
+        assert_eq!(parent.children(p).rev().count(), list.len);
+        for c in parent.children(p) {
+            assert_eq!(parent.child_to_parent.get(&c), Some(&Parent::Child(p)));
+        }
+    }
+}
+
+#[test]
+fn random_bind_delete() {
+    for seed in 1..9 {
+        let mut rng = Rng((seed as u32).wrapping_mul(2654435761));
+        let mut sched = Frontend::new();
+        let mut parent = parent(&mut sched);
+        let mut model = HashMap::new();
+        let entities: Vec<Entity> = (0..20).map(|_| Entity::new()).collect();
+
+        for _ in 0..30 {
+            for _ in 0..rng.below(8) + 1 {
+                let eid = entities[rng.below(entities.len())];
+                match rng.below(4) {
+                    0 => {
+                        eid.delete(&mut parent);
+                        model_delete(&mut model, eid);
+                    }
+                    1 => {
+                        eid.bind(Parent::Root).write(&mut parent);
+                        model.insert(eid, Parent::Root);
+                    }
+                    _ => {
+                        let p = entities[rng.below(entities.len())];
+                        eid.bind(Parent::Child(p)).write(&mut parent);
+                        if !model_is_ancestor(&model, eid, p) {
+                            model.insert(eid, Parent::Child(p));
+                        }
+                    }
+                }
+            }
+            parent = parent.next_frame().get().unwrap();
+            check(&parent, &model);
+        }
+    }
+}
+
+#[test]
+fn sibling_order() {
+    let mut sched = Frontend::new();
+    let mut parent = parent(&mut sched);
+
+    let root = Entity::new().bind(Parent::Root).write(&mut parent);
+    let a = Entity::new().bind(Parent::Child(root)).write(&mut parent);
+    let b = Entity::new().bind(Parent::Child(root)).write(&mut parent);
+    let c = Entity::new().bind(Bind::Before(a)).write(&mut parent);
+    let d = Entity::new().bind(Bind::After(a)).write(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+    assert_eq!(parent.children(root).collect::<Vec<_>>(), vec![c, a, d, b]);
+
+    // rebinding to the same parent keeps the order
+    a.bind(Parent::Child(root)).write(&mut parent);
+    b.bind(Bind::Before(c)).write(&mut parent);
+    root.bind(Bind::After(root)).write(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+    assert_eq!(parent.children(root).collect::<Vec<_>>(), vec![b, c, a, d]);
+    assert_eq!(parent.rejected, vec![Rejected::Sibling{child: root, sibling: root}]);
+}
+
+#[test]
+fn unchanged_bind_not_modified() {
+    let mut sched = Frontend::new();
+    let mut parent = parent(&mut sched);
+
+    let root = Entity::new().bind(Parent::Root).write(&mut parent);
+    let a = Entity::new().bind(Parent::Child(root)).write(&mut parent);
+    let b = Entity::new().bind(Parent::Child(root)).write(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+    assert_eq!(parent.modified.len(), 3);
+
+    // nothing moves, so nothing has to be walked again
+    root.bind(Parent::Root).write(&mut parent);
+    a.bind(Parent::Child(root)).write(&mut parent);
+    a.bind(Bind::Before(b)).write(&mut parent);
+    b.bind(Bind::After(a)).write(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+    assert!(parent.modified.is_empty());
+
+    // a new order is a change
+    b.bind(Bind::Before(a)).write(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+    assert_eq!(parent.modified.get(&b), Some(&Some(Parent::Child(root))));
+    assert_eq!(parent.children(root).collect::<Vec<_>>(), vec![b, a]);
+}
+
+#[test]
+fn hierarchy_queries() {
+    let mut sched = Frontend::new();
+    let mut parent = parent(&mut sched);
+
+    let root = Entity::new().bind(Parent::Root).write(&mut parent);
+    let a = Entity::new().bind(Parent::Child(root)).write(&mut parent);
+    let b = Entity::new().bind(Parent::Child(root)).write(&mut parent);
+    let a0 = Entity::new().bind(Parent::Child(a)).write(&mut parent);
+    let a1 = Entity::new().bind(Parent::Child(a)).write(&mut parent);
+    let b0 = Entity::new().bind(Parent::Child(b)).write(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+
+    assert_eq!(parent.ancestors(a1).collect::<Vec<_>>(), vec![a, root]);
+    assert_eq!(parent.ancestors(root).count(), 0);
+    assert_eq!(parent.descendants(root, Order::DepthFirst).collect::<Vec<_>>(),
+               vec![a, a0, a1, b, b0]);
+    assert_eq!(parent.descendants(root, Order::BreadthFirst).collect::<Vec<_>>(),
+               vec![a, b, a0, a1, b0]);
+    assert_eq!(parent.depth(root), Some(0));
+    assert_eq!(parent.depth(b0), Some(2));
+    assert_eq!(parent.depth(Entity::new()), None);
+    assert_eq!(parent.root_of(a1), Some(root));
+    assert_eq!(parent.root_of(root), Some(root));
+}
+
+#[test]
+fn root_sibling_rejected() {
+    let mut sched = Frontend::new();
+    let mut parent = parent(&mut sched);
+
+    let a = Entity::new().bind(Parent::Root).write(&mut parent);
+    let b = Entity::new().bind(Parent::Root).write(&mut parent);
+    let child = Entity::new().bind(Parent::Child(a)).write(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+
+    // roots have no order, so the child is not turned into a root
+    child.bind(Bind::After(b)).write(&mut parent);
+    parent = parent.next_frame().get().unwrap();
+    assert_eq!(parent.rejected, vec![Rejected::Sibling{child: child, sibling: b}]);
+    assert_eq!(parent.read(&child), Some(&Parent::Child(a)));
+}
+
+#[test]
+fn many_siblings() {
+    let mut sched = Frontend::new();
+    let mut parent = parent(&mut sched);
+
+    let root = Entity::new().bind(Parent::Root).write(&mut parent);
+    let children: Vec<Entity> = (0..10_000)
+        .map(|_| Entity::new().bind(Parent::Child(root)).write(&mut parent))
+        .collect();
+    parent = parent.next_frame().get().unwrap();
+
+    // removing every other child does not walk the siblings
+    for (_, c) in children.iter().enumerate().filter(|&(i, _)| i % 2 == 0) {
+        c.delete(&mut parent);
+    }
+    parent = parent.next_frame().get().unwrap();
+    let left: Vec<Entity> = children.iter()
+        .enumerate()
+        .filter(|&(i, _)| i % 2 == 1)
+        .map(|(_, &c)| c)
+        .collect();
+    assert_eq!(parent.children(root).collect::<Vec<_>>(), left);
+}
@@ -0,0 +1,176 @@
+extern crate renderer;
+extern crate spatial;
+extern crate scene;
+extern crate cgmath;
+extern crate fibe;
+extern crate entity;
+extern crate gfx_mesh;
+extern crate graphics;
+extern crate bounding;
+extern crate parent;
+extern crate transform;
+
+use cgmath::*;
+use fibe::*;
+use entity::*;
+use gfx_mesh::{Attribute, BuildInterlaced};
+use graphics::{Graphics, Geometry, Material, Primative, VertexBuffer, POSITION};
+use bounding::Bounding;
+use parent::{parent, Parent, ParentSystem};
+use transform::{transform, Local, TransformSystem};
+use renderer::{Camera, DrawBinding, Renderer, cursor_ray, pick, renderer};
+use spatial::{Ray, Spatial};
+use scene::{scene, Scene, SceneSystem};
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-4
+}
+
+#[test]
+fn cursor_through_camera() {
+    let camera = Camera(PerspectiveFov{fovy: deg(90.), aspect: 1., near: 1., far: 100.}, Scene::new());
+    let world = Matrix4::new(1., 0., 0., 0.,
+                             0., 1., 0., 0.,
+                             0., 0., 1., 0.,
+                             0., 0., 10., 1.);
+
+    // the center of the window looks down -Z
+    let ray = cursor_ray(&camera, &world, (50., 50.), (100, 100)).unwrap();
+    assert!(close(ray.origin.x, 0.) && close(ray.origin.y, 0.) && close(ray.origin.z, 9.));
+    assert!(close(ray.direction.x, 0.) && close(ray.direction.y, 0.) && close(ray.direction.z, -1.));
+
+    // the top right corner is 45 degrees up and to the right
+    let ray = cursor_ray(&camera, &world, (100., 0.), (100, 100)).unwrap();
+    assert!(close(ray.origin.x, 1.) && close(ray.origin.y, 1.) && close(ray.origin.z, 9.));
+    assert!(close(ray.direction.x, ray.direction.y));
+    assert!(close(ray.direction.x, -ray.direction.z));
+
+    // a window without an area has no ray through it
+    assert!(cursor_ray(&camera, &world, (0., 0.), (0, 100)).is_none());
+    assert!(cursor_ray(&camera, &world, (0., 0.), (100, 0)).is_none());
+}
+
+#[test]
+fn ray_triangle() {
+    let ray = Ray::new(Point3::new(0.25, 0.25, 5.), Vector3::new(0., 0., -1.));
+    let (a, b, c) = (Point3::new(0., 0., 0.), Point3::new(1., 0., 0.), Point3::new(0., 1., 0.));
+    assert_eq!(ray.intersect_triangle(a, b, c), Some(5.));
+    // both sides are hit
+    assert_eq!(ray.intersect_triangle(a, c, b), Some(5.));
+
+    let miss = Ray::new(Point3::new(1., 1., 5.), Vector3::new(0., 0., -1.));
+    assert_eq!(miss.intersect_triangle(a, b, c), None);
+    let behind = Ray::new(Point3::new(0.25, 0.25, -5.), Vector3::new(0., 0., -1.));
+    assert_eq!(behind.intersect_triangle(a, b, c), None);
+}
+
+struct Systems {
+    graphics: Graphics,
+    parents: ParentSystem,
+    transforms: TransformSystem,
+    scenes: SceneSystem,
+    render: Renderer,
+    spatial: Spatial
+}
+
+impl Systems {
+    fn new(sched: &mut Frontend) -> Systems {
+        let graphics = Graphics::new(sched);
+        let parents = parent(sched);
+        let transforms = transform(sched, parents.clone());
+        let scenes = scene(sched, parents.clone());
+        let render = renderer(sched);
+        let bounding = Bounding::with_world(sched,
+                                            graphics.clone(),
+                                            parents.clone(),
+                                            transforms.clone(),
+                                            render.clone());
+        let spatial = Spatial::new(sched, scenes.clone(), bounding);
+        Systems {
+            graphics: graphics,
+            parents: parents,
+            transforms: transforms,
+            scenes: scenes,
+            render: render,
+            spatial: spatial
+        }
+    }
+
+    fn next_frame(self) -> Systems {
+        let Systems{graphics, parents, transforms, scenes, render, mut spatial} = self;
+        let graphics = graphics.next_frame();
+        let parents = parents.next_frame();
+        let transforms = transforms.next_frame();
+        let scenes = scenes.next_frame();
+        let render = render.next_frame();
+        spatial.next_frame();
+        Systems {
+            graphics: graphics.get().unwrap(),
+            parents: parents.get().unwrap(),
+            transforms: transforms.get().unwrap(),
+            scenes: scenes.get().unwrap(),
+            render: render.get().unwrap(),
+            spatial: spatial
+        }
+    }
+}
+
+// A geometry made of a single triangle
+fn triangle(graphics: &mut Graphics, points: Vec<([f32; 3],)>) -> Geometry {
+    let vertex = [Attribute::f32(POSITION, 3)]
+        .build(points.into_iter())
+        .unwrap()
+        .owned_attributes();
+    let vb = VertexBuffer::new().bind(vertex).write(graphics);
+    Geometry::new().bind(vb.geometry(Primative::Triangle)).write(graphics)
+}
+
+fn at(x: f32, y: f32, z: f32) -> Local {
+    Local::Decomposed(Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(x, y, z)})
+}
+
+#[test]
+fn pick_nearest() {
+    let mut sched = Frontend::new();
+    let mut s = Systems::new(&mut sched);
+
+    // the ray passes through the upper left half of the square
+    let lower = triangle(&mut s.graphics, vec![([-1., -1., 0.],), ([1., 1., 0.],), ([1., -1., 0.],)]);
+    let upper = triangle(&mut s.graphics, vec![([-1., -1., 0.],), ([1., 1., 0.],), ([-1., 1., 0.],)]);
+    let ray = Ray::new(Point3::new(-0.5, 0.5, 10.), Vector3::new(0., 0., -1.));
+
+    let world = Scene::new();
+    let only_near = Scene::new();
+    let (near, mid, far, other) = (Entity::new(), Entity::new(), Entity::new(), Entity::new());
+    for &(eid, z, geo) in [(near, 5., lower), (mid, 0., upper), (far, -5., upper), (other, 8., upper)].iter() {
+        eid.bind(Parent::Root).write(&mut s.parents);
+        eid.bind(at(0., 0., z)).write(&mut s.transforms);
+        eid.bind(DrawBinding(geo, Material::new())).write(&mut s.render);
+    }
+    world.bind(near, &mut s.scenes);
+    world.bind(mid, &mut s.scenes);
+    world.bind(far, &mut s.scenes);
+    only_near.bind(near, &mut s.scenes);
+
+    let s = s.next_frame();
+    let graphics = Some(&*s.graphics);
+
+    // the bounds of near are hit first, but its triangle is missed
+    let hit = pick(&ray, world, &s.spatial, &s.render, &s.transforms, graphics).unwrap();
+    assert_eq!(hit.entity, mid);
+    assert!(close(hit.distance, 10.));
+    assert!(close(hit.point.x, -0.5) && close(hit.point.y, 0.5) && close(hit.point.z, 0.));
+
+    // without the triangles the closest bounds are hit
+    let hit = pick(&ray, world, &s.spatial, &s.render, &s.transforms, None).unwrap();
+    assert_eq!(hit.entity, near);
+    assert!(close(hit.distance, 5.));
+
+    // nothing is behind the missed triangle
+    assert!(pick(&ray, only_near, &s.spatial, &s.render, &s.transforms, graphics).is_none());
+    assert_eq!(pick(&ray, only_near, &s.spatial, &s.render, &s.transforms, None).map(|h| h.entity),
+               Some(near));
+
+    // an empty scene has nothing to hit
+    assert!(pick(&ray, Scene::new(), &s.spatial, &s.render, &s.transforms, graphics).is_none());
+}
@@ -0,0 +1,51 @@
+extern crate system;
+
+use system::profile::{Span, timeline};
+
+#[test]
+fn chrome_trace() {
+    let timeline = timeline();
+    timeline.enable(true);
+    timeline.record(Span {
+        name: "test_trace",
+        start: 1.,
+        ready: 1.,
+        end: 1.5,
+        messages: 3,
+        thread: 7
+    });
+
+    let mut out = Vec::new();
+    timeline.write_chrome_trace(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.starts_with("{\"traceEvents\":["));
+    assert!(out.contains("{\"name\":\"test_trace\",\"cat\":\"system\",\"ph\":\"X\",\"ts\":1000000,\"dur\":500000,\"pid\":0,\"tid\":7,\"args\":{\"messages\":3}}"));
+    assert!(timeline.latest().iter().any(|s| s.name == "test_trace"));
+}
+
+#[test]
+fn chrome_trace_wait() {
+    let timeline = timeline();
+    timeline.enable(true);
+    let span = Span {
+        name: "test_wait",
+        start: 2.,
+        ready: 2.25,
+        end: 3.,
+        messages: 1,
+        thread: 4
+    };
+    assert_eq!(span.wait(), 0.25);
+    assert_eq!(span.work(), 0.75);
+    assert_eq!(span.duration(), 1.);
+    timeline.record(span);
+
+    let mut out = Vec::new();
+    timeline.write_chrome_trace(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+
+    // the wait and the work are separate events
+    assert!(out.contains("{\"name\":\"test_wait\",\"cat\":\"wait\",\"ph\":\"X\",\"ts\":2000000,\"dur\":250000,\"pid\":0,\"tid\":4}"));
+    assert!(out.contains("{\"name\":\"test_wait\",\"cat\":\"system\",\"ph\":\"X\",\"ts\":2250000,\"dur\":750000,\"pid\":0,\"tid\":4,\"args\":{\"messages\":1}}"));
+}
@@ -118,4 +118,233 @@ fn delete_parent() {
     assert!(map.get(&scene).is_none());
 }
 
-*/
\ No newline at end of file
+*/
+use entity::*;
+use std::collections::HashSet;
+use parent::{parent, Parent, ParentSystem};
+use scene::{scene, Scene, SceneSystem, Layers};
+
+fn next_frame(parents: ParentSystem, scenes: SceneSystem) -> (ParentSystem, SceneSystem) {
+    let parents = parents.next_frame();
+    let scenes = scenes.next_frame();
+    (parents.get().unwrap(), scenes.get().unwrap())
+}
+
+#[test]
+fn hierarchical_scenes() {
+    let mut sched = fibe::Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut scenes = scene(&mut sched, parents.clone());
+
+    let model = Entity::new().bind(Parent::Root).write(&mut parents);
+    let parts: Vec<Entity> = (0..10).map(|_| {
+        Entity::new().bind(Parent::Child(model)).write(&mut parents)
+    }).collect();
+    let other = Entity::new().bind(Parent::Root).write(&mut parents);
+
+    let world = Scene::new();
+    let level = Scene::new();
+    level.bind(model, &mut scenes);
+    world.bind(other, &mut scenes);
+    world.include(level, &mut scenes);
+
+    let (p, mut s) = next_frame(parents, scenes);
+    {
+        let level_entities = s.scene_entities(level).unwrap();
+        let world_entities = s.scene_entities(world).unwrap();
+        assert_eq!(level_entities.len(), 11);
+        assert_eq!(world_entities.len(), 12);
+        for part in &parts {
+            assert!(level_entities.contains(part));
+            assert!(world_entities.contains(part));
+        }
+        assert_eq!(s.bound_entities(level).unwrap().len(), 1);
+    }
+
+    // the parts inherit the second layer from the model
+    model.bind(Layers(2)).write(&mut s);
+    world.set_mask(2, &mut s);
+    let (p, mut s) = next_frame(p, s);
+    {
+        let world_entities = s.scene_entities(world).unwrap();
+        assert_eq!(world_entities.len(), 11);
+        assert!(world_entities.contains(&model));
+        assert!(!world_entities.contains(&other));
+        assert_eq!(s.scene_entities(level).unwrap().len(), 11);
+    }
+
+    level.enable(false, &mut s);
+    let (p, mut s) = next_frame(p, s);
+    assert!(!s.is_enabled(level));
+    assert!(s.scene_entities(level).is_none());
+    assert!(s.scene_entities(world).is_none());
+
+    level.enable(true, &mut s);
+    world.set_mask(!0, &mut s);
+    world.exclude(level, &mut s);
+    let (_, s) = next_frame(p, s);
+    assert_eq!(s.scene_entities(level).unwrap().len(), 11);
+    assert_eq!(s.scene_entities(world).unwrap().len(), 1);
+    assert!(s.scene_entities(world).unwrap().contains(&other));
+}
+
+#[test]
+fn include_cycle() {
+    let mut sched = fibe::Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut scenes = scene(&mut sched, parents.clone());
+
+    let a = Scene::new();
+    let b = Scene::new();
+    let ea = Entity::new().bind(Parent::Root).write(&mut parents);
+    let eb = Entity::new().bind(Parent::Root).write(&mut parents);
+    a.bind(ea, &mut scenes);
+    b.bind(eb, &mut scenes);
+    a.include(b, &mut scenes);
+    b.include(a, &mut scenes);
+
+    let (_, s) = next_frame(parents, scenes);
+    let entities = s.scene_entities(a).unwrap();
+    assert!(entities.contains(&ea));
+    assert!(entities.contains(&eb));
+}
+
+#[test]
+fn membership_changes() {
+    let mut sched = fibe::Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut scenes = scene(&mut sched, parents.clone());
+
+    let scene = Scene::new();
+    let a = Entity::new().bind(Parent::Root).write(&mut parents);
+    let b = Entity::new().bind(Parent::Root).write(&mut parents);
+    let c = Entity::new().bind(Parent::Child(b)).write(&mut parents);
+    scene.bind(a, &mut scenes);
+    scene.bind(b, &mut scenes);
+
+    let (mut p, mut s) = next_frame(parents, scenes);
+    assert_eq!(s.added(scene).unwrap().len(), 3);
+    assert!(s.removed(scene).is_none());
+
+    // nothing changed
+    let (next_p, next_s) = next_frame(p, s);
+    p = next_p; s = next_s;
+    assert!(s.added(scene).is_none());
+    assert!(s.removed(scene).is_none());
+    assert!(s.changed_scenes().is_empty());
+
+    scene.unbind(a, &mut s);
+    let (mut p, s) = next_frame(p, s);
+    assert!(s.added(scene).is_none());
+    assert_eq!(s.removed(scene).unwrap().len(), 1);
+    assert!(s.removed(scene).unwrap().contains(&a));
+
+    // deleting b deletes c as well
+    b.delete(&mut p);
+    let (p, s) = next_frame(p, s);
+    let removed = s.removed(scene).unwrap();
+    assert!(removed.contains(&b));
+    assert!(removed.contains(&c));
+    assert!(s.scene_entities(scene).is_none());
+
+    let (_, s) = next_frame(p, s);
+    assert!(s.changed_scenes().is_empty());
+}
+
+#[test]
+fn layer_override() {
+    let mut sched = fibe::Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut scenes = scene(&mut sched, parents.clone());
+
+    let model = Entity::new().bind(Parent::Root).write(&mut parents);
+    let arm = Entity::new().bind(Parent::Child(model)).write(&mut parents);
+    let hand = Entity::new().bind(Parent::Child(arm)).write(&mut parents);
+    let scene = Scene::new();
+    scene.bind(model, &mut scenes);
+    scene.set_mask(2, &mut scenes);
+    model.bind(Layers(2)).write(&mut scenes);
+    arm.bind(Layers(1)).write(&mut scenes);
+
+    // the hand gets its layers from the arm, not the model
+    let (p, mut s) = next_frame(parents, scenes);
+    assert_eq!(s.layers(hand, &p), Layers(1));
+    assert_eq!(s.scene_entities(scene).unwrap().len(), 1);
+    assert!(s.scene_entities(scene).unwrap().contains(&model));
+
+    // once the override is gone the arm and hand follow the model
+    arm.bind(Layers(2)).write(&mut s);
+    let (p, s) = next_frame(p, s);
+    assert_eq!(s.layers(hand, &p), Layers(2));
+    assert_eq!(s.scene_entities(scene).unwrap().len(), 3);
+    assert!(s.added(scene).unwrap().contains(&hand));
+}
+
+#[test]
+fn belongs_to() {
+    let mut sched = fibe::Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut scenes = scene(&mut sched, parents.clone());
+
+    let model = Entity::new().bind(Parent::Root).write(&mut parents);
+    let part = Entity::new().bind(Parent::Child(model)).write(&mut parents);
+    let level = Scene::new();
+    let world = Scene::new();
+    level.bind(model, &mut scenes);
+    world.include(level, &mut scenes);
+
+    // the part is in the level through its parent and in the world through the include
+    let (p, mut s) = next_frame(parents, scenes);
+    let expected: HashSet<Entity> = vec![level.0, world.0].into_iter().collect();
+    assert_eq!(s.belongs_to(part), Some(&expected));
+    assert_eq!(s.belongs_to(model), Some(&expected));
+
+    world.exclude(level, &mut s);
+    let (mut p, s) = next_frame(p, s);
+    let expected: HashSet<Entity> = vec![level.0].into_iter().collect();
+    assert_eq!(s.belongs_to(part), Some(&expected));
+
+    model.delete(&mut p);
+    let (_, s) = next_frame(p, s);
+    assert!(s.belongs_to(part).is_none());
+    assert!(s.scene_entities(level).is_none());
+}
+
+#[test]
+fn scene_changes_reported() {
+    let mut sched = fibe::Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut scenes = scene(&mut sched, parents.clone());
+
+    let model = Entity::new().bind(Parent::Root).write(&mut parents);
+    let part = Entity::new().bind(Parent::Child(model)).write(&mut parents);
+    let level = Scene::new();
+    let world = Scene::new();
+    level.bind(model, &mut scenes);
+    world.include(level, &mut scenes);
+    let (p, mut s) = next_frame(parents, scenes);
+    assert_eq!(s.added(world).unwrap().len(), 2);
+
+    // disabling the level empties both scenes
+    level.enable(false, &mut s);
+    let (p, mut s) = next_frame(p, s);
+    assert_eq!(s.removed(level).unwrap().len(), 2);
+    assert_eq!(s.removed(world).unwrap().len(), 2);
+    assert!(s.added(world).is_none());
+
+    // a frame later the change is not reported again
+    let (p, mut s) = next_frame(p, s);
+    assert!(s.changed_scenes().is_empty());
+
+    level.enable(true, &mut s);
+    world.set_mask(2, &mut s);
+    let (p, mut s) = next_frame(p, s);
+    assert_eq!(s.added(level).unwrap().len(), 2);
+    assert!(s.added(world).is_none());
+
+    part.bind(Layers(2)).write(&mut s);
+    let (_, s) = next_frame(p, s);
+    assert_eq!(s.added(world).unwrap().len(), 1);
+    assert!(s.added(world).unwrap().contains(&part));
+    assert!(s.added(level).is_none());
+}
@@ -0,0 +1,191 @@
+extern crate system;
+extern crate fibe;
+extern crate entity;
+extern crate parent;
+extern crate transform;
+extern crate scene;
+extern crate name;
+extern crate config;
+extern crate cgmath;
+
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::channel;
+use std::time::Duration;
+use system::{Graph, ScheduleError};
+use fibe::*;
+use entity::{Entity, ReadEntity, WriteEntity};
+use parent::Parent;
+use transform::Local;
+use cgmath::*;
+
+#[test]
+fn resolve_levels() {
+    let mut graph = Graph::new();
+    graph.add("transform", &["parent"], || true)
+         .add("parent", &[], || true)
+         .add("scene", &["parent"], || true)
+         .add("animation", &["transform"], || true);
+
+    let levels = graph.resolve().unwrap();
+    assert_eq!(levels.len(), 3);
+    assert_eq!(levels[0], vec!["parent".to_string()]);
+    assert!(levels[1].contains(&"transform".to_string()));
+    assert!(levels[1].contains(&"scene".to_string()));
+    assert_eq!(levels[2], vec!["animation".to_string()]);
+}
+
+#[test]
+fn cycle() {
+    let mut graph = Graph::new();
+    graph.add("a", &["c"], || true)
+         .add("b", &["a"], || true)
+         .add("c", &["b"], || true);
+
+    match graph.resolve() {
+        Err(ScheduleError::Cycle(path)) => {
+            assert_eq!(path.len(), 4);
+            assert_eq!(path[0], path[3]);
+        }
+        x => panic!("expected a cycle {:?}", x)
+    }
+}
+
+#[test]
+fn unknown_dependency() {
+    let mut graph = Graph::new();
+    graph.add("a", &["b"], || true);
+
+    assert_eq!(graph.resolve(), Err(ScheduleError::UnknownDependency{
+        system: "a".to_string(),
+        dependency: "b".to_string()
+    }));
+}
+
+#[test]
+fn run_in_order() {
+    let mut sched = Frontend::new();
+    let log = Arc::new(Mutex::new(Vec::new()));
+
+    let mut graph = Graph::new();
+    let l = log.clone();
+    graph.add("parent", &[], move || { l.lock().unwrap().push("parent"); true });
+    let l = log.clone();
+    graph.add("transform", &["parent"], move || { l.lock().unwrap().push("transform"); true });
+
+    let mut frame = graph.build().unwrap();
+    assert_eq!(frame.order(), vec![vec!["parent"], vec!["transform"]]);
+
+    assert_eq!(frame.run_frame(&mut sched), Ok(true));
+    assert_eq!(frame.run_frame(&mut sched), Ok(true));
+    assert_eq!(*log.lock().unwrap(), vec!["parent", "transform", "parent", "transform"]);
+}
+
+#[test]
+fn deadlock() {
+    let mut sched = Frontend::new();
+    let (tx, rx) = channel::<()>();
+
+    let mut graph = Graph::new();
+    graph.add("stuck", &[], move || { rx.recv().unwrap(); true });
+
+    let mut frame = graph.build().unwrap();
+    frame.set_timeout(Duration::from_millis(50));
+
+    assert_eq!(frame.run_frame(&mut sched), Err(ScheduleError::Deadlock(vec!["stuck".to_string()])));
+    tx.send(()).unwrap();
+}
+
+#[test]
+fn finished_step_not_rerun() {
+    let mut sched = Frontend::new();
+    let runs = Arc::new(Mutex::new(0));
+
+    let mut graph = Graph::new();
+    let r = runs.clone();
+    graph.add("once", &[], move || { *r.lock().unwrap() += 1; false });
+
+    let mut frame = graph.build().unwrap();
+    assert_eq!(frame.run_frame(&mut sched), Ok(false));
+    assert_eq!(frame.run_frame(&mut sched), Ok(false));
+    assert_eq!(*runs.lock().unwrap(), 1);
+}
+
+#[test]
+fn run_system() {
+    let mut sched = Frontend::new();
+    let (mut parent, step) = parent::parent_step();
+
+    let mut graph = Graph::new();
+    graph.add_step("parent", &[], step);
+    let mut frame = graph.build().unwrap();
+
+    let root = Entity::new();
+    root.bind(Parent::Root).write(&mut parent);
+    let next = parent.next_frame();
+    assert_eq!(frame.run_frame(&mut sched), Ok(true));
+    let parent = next.get().unwrap();
+    assert_eq!(parent.read(&root), Some(&Parent::Root));
+
+    parent.shutdown();
+    let next = parent.next_frame();
+    assert_eq!(frame.run_frame(&mut sched), Ok(false));
+    assert!(next.get().unwrap().is_shutdown());
+
+    // the system has exited, it is not stepped again
+    assert_eq!(frame.run_frame(&mut sched), Ok(false));
+}
+
+#[test]
+fn run_systems() {
+    let mut sched = Frontend::new();
+    let (mut parents, step) = parent::parent_step();
+    let (mut transforms, tstep) = transform::transform_step(parents.clone());
+    let (scenes, sstep) = scene::scene_step(parents.clone());
+    let (names, nstep) = name::name_step(parents.clone());
+    let (configs, cstep) = config::config_step(parents.clone());
+
+    let mut graph = Graph::new();
+    graph.add_step("parent", &[], step)
+         .add_step("transform", &["parent"], tstep)
+         .add_step("scene", &["parent"], sstep)
+         .add_step("name", &["parent"], nstep)
+         .add_step("config", &["parent"], cstep);
+    let mut frame = graph.build().unwrap();
+    assert_eq!(frame.order()[0], vec!["parent"]);
+    assert_eq!(frame.order()[1].len(), 4);
+
+    let a = Entity::new().bind(Parent::Root).write(&mut parents);
+    let b = Entity::new().bind(Parent::Child(a)).write(&mut parents);
+    for &e in [a, b].iter() {
+        e.bind(Local::Decomposed(Decomposed{
+            scale: 1.,
+            rot: Quaternion::identity(),
+            disp: Vector3::new(1., 0., 0.)
+        })).write(&mut transforms);
+    }
+
+    let next = (parents.next_frame(), transforms.next_frame(), scenes.next_frame(),
+                names.next_frame(), configs.next_frame());
+    assert_eq!(frame.run_frame(&mut sched), Ok(true));
+    let (parents, transforms, scenes, names, configs) =
+        (next.0.get().unwrap(), next.1.get().unwrap(), next.2.get().unwrap(),
+         next.3.get().unwrap(), next.4.get().unwrap());
+
+    // every system is on the frame of the parent it followed
+    assert_eq!(transforms.frame_number(), parents.frame_number());
+    assert_eq!(scenes.frame_number(), parents.frame_number());
+    assert_eq!(names.frame_number(), parents.frame_number());
+    assert_eq!(configs.frame_number(), parents.frame_number());
+    assert_eq!(transforms.world(b).unwrap().0.w, Vector4::new(2., 0., 0., 1.));
+
+    // the followers see the parent shutdown on the same frame
+    parents.shutdown();
+    let next = (parents.next_frame(), transforms.next_frame(), scenes.next_frame(),
+                names.next_frame(), configs.next_frame());
+    assert_eq!(frame.run_frame(&mut sched), Ok(false));
+    assert!(next.0.get().unwrap().is_shutdown());
+    assert!(next.1.get().unwrap().is_shutdown());
+    assert!(next.2.get().unwrap().is_shutdown());
+    assert!(next.3.get().unwrap().is_shutdown());
+    assert!(next.4.get().unwrap().is_shutdown());
+}
@@ -0,0 +1,271 @@
+extern crate spatial;
+extern crate entity;
+extern crate cgmath;
+extern crate fibe;
+extern crate gfx_mesh;
+extern crate graphics;
+extern crate bounding;
+extern crate parent;
+extern crate transform;
+extern crate scene;
+extern crate renderer;
+
+use std::collections::HashMap;
+use entity::*;
+use cgmath::*;
+use fibe::*;
+use gfx_mesh::{Attribute, BuildInterlaced};
+use graphics::{Graphics, Geometry, Material, Primative, VertexBuffer, POSITION};
+use bounding::Bounding;
+use parent::{parent, Parent, ParentSystem};
+use transform::{transform, Local, TransformSystem};
+use scene::{scene, Scene, SceneSystem};
+use renderer::{renderer, DrawBinding, Renderer};
+use spatial::{Bvh, Frustum, Sphere, Ray, SpatialIndex, overlaps};
+
+/// A small xorshift generator so the random tests are repeatable
+struct Rng(u32);
+
+impl Rng {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 % 10_000) as f32 / 100.
+    }
+
+    fn aabb(&mut self) -> Aabb3<f32> {
+        let (x, y, z) = (self.next(), self.next(), self.next());
+        let size = self.next() / 20.;
+        Aabb3::new(Point3::new(x, y, z), Point3::new(x + size, y + size, z + size))
+    }
+}
+
+fn sorted(mut v: Vec<Entity>) -> Vec<Entity> {
+    v.sort();
+    v
+}
+
+#[test]
+fn matches_linear_search() {
+    let mut rng = Rng(2463534242);
+    let mut bvh = Bvh::new();
+    let mut model: HashMap<Entity, Aabb3<f32>> = HashMap::new();
+
+    for _ in 0..2000 {
+        let (eid, aabb) = (Entity::new(), rng.aabb());
+        bvh.insert(eid, aabb);
+        model.insert(eid, aabb);
+    }
+
+    // move and remove some of the entities
+    let eids: Vec<Entity> = model.keys().cloned().collect();
+    for (i, &eid) in eids.iter().enumerate() {
+        match i % 3 {
+            0 => {
+                let aabb = rng.aabb();
+                bvh.insert(eid, aabb);
+                model.insert(eid, aabb);
+            }
+            1 => {
+                assert!(bvh.remove(eid));
+                model.remove(&eid);
+            }
+            _ => ()
+        }
+    }
+    assert_eq!(bvh.len(), model.len());
+
+    for _ in 0..50 {
+        let query = rng.aabb();
+        let expected = model.iter()
+            .filter(|&(_, b)| overlaps(b, &query))
+            .map(|(&e, _)| e)
+            .collect();
+        assert_eq!(sorted(bvh.query_aabb(&query)), sorted(expected));
+
+        let sphere = Sphere::new(Point3::new(rng.next(), rng.next(), rng.next()), rng.next() / 5.);
+        let expected = model.iter()
+            .filter(|&(_, b)| sphere.overlaps(b))
+            .map(|(&e, _)| e)
+            .collect();
+        assert_eq!(sorted(bvh.query_sphere(&sphere)), sorted(expected));
+    }
+}
+
+#[test]
+fn ray_hits_closest_first() {
+    let mut bvh = Bvh::new();
+    let (a, b, c) = (Entity::new(), Entity::new(), Entity::new());
+    bvh.insert(a, Aabb3::new(Point3::new(4., -1., -1.), Point3::new(5., 1., 1.)));
+    bvh.insert(b, Aabb3::new(Point3::new(2., -1., -1.), Point3::new(3., 1., 1.)));
+    bvh.insert(c, Aabb3::new(Point3::new(2., 5., -1.), Point3::new(3., 6., 1.)));
+
+    let ray = Ray::new(Point3::new(0., 0., 0.), Vector3::new(1., 0., 0.));
+    let hits = bvh.query_ray(&ray);
+    assert_eq!(hits, vec![(b, 2.), (a, 4.)]);
+    assert_eq!(ray.at(2.), Point3::new(2., 0., 0.));
+}
+
+#[test]
+fn frustum_culling() {
+    let mut bvh = Bvh::new();
+    let (inside, edge, outside) = (Entity::new(), Entity::new(), Entity::new());
+    bvh.insert(inside, Aabb3::new(Point3::new(-0.5, -0.5, -0.5), Point3::new(0.5, 0.5, 0.5)));
+    bvh.insert(edge, Aabb3::new(Point3::new(0.5, 0.5, 0.5), Point3::new(2., 2., 2.)));
+    bvh.insert(outside, Aabb3::new(Point3::new(3., 0., 0.), Point3::new(4., 1., 1.)));
+
+    // the identity's frustum is the cube from -1 to 1
+    let frustum = Frustum::from_matrix(&Matrix4::identity());
+    assert_eq!(sorted(bvh.query_frustum(&frustum)), sorted(vec![inside, edge]));
+}
+
+struct Systems {
+    graphics: Graphics,
+    parents: ParentSystem,
+    transforms: TransformSystem,
+    scenes: SceneSystem,
+    render: Renderer,
+    bounding: Bounding,
+    index: SpatialIndex
+}
+
+impl Systems {
+    fn new(sched: &mut Frontend) -> Systems {
+        let graphics = Graphics::new(sched);
+        let parents = parent(sched);
+        let transforms = transform(sched, parents.clone());
+        let scenes = scene(sched, parents.clone());
+        let render = renderer(sched);
+        let bounding = Bounding::with_world(sched,
+                                            graphics.clone(),
+                                            parents.clone(),
+                                            transforms.clone(),
+                                            render.clone());
+        Systems {
+            graphics: graphics,
+            parents: parents,
+            transforms: transforms,
+            scenes: scenes,
+            render: render,
+            bounding: bounding,
+            index: SpatialIndex::new()
+        }
+    }
+
+    fn next_frame(self) -> Systems {
+        let Systems{graphics, parents, transforms, scenes, render, mut bounding, mut index} = self;
+        let graphics = graphics.next_frame();
+        let parents = parents.next_frame();
+        let transforms = transforms.next_frame();
+        let scenes = scenes.next_frame();
+        let render = render.next_frame();
+        bounding.next_frame();
+        let scenes = scenes.get().unwrap();
+        index.update(&scenes, &bounding.world);
+        Systems {
+            graphics: graphics.get().unwrap(),
+            parents: parents.get().unwrap(),
+            transforms: transforms.get().unwrap(),
+            scenes: scenes,
+            render: render.get().unwrap(),
+            bounding: bounding,
+            index: index
+        }
+    }
+}
+
+// A geometry whose box is from -1 to 1 on every axis
+fn unit_box(graphics: &mut Graphics) -> Geometry {
+    let vertex = [Attribute::f32(POSITION, 3)]
+        .build(vec![([-1f32, -1., -1.],), ([1., 1., 1.],), ([1., -1., 1.],)].into_iter())
+        .unwrap()
+        .owned_attributes();
+    let vb = VertexBuffer::new().bind(vertex).write(graphics);
+    Geometry::new().bind(vb.geometry(Primative::Triangle)).write(graphics)
+}
+
+fn at(x: f32, y: f32, z: f32) -> Local {
+    Local::Decomposed(Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(x, y, z)})
+}
+
+#[test]
+fn index_follows_scenes_and_bounds() {
+    let mut sched = Frontend::new();
+    let mut s = Systems::new(&mut sched);
+
+    let geo = unit_box(&mut s.graphics);
+    let world = Scene::new();
+    let a = Entity::new().bind(Parent::Root).write(&mut s.parents);
+    let b = Entity::new().bind(Parent::Root).write(&mut s.parents);
+    for &(eid, x) in [(a, 5.), (b, -5.)].iter() {
+        eid.bind(at(x, 0., 0.)).write(&mut s.transforms);
+        eid.bind(DrawBinding(geo, Material::new())).write(&mut s.render);
+    }
+    world.bind(a, &mut s.scenes);
+
+    let mut s = s.next_frame();
+    {
+        let bvh = s.index.scene(world).unwrap();
+        assert_eq!(bvh.len(), 1);
+        assert_eq!(bvh.bounds(a), Some(&Aabb3::new(Point3::new(4., -1., -1.), Point3::new(6., 1., 1.))));
+        assert!(!bvh.contains(b));
+    }
+
+    // b joins the scene and a moves
+    world.bind(b, &mut s.scenes);
+    a.bind(at(0., 2., 0.)).write(&mut s.transforms);
+    let mut s = s.next_frame();
+    {
+        let bvh = s.index.scene(world).unwrap();
+        assert_eq!(bvh.len(), 2);
+        assert_eq!(bvh.bounds(a), Some(&Aabb3::new(Point3::new(-1., 1., -1.), Point3::new(1., 3., 1.))));
+        assert!(bvh.contains(b));
+    }
+
+    // a leaves the scene
+    world.unbind(a, &mut s.scenes);
+    let mut s = s.next_frame();
+    {
+        let bvh = s.index.scene(world).unwrap();
+        assert!(!bvh.contains(a));
+        assert!(bvh.contains(b));
+    }
+
+    // b is still in the scene, but without a transform it has no bounds
+    s.transforms.delete(b);
+    let mut s = s.next_frame();
+    assert!(s.index.scene(world).is_none());
+
+    b.bind(at(-5., 0., 0.)).write(&mut s.transforms);
+    let s = s.next_frame();
+    assert!(s.index.scene(world).unwrap().contains(b));
+}
+
+#[test]
+fn visible_includes_unbounded() {
+    let mut sched = Frontend::new();
+    let mut s = Systems::new(&mut sched);
+
+    let geo = unit_box(&mut s.graphics);
+    let world = Scene::new();
+    let near = Entity::new().bind(Parent::Root).write(&mut s.parents);
+    let far = Entity::new().bind(Parent::Root).write(&mut s.parents);
+    let loose = Entity::new().bind(Parent::Root).write(&mut s.parents);
+    near.bind(at(0., 0., 0.)).write(&mut s.transforms);
+    far.bind(at(0., 0., 10.)).write(&mut s.transforms);
+    for &eid in [near, far, loose].iter() {
+        eid.bind(DrawBinding(geo, Material::new())).write(&mut s.render);
+        world.bind(eid, &mut s.scenes);
+    }
+
+    let s = s.next_frame();
+    // the frustum is the box from -1 to 1
+    let frustum = Frustum::from_matrix(&Matrix4::identity());
+
+    // `loose` has no transform so it has no world bounds, it can't be culled
+    assert_eq!(sorted(s.index.visible(world, &s.scenes, &s.bounding.world, Some(&frustum))),
+               sorted(vec![near, loose]));
+    assert_eq!(sorted(s.index.visible(world, &s.scenes, &s.bounding.world, None)),
+               sorted(vec![near, far, loose]));
+}
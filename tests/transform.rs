@@ -5,6 +5,7 @@ extern crate snowstorm;
 #[macro_use(router)]
 extern crate entity;
 extern crate cgmath;
+extern crate system;
 /*
 use std::collections::HashMap;
 use entity::*;
@@ -169,4 +170,296 @@ fn dirty_count() {
     }
     drop(front);
 }
-*/
\ No newline at end of file
+*/
+use entity::*;
+use parent::{parent, Parent, ParentSystem};
+use transform::*;
+use fibe::*;
+use cgmath::*;
+
+fn next_frame(parents: ParentSystem, transforms: TransformSystem) -> (ParentSystem, TransformSystem) {
+    let parents = parents.next_frame();
+    let transforms = transforms.next_frame();
+    (parents.get().unwrap(), transforms.get().unwrap())
+}
+
+fn at(x: f32, y: f32, z: f32) -> Local {
+    Local::Decomposed(Decomposed{scale: 1f32, rot: Quaternion::identity(), disp: Vector3::new(x, y, z)})
+}
+
+#[test]
+fn reparent_keeps_world() {
+    let mut sched = Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut transforms = transform(&mut sched, parents.clone());
+
+    let a = Entity::new().bind(Parent::Root).write(&mut parents);
+    let b = Entity::new().bind(Parent::Root).write(&mut parents);
+    let c = Entity::new().bind(Parent::Child(a)).write(&mut parents);
+    a.bind(at(1., 0., 0.)).write(&mut transforms);
+    b.bind(at(0., 2., 0.)).write(&mut transforms);
+    c.bind(at(1., 0., 0.)).write(&mut transforms);
+
+    let (mut p, mut t) = next_frame(parents, transforms);
+    assert_eq!(t.world(c).unwrap().0.w, Vector4::new(2., 0., 0., 1.));
+
+    reparent(&mut p, &mut t, c, Parent::Child(b)).unwrap();
+    let (mut p, mut t) = next_frame(p, t);
+    assert_eq!(p.read(&c).unwrap(), &Parent::Child(b));
+    assert_eq!(t.local(c).unwrap().to_mat().w, Vector4::new(2., -2., 0., 1.));
+    assert_eq!(t.world(c).unwrap().0.w, Vector4::new(2., 0., 0., 1.));
+    assert!(t.local(c).unwrap().decomposed().is_some());
+
+    reparent(&mut p, &mut t, c, Parent::Root).unwrap();
+    let (p, t) = next_frame(p, t);
+    assert_eq!(p.read(&c).unwrap(), &Parent::Root);
+    assert_eq!(t.local(c).unwrap().to_mat().w, Vector4::new(2., 0., 0., 1.));
+    assert_eq!(t.world(c).unwrap().0.w, Vector4::new(2., 0., 0., 1.));
+}
+
+#[test]
+fn reparent_frame_mismatch() {
+    let mut sched = Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut transforms = transform(&mut sched, parents.clone());
+
+    let a = Entity::new().bind(Parent::Root).write(&mut parents);
+    let b = Entity::new().bind(Parent::Root).write(&mut parents);
+    let c = Entity::new().bind(Parent::Child(a)).write(&mut parents);
+    a.bind(at(1., 0., 0.)).write(&mut transforms);
+    b.bind(at(0., 2., 0.)).write(&mut transforms);
+    c.bind(at(1., 0., 0.)).write(&mut transforms);
+    let (p, mut t) = next_frame(parents, transforms);
+
+    // the parents are a frame ahead of the transforms
+    let mut p = p.next_frame().get().unwrap();
+    assert_eq!(reparent(&mut p, &mut t, c, Parent::Child(b)),
+               Err(ReparentError::Transaction(system::TransactionError::FrameMismatch{
+                   system: "transform",
+                   expected: 2,
+                   found: 1
+               })));
+
+    // neither the parent nor the local was written
+    let (p, t) = next_frame(p, t);
+    assert_eq!(p.read(&c).unwrap(), &Parent::Child(a));
+    assert_eq!(t.local(c).unwrap().to_mat().w, Vector4::new(1., 0., 0., 1.));
+    assert_eq!(t.world(c).unwrap().0.w, Vector4::new(2., 0., 0., 1.));
+}
+
+#[test]
+fn reparent_rejects_parent() {
+    let mut sched = Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut transforms = transform(&mut sched, parents.clone());
+
+    let a = Entity::new().bind(Parent::Root).write(&mut parents);
+    let flat = Entity::new().bind(Parent::Root).write(&mut parents);
+    let bare = Entity::new().bind(Parent::Root).write(&mut parents);
+    let c = Entity::new().bind(Parent::Child(a)).write(&mut parents);
+    a.bind(at(1., 0., 0.)).write(&mut transforms);
+    flat.bind(Local::Decomposed(Decomposed{
+        scale: 0.,
+        rot: Quaternion::identity(),
+        disp: Vector3::new(0., 2., 0.)
+    })).write(&mut transforms);
+    c.bind(at(1., 0., 0.)).write(&mut transforms);
+    let (mut p, mut t) = next_frame(parents, transforms);
+
+    assert_eq!(reparent(&mut p, &mut t, c, Parent::Child(flat)),
+               Err(ReparentError::Singular(flat)));
+    assert_eq!(reparent(&mut p, &mut t, c, Parent::Child(bare)),
+               Err(ReparentError::NoTransform(bare)));
+
+    // nothing was written, the entity did not move
+    let (p, t) = next_frame(p, t);
+    assert_eq!(p.read(&c).unwrap(), &Parent::Child(a));
+    assert_eq!(t.local(c).unwrap().to_mat().w, Vector4::new(1., 0., 0., 1.));
+    assert_eq!(t.world(c).unwrap().0.w, Vector4::new(2., 0., 0., 1.));
+}
+
+#[test]
+fn deep_hierarchy() {
+    let mut sched = Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut transforms = transform(&mut sched, parents.clone());
+
+    let mut last = Entity::new().bind(Parent::Root).write(&mut parents);
+    last.bind(at(1., 0., 0.)).write(&mut transforms);
+    for _ in 1..10_000 {
+        last = Entity::new().bind(Parent::Child(last)).write(&mut parents);
+        last.bind(at(1., 0., 0.)).write(&mut transforms);
+    }
+
+    let (_, t) = next_frame(parents, transforms);
+    assert_eq!(t.world(last).unwrap().0.w, Vector4::new(10_000., 0., 0., 1.));
+}
+
+#[test]
+fn wide_hierarchy() {
+    let mut sched = Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut transforms = transform(&mut sched, parents.clone());
+
+    let root = Entity::new().bind(Parent::Root).write(&mut parents);
+    root.bind(at(0., 1., 0.)).write(&mut transforms);
+    let children: Vec<Entity> = (0..5_000).map(|i| {
+        let child = Entity::new().bind(Parent::Child(root)).write(&mut parents);
+        child.bind(at(i as f32, 0., 0.)).write(&mut transforms);
+        child
+    }).collect();
+
+    let (p, mut t) = next_frame(parents, transforms);
+    for (i, &c) in children.iter().enumerate() {
+        assert_eq!(t.world(c).unwrap().0.w, Vector4::new(i as f32, 1., 0., 1.));
+    }
+
+    // moving the root moves every child
+    root.bind(at(0., 2., 0.)).write(&mut t);
+    let (_, t) = next_frame(p, t);
+    for (i, &c) in children.iter().enumerate() {
+        assert_eq!(t.world(c).unwrap().0.w, Vector4::new(i as f32, 2., 0., 1.));
+    }
+}
+
+#[test]
+fn non_uniform_scale() {
+    let mut sched = Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut transforms = transform(&mut sched, parents.clone());
+
+    let root = Entity::new().bind(Parent::Root).write(&mut parents);
+    root.bind(Local::Scaled{
+        scale: Vector3::new(2., 3., 1.),
+        rot: Quaternion::identity(),
+        disp: Vector3::new(0., 0., 1.)
+    }).write(&mut transforms);
+    let child = Entity::new().bind(Parent::Child(root)).write(&mut parents);
+    child.bind(at(1., 1., 0.)).write(&mut transforms);
+
+    let (_, t) = next_frame(parents, transforms);
+    let world = t.world(child).unwrap().to_mat();
+    assert_eq!(world.w, Vector4::new(2., 3., 1., 1.));
+    assert_eq!(world.x, Vector4::new(2., 0., 0., 0.));
+    assert_eq!(world.y, Vector4::new(0., 3., 0., 0.));
+}
+
+#[test]
+fn position_far_from_origin() {
+    let mut sched = Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut transforms = transform(&mut sched, parents.clone());
+
+    let root = Entity::new().bind(Parent::Root).write(&mut parents);
+    root.bind(at(10_000_000., 0., 0.)).write(&mut transforms);
+    let child = Entity::new().bind(Parent::Child(root)).write(&mut parents);
+    child.bind(at(0.001, 0., 0.)).write(&mut transforms);
+
+    // the world rounds the child onto the root, the position does not
+    let (_, t) = next_frame(parents, transforms);
+    assert_eq!(t.world(child).unwrap().0.w.x, 10_000_000.);
+    assert!((t.position(child).unwrap().x - 10_000_000.001).abs() < 1e-6);
+}
+
+#[test]
+fn local_from_mat() {
+    let rot: Quaternion<f32> = Rotation3::from_angle_z(rad(1.));
+    let m = Local::Decomposed(Decomposed{scale: 2., rot: rot, disp: Vector3::new(1., 2., 3.)}).to_mat();
+    match Local::from_mat(m) {
+        Local::Decomposed(d) => {
+            assert!((d.scale - 2.).abs() < 1e-5);
+            assert_eq!(d.disp, Vector3::new(1., 2., 3.));
+            assert!((d.rot.s - rot.s).abs() < 1e-5 && (d.rot.v.z - rot.v.z).abs() < 1e-5);
+        }
+        x => panic!("expected a decomposed transform {:?}", x)
+    }
+
+    let m = Local::Scaled{scale: Vector3::new(1., 2., 3.), rot: rot, disp: Vector3::new(0., 0., 0.)}.to_mat();
+    match Local::from_mat(m) {
+        Local::Scaled{scale, ..} => {
+            assert!((scale.x - 1.).abs() < 1e-5 && (scale.y - 2.).abs() < 1e-5 && (scale.z - 3.).abs() < 1e-5);
+        }
+        x => panic!("expected a scaled transform {:?}", x)
+    }
+
+    // shear can only be kept as a matrix
+    let mut m = Matrix4::identity();
+    m.y.x = 1.;
+    match Local::from_mat(m) {
+        Local::Matrix(_) => (),
+        x => panic!("expected a matrix {:?}", x)
+    }
+    assert!(Local::Matrix(m).decomposed().is_none());
+}
+
+#[test]
+fn changed_and_velocity() {
+    let mut sched = Frontend::new();
+    let mut parents = parent(&mut sched);
+    let mut transforms = transform(&mut sched, parents.clone());
+
+    let a = Entity::new().bind(Parent::Root).write(&mut parents);
+    let b = Entity::new().bind(Parent::Root).write(&mut parents);
+    let c = Entity::new().bind(Parent::Child(a)).write(&mut parents);
+    a.bind(at(1., 0., 0.)).write(&mut transforms);
+    b.bind(at(0., 1., 0.)).write(&mut transforms);
+    c.bind(at(0., 0., 1.)).write(&mut transforms);
+
+    let (p, mut t) = next_frame(parents, transforms);
+    assert_eq!(t.changed.len(), 3);
+    assert_eq!(t.velocity(a), Some(Velocity::zero()));
+
+    let rot = Rotation3::from_angle_z(rad(0.5));
+    a.bind(Local::Decomposed(Decomposed{scale: 1., rot: rot, disp: Vector3::new(3., 0., 0.)}))
+     .write(&mut t);
+    let (_, t) = next_frame(p, t);
+
+    // c moves with its parent, b did not move
+    assert!(t.changed.contains(&a));
+    assert!(t.changed.contains(&c));
+    assert!(!t.changed.contains(&b));
+    assert_eq!(t.velocity(b), Some(Velocity::zero()));
+
+    let v = t.velocity(a).unwrap();
+    assert_eq!(v.linear, Vector3::new(2., 0., 0.));
+    assert!(v.angular.x.abs() < 1e-5);
+    assert!(v.angular.y.abs() < 1e-5);
+    assert!((v.angular.z - 0.5).abs() < 1e-5);
+}
+
+#[test]
+fn backpressure_block() {
+    use std::thread;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use system::Backpressure;
+
+    let mut sched = Frontend::new();
+    let parents = parent(&mut sched);
+    let mut transforms = transform(&mut sched, parents.clone());
+    transforms.set_backpressure(Backpressure::Block(2));
+    let watch = transforms.clone();
+
+    let sent = Arc::new(AtomicUsize::new(0));
+    let s = sent.clone();
+    let producer = thread::spawn(move || {
+        for i in 0..5 {
+            Entity::new().bind(at(i as f32, 0., 0.)).write(&mut transforms);
+            s.fetch_add(1, Ordering::SeqCst);
+        }
+        transforms.next_frame().get().unwrap()
+    });
+
+    // the transform system reads its messages once the parent frame
+    // is done, until then the producer is held back at the limit
+    while watch.queue_stats().blocked == 0 {
+        thread::yield_now();
+    }
+    assert_eq!(sent.load(Ordering::SeqCst), 2);
+    drop(watch);
+
+    let _parents = parents.next_frame().get().unwrap();
+    let transforms = producer.join().unwrap();
+    assert_eq!(sent.load(Ordering::SeqCst), 5);
+    assert_eq!(transforms.queue_stats().messages, 5);
+}